@@ -0,0 +1,26 @@
+-- Tables the bot has always relied on. `IF NOT EXISTS` lets deployments that
+-- created them by hand adopt the migrations without losing data.
+
+CREATE TABLE IF NOT EXISTS accounts (
+    user_id BIGINT PRIMARY KEY,
+    currency INTEGER NOT NULL DEFAULT 500,
+    premium_currency INTEGER NOT NULL DEFAULT 0,
+    waifus SMALLINT[] NOT NULL DEFAULT '{}',
+    packs SMALLINT NOT NULL DEFAULT 3,
+    premium_one_packs SMALLINT NOT NULL DEFAULT 0,
+    experience INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS alliances (
+    owner BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    members BIGINT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS premium_products (
+    product_id TEXT PRIMARY KEY,
+    currency INTEGER NOT NULL DEFAULT 0,
+    premium_currency INTEGER NOT NULL DEFAULT 0,
+    packs SMALLINT NOT NULL DEFAULT 0,
+    premium_one_packs SMALLINT NOT NULL DEFAULT 0
+);
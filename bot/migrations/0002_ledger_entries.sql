@@ -0,0 +1,27 @@
+-- Append-only audit log of every economic change, written by `apply_ledger`.
+-- Entries written in the same database transaction share a `transaction_id`.
+
+CREATE TABLE IF NOT EXISTS ledger_entries (
+    entry_id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL DEFAULT txid_current(),
+    user_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    delta INTEGER NOT NULL,
+    waifu_id SMALLINT,
+    reason TEXT NOT NULL,
+    command TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS ledger_entries_user_id_idx ON ledger_entries (user_id, created_at);
+
+CREATE OR REPLACE FUNCTION ledger_entries_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'ledger_entries is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS ledger_entries_append_only ON ledger_entries;
+CREATE TRIGGER ledger_entries_append_only
+    BEFORE UPDATE OR DELETE ON ledger_entries
+    FOR EACH ROW EXECUTE FUNCTION ledger_entries_append_only();
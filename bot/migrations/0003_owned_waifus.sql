@@ -0,0 +1,19 @@
+-- One row per owned waifu instead of the `accounts.waifus` array, so every copy
+-- has its own identity, acquisition date and source.
+
+CREATE TABLE owned_waifus (
+    instance_id BIGSERIAL PRIMARY KEY,
+    waifu_id SMALLINT NOT NULL,
+    owner_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    acquired_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    source TEXT NOT NULL
+);
+
+CREATE INDEX owned_waifus_owner_id_idx ON owned_waifus (owner_id);
+
+INSERT INTO owned_waifus (waifu_id, owner_id, source)
+    SELECT unnest(waifus), user_id, 'migration' FROM accounts;
+
+ALTER TABLE accounts DROP COLUMN waifus;
+
+ALTER TABLE ledger_entries ADD COLUMN instance_id BIGINT;
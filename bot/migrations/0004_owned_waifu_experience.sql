@@ -0,0 +1,3 @@
+-- Experience earned by each owned waifu from feeding
+
+ALTER TABLE owned_waifus ADD COLUMN experience INTEGER NOT NULL DEFAULT 0;
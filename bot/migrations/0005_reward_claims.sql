@@ -0,0 +1,9 @@
+-- Last claim and current streak of each recurring reward (`/daily`, `/weekly`)
+
+CREATE TABLE reward_claims (
+    user_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    last_claimed_at TIMESTAMPTZ NOT NULL,
+    streak INTEGER NOT NULL,
+    PRIMARY KEY (user_id, kind)
+);
@@ -0,0 +1,16 @@
+-- Players seen using the bot in each guild, for server leaderboards. Rows are
+-- joined against `accounts`, so deleted accounts simply stop showing up.
+
+CREATE TABLE guild_members (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+-- Leaderboards read the top rows of these orderings instead of sorting every account
+
+CREATE INDEX accounts_experience_idx ON accounts (experience DESC, user_id);
+
+CREATE INDEX accounts_currency_idx ON accounts (currency DESC, user_id);
+
+CREATE INDEX alliances_size_idx ON alliances ((cardinality(members)) DESC, owner);
@@ -0,0 +1,18 @@
+-- Waifus put up for sale on the market. A waifu with an unexpired listing is in
+-- escrow: it keeps its owner but ledger changes refuse to touch it.
+
+CREATE TABLE market_listings (
+    listing_id BIGSERIAL PRIMARY KEY,
+    instance_id BIGINT NOT NULL UNIQUE REFERENCES owned_waifus (instance_id) ON DELETE CASCADE,
+    seller_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    waifu_id SMALLINT NOT NULL,
+    price INTEGER NOT NULL CHECK (price > 0),
+    listed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX market_listings_expires_at_idx ON market_listings (expires_at);
+
+CREATE INDEX market_listings_waifu_id_idx ON market_listings (waifu_id);
+
+CREATE INDEX market_listings_seller_id_idx ON market_listings (seller_id);
@@ -0,0 +1,8 @@
+-- Packs opened in a row without a premium waifu, per account and pack type
+
+CREATE TABLE pity_counters (
+    user_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    pack TEXT NOT NULL,
+    pulls INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, pack)
+);
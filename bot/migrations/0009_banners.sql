@@ -0,0 +1,19 @@
+-- Time-boxed summon banners. Rows are managed by hand, the bot only reads them.
+-- A banner either has its own pack (name, price and size) or none at all.
+
+CREATE TABLE banners (
+    banner_id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    featured_waifus SMALLINT[] NOT NULL DEFAULT '{}',
+    rate_up INTEGER NOT NULL DEFAULT 50 CHECK (rate_up BETWEEN 0 AND 100),
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    pack_name TEXT,
+    pack_price INTEGER CHECK (pack_price >= 0),
+    pack_size INTEGER CHECK (pack_size > 0),
+    CHECK (starts_at < ends_at),
+    CHECK ((pack_name IS NULL) = (pack_price IS NULL) AND (pack_name IS NULL) = (pack_size IS NULL))
+);
+
+CREATE INDEX banners_ends_at_idx ON banners (ends_at);
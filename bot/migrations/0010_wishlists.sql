@@ -0,0 +1,10 @@
+-- Waifus each player is chasing. Wishlisted waifus are drawn slightly more often.
+
+CREATE TABLE wishlists (
+    user_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    waifu_id SMALLINT NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, waifu_id)
+);
+
+CREATE INDEX wishlists_waifu_id_idx ON wishlists (waifu_id);
@@ -0,0 +1,12 @@
+-- Likes and trash votes on catalog waifus, one per player and waifu.
+-- The totals are copied onto the Mongo waifu documents whenever a vote changes.
+
+CREATE TABLE waifu_votes (
+    user_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    waifu_id SMALLINT NOT NULL,
+    vote TEXT NOT NULL CHECK (vote IN ('like', 'trash')),
+    voted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, waifu_id)
+);
+
+CREATE INDEX waifu_votes_waifu_id_idx ON waifu_votes (waifu_id);
@@ -0,0 +1,12 @@
+-- Sell prices computed from votes, rarity and the copies in circulation.
+-- A row is added whenever a waifu's price changes, the newest row is the current price.
+
+CREATE TABLE waifu_prices (
+    waifu_id SMALLINT NOT NULL,
+    price INTEGER NOT NULL CHECK (price >= 0),
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (waifu_id, computed_at)
+);
+
+-- copies are counted per waifu on every recompute
+CREATE INDEX owned_waifus_waifu_id_idx ON owned_waifus (waifu_id);
@@ -0,0 +1,20 @@
+-- Collection sets and unlocked achievements.
+-- Sets are managed by hand like banners, achievements themselves are defined in code.
+
+CREATE TABLE collection_sets (
+    set_id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    waifu_ids SMALLINT[] NOT NULL CHECK (cardinality(waifu_ids) > 0),
+    reward_currency INTEGER NOT NULL DEFAULT 0 CHECK (reward_currency >= 0),
+    reward_packs SMALLINT NOT NULL DEFAULT 0 CHECK (reward_packs >= 0),
+    reward_premium_one_packs SMALLINT NOT NULL DEFAULT 0 CHECK (reward_premium_one_packs >= 0)
+);
+
+-- `achievement` is an achievement key, or `set-<set_id>` for a completed collection set
+CREATE TABLE achievements (
+    user_id BIGINT NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+    achievement TEXT NOT NULL,
+    unlocked_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, achievement)
+);
@@ -0,0 +1,29 @@
+-- Alliances get their own id, and every membership becomes a row with a role.
+-- This replaces the `owner` column and the `members` array.
+
+ALTER TABLE alliances ADD COLUMN alliance_id SERIAL;
+
+CREATE TABLE alliance_members (
+    user_id BIGINT PRIMARY KEY,
+    alliance_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'officer', 'member')),
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO alliance_members (user_id, alliance_id, role)
+    SELECT owner, alliance_id, 'owner' FROM alliances;
+INSERT INTO alliance_members (user_id, alliance_id, role)
+    SELECT m.user_id, a.alliance_id, 'member' FROM alliances a, unnest(a.members) AS m(user_id)
+    ON CONFLICT (user_id) DO NOTHING;
+
+DROP INDEX alliances_size_idx;
+ALTER TABLE alliances DROP CONSTRAINT alliances_pkey;
+ALTER TABLE alliances DROP COLUMN owner, DROP COLUMN members;
+ALTER TABLE alliances ADD PRIMARY KEY (alliance_id);
+
+ALTER TABLE alliance_members
+    ADD FOREIGN KEY (alliance_id) REFERENCES alliances (alliance_id) ON DELETE CASCADE;
+
+CREATE INDEX alliance_members_alliance_id_idx ON alliance_members (alliance_id);
+-- exactly one owner per alliance
+CREATE UNIQUE INDEX alliance_members_owner_idx ON alliance_members (alliance_id) WHERE role = 'owner';
@@ -0,0 +1,23 @@
+-- Alliances keep a shared currency balance. Every deposit, withdrawal and upgrade
+-- is recorded in `alliance_treasury_movements` with the member who made it.
+
+ALTER TABLE alliances ADD COLUMN treasury INTEGER NOT NULL DEFAULT 0 CHECK (treasury >= 0);
+
+CREATE TABLE alliance_treasury_movements (
+    movement_id BIGSERIAL PRIMARY KEY,
+    alliance_id INTEGER NOT NULL REFERENCES alliances (alliance_id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL,
+    delta INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX alliance_treasury_movements_alliance_id_idx
+    ON alliance_treasury_movements (alliance_id, created_at);
+
+CREATE TABLE alliance_upgrades (
+    alliance_id INTEGER NOT NULL REFERENCES alliances (alliance_id) ON DELETE CASCADE,
+    upgrade TEXT NOT NULL,
+    level SMALLINT NOT NULL,
+    PRIMARY KEY (alliance_id, upgrade)
+);
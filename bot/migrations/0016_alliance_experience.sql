@@ -0,0 +1,3 @@
+-- Alliances level up from their members' activity, see `alliance_level_for`.
+
+ALTER TABLE alliances ADD COLUMN experience INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,19 @@
+-- Battles between alliances. The seed and rosters are kept so a battle can be replayed,
+-- and the log is what the simulation reported round by round.
+
+CREATE TABLE alliance_battles (
+    battle_id BIGSERIAL PRIMARY KEY,
+    challenger_id INTEGER NOT NULL REFERENCES alliances (alliance_id) ON DELETE CASCADE,
+    defender_id INTEGER NOT NULL REFERENCES alliances (alliance_id) ON DELETE CASCADE,
+    winner_id INTEGER NOT NULL,
+    seed BIGINT NOT NULL,
+    wager INTEGER NOT NULL CHECK (wager >= 0),
+    rounds SMALLINT NOT NULL,
+    log TEXT[] NOT NULL,
+    challenger_roster BIGINT[] NOT NULL,
+    defender_roster BIGINT[] NOT NULL,
+    fought_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX alliance_battles_challenger_id_idx ON alliance_battles (challenger_id, fought_at);
+CREATE INDEX alliance_battles_defender_id_idx ON alliance_battles (defender_id, fought_at);
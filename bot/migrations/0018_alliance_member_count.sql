@@ -0,0 +1,9 @@
+-- Alliance sizes are kept on the alliance itself, so the Alliance Size leaderboard reads
+-- the top of an index instead of counting every membership. Replaces `alliances_size_idx`.
+
+ALTER TABLE alliances ADD COLUMN member_count INTEGER NOT NULL DEFAULT 0 CHECK (member_count >= 0);
+
+UPDATE alliances a SET member_count =
+    (SELECT COUNT(*) FROM alliance_members m WHERE m.alliance_id = a.alliance_id);
+
+CREATE INDEX alliances_member_count_idx ON alliances (member_count DESC, alliance_id);
@@ -0,0 +1,9 @@
+-- How many waifus each account owns, kept up to date by `apply_ledger` so the
+-- Waifu Count leaderboard reads the top of an index instead of grouping `owned_waifus`.
+
+ALTER TABLE accounts ADD COLUMN waifu_count INTEGER NOT NULL DEFAULT 0 CHECK (waifu_count >= 0);
+
+UPDATE accounts a SET waifu_count =
+    (SELECT COUNT(*) FROM owned_waifus w WHERE w.owner_id = a.user_id);
+
+CREATE INDEX accounts_waifu_count_idx ON accounts (waifu_count DESC, user_id);
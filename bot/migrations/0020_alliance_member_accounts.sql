@@ -0,0 +1,24 @@
+-- Memberships go away with their account. `delete_account` leaves the alliance first, so
+-- member counts and ownership stay right; the key makes sure no membership outlives its account.
+
+-- accounts deleted before this migration left their memberships behind
+DELETE FROM alliance_members m
+    WHERE NOT EXISTS (SELECT 1 FROM accounts a WHERE a.user_id = m.user_id);
+
+-- alliances whose owner was deleted go to their oldest officer, or their oldest member
+UPDATE alliance_members SET role = 'owner' WHERE user_id IN (
+    SELECT DISTINCT ON (m.alliance_id) m.user_id FROM alliance_members m
+        WHERE NOT EXISTS (SELECT 1 FROM alliance_members o
+            WHERE o.alliance_id = m.alliance_id AND o.role = 'owner')
+        ORDER BY m.alliance_id, m.role = 'officer' DESC, m.joined_at, m.user_id
+);
+
+-- and alliances nobody is left in are deleted
+DELETE FROM alliances a
+    WHERE NOT EXISTS (SELECT 1 FROM alliance_members m WHERE m.alliance_id = a.alliance_id);
+
+UPDATE alliances a SET member_count =
+    (SELECT COUNT(*) FROM alliance_members m WHERE m.alliance_id = a.alliance_id);
+
+ALTER TABLE alliance_members
+    ADD FOREIGN KEY (user_id) REFERENCES accounts (user_id) ON DELETE CASCADE;
@@ -11,13 +11,13 @@ pub async fn has_account(ctx: Context<'_>) -> Result<bool, Error> {
         // so we'll just return it
         Ok(has_account_value)
     } else {
-        let postgres_result = ctx.data().postgres.get_account(ctx.author().id).await;
+        let account_result = ctx.data().accounts.get_account(ctx.author().id).await;
         ctx.data()
             .check_cache
-            .insert_has_account(ctx.author().id, postgres_result.is_ok())
+            .insert_has_account(ctx.author().id, account_result.is_ok())
             .await;
 
-        if postgres_result.is_err() {
+        if account_result.is_err() {
             ctx.send(|cr| {
                 cr.ephemeral(true).embed(|ce| fmt::error("You must have an account to run this command. If you believe this is an error, contact our support team.", ce))
             })
@@ -25,6 +25,6 @@ pub async fn has_account(ctx: Context<'_>) -> Result<bool, Error> {
             .ok();
         }
 
-        Ok(postgres_result.is_ok())
+        Ok(account_result.is_ok())
     }
 }
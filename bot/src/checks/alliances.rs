@@ -11,13 +11,13 @@ pub async fn in_alliance(ctx: Context<'_>) -> Result<bool, Error> {
         // so we'll just return it
         Ok(in_alliance_value)
     } else {
-        let postgres_result = ctx.data().postgres.get_alliance(ctx.author().id).await;
+        let alliance_result = ctx.data().alliances.get_alliance(ctx.author().id).await;
         ctx.data()
             .check_cache
-            .insert_in_alliance(ctx.author().id, postgres_result.is_ok())
+            .insert_in_alliance(ctx.author().id, alliance_result.is_ok())
             .await;
 
-        if postgres_result.is_err() {
+        if alliance_result.is_err() {
             ctx.send(|cr| {
                 cr.ephemeral(true).embed(|ce| fmt::error("You must have inside an alliance to run this command. If you believe this is an error, contact our support team.", ce))
             })
@@ -25,6 +25,6 @@ pub async fn in_alliance(ctx: Context<'_>) -> Result<bool, Error> {
             .ok();
         }
 
-        Ok(postgres_result.is_ok())
+        Ok(alliance_result.is_ok())
     }
 }
@@ -1,23 +1,38 @@
 mod accounts;
 mod alliances;
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use poise::serenity_prelude as serenity;
 use tokio::sync::Mutex as TokioMutex;
 
+use crate::models::waifu::OwnedWaifu;
+
 pub use accounts::has_account;
 pub use alliances::in_alliance;
 
+/// How long a cached inventory is trusted. Commands drop the cache of whoever they changed,
+/// this only bounds how stale it gets from changes no command makes, like expiring listings.
+const OWNED_WAIFUS_TTL: Duration = Duration::from_secs(60);
+
 pub struct CheckCache {
     has_account_cache: TokioMutex<HashMap<u64, bool>>,
     in_alliance_cache: TokioMutex<HashMap<u64, bool>>,
+    /// (guild id, user id) pairs already recorded for server leaderboards
+    guild_member_cache: TokioMutex<HashSet<(u64, u64)>>,
+    /// Inventories for autocomplete, which would otherwise query them on every keystroke
+    owned_waifus_cache: TokioMutex<HashMap<u64, (Instant, Vec<OwnedWaifu>)>>,
 }
 impl CheckCache {
     pub fn new() -> Self {
         Self {
             has_account_cache: TokioMutex::new(HashMap::new()),
             in_alliance_cache: TokioMutex::new(HashMap::new()),
+            guild_member_cache: TokioMutex::new(HashSet::new()),
+            owned_waifus_cache: TokioMutex::new(HashMap::new()),
         }
     }
     pub async fn insert_has_account(&self, user_id: serenity::UserId, value: bool) {
@@ -45,4 +60,37 @@ impl CheckCache {
             None => None,
         }
     }
+
+    pub async fn insert_guild_member(
+        &self,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) {
+        let mut guard = self.guild_member_cache.lock().await;
+        guard.insert((guild_id.0, user_id.0));
+    }
+    pub async fn has_guild_member(
+        &self,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) -> bool {
+        let guard = self.guild_member_cache.lock().await;
+        guard.contains(&(guild_id.0, user_id.0))
+    }
+
+    pub async fn insert_owned_waifus(&self, user_id: serenity::UserId, waifus: Vec<OwnedWaifu>) {
+        let mut guard = self.owned_waifus_cache.lock().await;
+        guard.insert(user_id.0, (Instant::now(), waifus));
+    }
+    pub async fn get_owned_waifus(&self, user_id: serenity::UserId) -> Option<Vec<OwnedWaifu>> {
+        let guard = self.owned_waifus_cache.lock().await;
+        let (cached_at, waifus) = guard.get(&user_id.0)?;
+        (cached_at.elapsed() < OWNED_WAIFUS_TTL).then(|| waifus.clone())
+    }
+    pub async fn invalidate_owned_waifus(&self, user_ids: &[serenity::UserId]) {
+        let mut guard = self.owned_waifus_cache.lock().await;
+        for user_id in user_ids {
+            guard.remove(&user_id.0);
+        }
+    }
 }
@@ -2,6 +2,7 @@ use poise::serenity_prelude as serenity;
 
 use crate::{
     components::{confirm::ConfirmMenu, paginator::EmbedPaginator},
+    models::waifu::{level_for, InventoryWaifu},
     utils::fmt,
     Context, Error,
 };
@@ -20,7 +21,7 @@ pub async fn account(_: Context<'_>) -> Result<(), Error> {
 pub async fn create(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
-    let register_result = ctx.data().postgres.register_account(ctx.author().id).await;
+    let register_result = ctx.data().accounts.register_account(ctx.author().id).await;
     if register_result.is_ok() {
         ctx.data()
             .check_cache
@@ -46,7 +47,7 @@ pub async fn delete(ctx: Context<'_>) -> Result<(), Error> {
     ).await?;
 
     if confirmed {
-        ctx.data().postgres.delete_account(ctx.author().id).await?;
+        ctx.data().accounts.delete_account(ctx.author().id).await?;
         ctx.data()
             .check_cache
             .insert_has_account(ctx.author().id, false)
@@ -77,8 +78,9 @@ pub async fn delete(ctx: Context<'_>) -> Result<(), Error> {
 pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
-    let account = ctx.data().postgres.get_account(ctx.author().id).await?;
-    let level = account.experience / 250;
+    let account = ctx.data().accounts.get_account(ctx.author().id).await?;
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    let level = level_for(account.experience);
     ctx.send(|cr| {
         cr.embed(|ce| {
             ce.author(|ca| {
@@ -92,7 +94,7 @@ pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
             .field("Packs", account.packs, true)
             .field("Gold Packs", account.premium_one_packs, true)
             .field("Currency", account.currency, true)
-            .field("Waifu Count", account.waifus.len(), true)
+            .field("Waifu Count", owned_waifus.len(), true)
             .field("Level", level, true)
             .field("Experience", account.experience, true)
             .thumbnail(
@@ -118,9 +120,10 @@ pub async fn waifus(
         true => ctx.defer_ephemeral().await?,
         false => ctx.defer().await?,
     };
-    let waifu_ids = ctx.data().postgres.get_waifus(ctx.author().id).await?;
-    let transformed: Vec<i32> = waifu_ids.iter().map(|id| id.clone().into()).collect();
-    let waifus = ctx.data().mongo.get_waifus(transformed).await?;
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    let transformed: Vec<i32> = owned_waifus.iter().map(|w| w.waifu_id.into()).collect();
+    let waifus = ctx.data().catalog.get_waifus(transformed).await?;
+    let waifus = InventoryWaifu::join(owned_waifus, &waifus);
     if waifus.len() <= 0 {
         ctx.send(|cr| cr.embed(|ce| fmt::error("You don't have any waifus.", ce)))
             .await?;
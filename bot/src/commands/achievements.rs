@@ -0,0 +1,215 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    models::{
+        achievement::{Achievement, Progress},
+        ledger::{LedgerAction, LedgerReason},
+        reward::Reward,
+        waifu::level_for,
+    },
+    utils::fmt,
+    Context, Error,
+};
+
+/// See your achievements and collection sets, or someone else's
+#[poise::command(slash_command, check = "crate::checks::has_account")]
+pub async fn achievements(
+    ctx: Context<'_>,
+    #[description = "Whose achievements to view"] member: Option<serenity::User>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let user = member.as_ref().unwrap_or(ctx.author());
+    let unlocked = ctx.data().achievements.get_achievements(user.id).await?;
+    let is_unlocked = |key: &str| unlocked.iter().any(|a| a.achievement == key);
+
+    let achievement_lines: Vec<String> = Achievement::ALL
+        .iter()
+        .map(|a| {
+            let icon = if is_unlocked(a.key()) { "🏆" } else { "🔒" };
+            format!(
+                "{} **{}** - {}\n{}",
+                icon,
+                a.name(),
+                a.description(),
+                a.reward().describe()
+            )
+        })
+        .collect();
+
+    let owned_waifus = ctx.data().accounts.get_waifus(user.id).await?;
+    let owned: Vec<i16> = owned_waifus.iter().map(|w| w.waifu_id).collect();
+    let sets = ctx.data().collection_sets.read().await.clone();
+    let set_lines: Vec<String> = sets
+        .iter()
+        .map(|set| {
+            let icon = if is_unlocked(&set.key()) {
+                "🏆"
+            } else {
+                "🔒"
+            };
+            format!(
+                "{} **{}** - {}/{} waifus\n{}",
+                icon,
+                set.name,
+                set.progress(&owned),
+                set.waifu_ids.len(),
+                set.reward().describe()
+            )
+        })
+        .collect();
+    let completed = Achievement::ALL
+        .iter()
+        .filter(|a| is_unlocked(a.key()))
+        .count()
+        + sets.iter().filter(|s| is_unlocked(&s.key())).count();
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(format!("{}'s Achievements", user.name))
+                .description(achievement_lines.join("\n"))
+                .footer(|cf| {
+                    cf.text(format!(
+                        "{}/{} unlocked",
+                        completed,
+                        Achievement::ALL.len() + sets.len()
+                    ))
+                })
+                .colour(serenity::Colour::GOLD);
+            if !set_lines.is_empty() {
+                ce.field("Collection Sets", set_lines.join("\n"), false);
+            }
+            ce
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Unlocks the achievement, paying out its reward. Returns `false` if it was already unlocked.
+async fn unlock(
+    ctx: Context<'_>,
+    user_id: serenity::UserId,
+    key: &str,
+    reward: &Reward,
+) -> Result<bool, Error> {
+    let action = LedgerAction::new(LedgerReason::Achievement, &ctx.command().qualified_name)
+        .currencies(user_id, reward.currency, 0)
+        .packs(user_id, reward.packs, reward.premium_one_packs);
+
+    ctx.data()
+        .achievements
+        .unlock_achievement(user_id, key, &action)
+        .await
+}
+
+async fn announce(ctx: Context<'_>, unlocked: &[(String, Reward)]) -> Result<(), Error> {
+    let lines: Vec<String> = unlocked
+        .iter()
+        .map(|(name, reward)| format!("🏆 **{}** - {}", name, reward.describe()))
+        .collect();
+    let message = format!(
+        "You unlocked:\n{}\n\nSee them all with `/achievements`",
+        lines.join("\n")
+    );
+    ctx.send(|cr| {
+        cr.embed(|ce| fmt::success(&message, ce).title("Achievement unlocked!"))
+            .ephemeral(true)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Unlocks an achievement for something that just happened, telling the author if it was theirs
+pub async fn award(
+    ctx: Context<'_>,
+    user_id: serenity::UserId,
+    achievement: Achievement,
+) -> Result<(), Error> {
+    let reward = achievement.reward();
+    let unlocked = unlock(ctx, user_id, achievement.key(), &reward).await?;
+    if unlocked && user_id == ctx.author().id {
+        announce(ctx, &[(achievement.name().into(), reward)]).await?;
+    }
+
+    Ok(())
+}
+
+/// Commands that can change what achievements are checked against: the author's inventory, level or alliance
+const PROGRESS_COMMANDS: [&str; 7] = [
+    "account", "alliance", "interact", "market", "shop", "summon", "trade",
+];
+
+/// Unlocks every achievement and collection set the author's inventory and stats now earn.
+/// Runs after every command in [`PROGRESS_COMMANDS`], players without an account are skipped.
+pub async fn track_achievements(ctx: Context<'_>) {
+    let root = ctx
+        .command()
+        .qualified_name
+        .split(' ')
+        .next()
+        .unwrap_or_default();
+    if !PROGRESS_COMMANDS.contains(&root) {
+        return;
+    }
+    if ctx
+        .data()
+        .check_cache
+        .get_has_account(ctx.author().id)
+        .await
+        != Some(true)
+    {
+        return;
+    }
+    if let Err(e) = check_achievements(ctx).await {
+        println!("Failed to track achievements: {e}");
+    }
+}
+
+async fn check_achievements(ctx: Context<'_>) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let unlocked = ctx.data().achievements.get_achievements(user_id).await?;
+    let is_unlocked = |key: &str| unlocked.iter().any(|a| a.achievement == key);
+
+    let account = ctx.data().accounts.get_account(user_id).await?;
+    let owned_waifus = ctx.data().accounts.get_waifus(user_id).await?;
+    let owned: Vec<i16> = owned_waifus.iter().map(|w| w.waifu_id).collect();
+    let progress = Progress {
+        waifu_count: owned_waifus.len(),
+        level: level_for(account.experience),
+        in_alliance: ctx.data().alliances.get_alliance(user_id).await.is_ok(),
+    };
+
+    let mut newly_unlocked = vec![];
+    for achievement in Achievement::ALL {
+        if is_unlocked(achievement.key()) || !achievement.is_met(&progress) {
+            continue;
+        }
+        let reward = achievement.reward();
+        if unlock(ctx, user_id, achievement.key(), &reward).await? {
+            newly_unlocked.push((achievement.name().to_string(), reward));
+        }
+    }
+    let sets = ctx.data().collection_sets.read().await.clone();
+    for set in sets {
+        if is_unlocked(&set.key()) || !set.is_complete(&owned) {
+            continue;
+        }
+        let reward = set.reward();
+        if unlock(ctx, user_id, &set.key(), &reward).await? {
+            newly_unlocked.push((format!("{} (collection set)", set.name), reward));
+        }
+    }
+
+    if !newly_unlocked.is_empty() {
+        announce(ctx, &newly_unlocked).await?;
+    }
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [achievements()]
+}
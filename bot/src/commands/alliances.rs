@@ -1,21 +1,48 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use petgraph::{
     dot::{Config as DotConfig, Dot},
     Graph,
 };
 use poise::serenity_prelude as serenity;
+use rand::{thread_rng, Rng};
 use tokio::io::AsyncWriteExt;
 
 use crate::{
     components::confirm::ConfirmMenu,
+    models::{
+        alliance::{
+            member_cap, pack_discount, Alliance, AllianceMember, AlliancePermission, AllianceRole,
+            AllianceUpgrade, ALLIANCE_LEVEL_EXPERIENCE,
+        },
+        battle::{pick_roster, simulate, Fighter, ROSTER_SIZE},
+        ledger::{LedgerAction, LedgerError, LedgerReason},
+        waifu::InventoryWaifu,
+    },
     utils::{fmt, random_component_id},
     Context, Error,
 };
 
 #[poise::command(
     slash_command,
-    subcommands("visualize", "create", "invite", "delete"),
+    subcommands(
+        "visualize",
+        "info",
+        "create",
+        "invite",
+        "leave",
+        "kick",
+        "promote",
+        "demote",
+        "rename",
+        "treasury",
+        "deposit",
+        "withdraw",
+        "upgrade",
+        "challenge",
+        "transfer",
+        "delete"
+    ),
     check = "crate::checks::has_account"
 )]
 pub async fn alliance(_: Context<'_>) -> Result<(), Error> {
@@ -35,7 +62,7 @@ pub async fn create(ctx: Context<'_>, name: String) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
     ctx.data()
-        .postgres
+        .alliances
         .create_alliance(ctx.author().id, &name)
         .await?;
     ctx.data()
@@ -61,16 +88,25 @@ pub async fn create(ctx: Context<'_>, name: String) -> Result<(), Error> {
 pub async fn delete(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
-    let alliance = ctx.data().postgres.get_alliance(ctx.author().id).await?;
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
     if alliance.owner != ctx.author().id.0 as i64 {
         ctx.send(|cr| cr.embed(|ce| fmt::error("You must own an alliance to delete it!", ce)))
             .await?;
     } else {
-        ctx.data().postgres.delete_alliance(ctx.author().id).await?;
+        ctx.data()
+            .alliances
+            .delete_alliance(ctx.author().id)
+            .await?;
         ctx.data()
             .check_cache
             .insert_in_alliance(ctx.author().id, false)
             .await;
+        for member in alliance.members.iter() {
+            ctx.data()
+                .check_cache
+                .insert_in_alliance(serenity::UserId(*member as u64), false)
+                .await;
+        }
         ctx.send(|cr| cr.embed(|ce| fmt::success("Alliance deleted.", ce)))
             .await?;
     }
@@ -83,48 +119,820 @@ pub async fn delete(ctx: Context<'_>) -> Result<(), Error> {
 pub async fn invite(ctx: Context<'_>, member: serenity::Member) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let member_account = ctx.data().postgres.get_account(member.user.id).await;
+    let member_account = ctx.data().accounts.get_account(member.user.id).await;
     if member_account.is_err() {
         ctx.send(|cr| cr.embed(|ce| fmt::error("This user does not have an account. Tell them to make one to invite them to your alliance", ce)).ephemeral(true)).await?;
         return Ok(());
     } else {
-        let alliance_result = ctx.data().postgres.get_alliance(member.user.id).await;
+        let alliance_result = ctx.data().alliances.get_alliance(member.user.id).await;
         if alliance_result.is_ok() {
             ctx.send(|cr| cr.embed(|ce| fmt::error("This user is already in an alliance. Ask them to leave it if you want them to join yours", ce)).ephemeral(true)).await?;
             return Ok(());
         }
     }
 
-    let alliance = ctx.data().postgres.get_alliance(ctx.author().id).await?;
-    if alliance.owner != ctx.author().id.0 as i64 {
+    let Some(membership) = require_permission(ctx, AlliancePermission::Invite).await? else {
+        return Ok(());
+    };
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    if alliance.is_full() {
+        let message = format!(
+            "**`{}`** is full at {} members. Level up your alliance to make room for more.",
+            alliance.name,
+            alliance.size()
+        );
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)).ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    let message = format!(
+        "**`{}`**, would you like to join **`{}`**?",
+        member.display_name(),
+        alliance.name
+    );
+    let confirmed = ConfirmMenu::start(ctx, member.user.id, &message).await?;
+    if confirmed {
+        // the check above is only a courtesy, the cap is enforced when joining
+        let cap = member_cap(alliance.level()) as i32;
+        let joined = ctx
+            .data()
+            .alliances
+            .join_alliance(membership.alliance_id, member.user.id, cap)
+            .await?;
+        if !joined {
+            let message = format!(
+                "**`{}`** is full. Level up your alliance to make room for more.",
+                alliance.name
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)).ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        ctx.data()
+            .check_cache
+            .insert_in_alliance(member.user.id, true)
+            .await;
         ctx.send(|cr| {
-            cr.embed(|ce| fmt::error("You must own the alliance to invite people", ce))
-                .ephemeral(true)
+            cr.embed(|ce| {
+                fmt::success(
+                    "Joined alliance. Check out your new alliance with `/alliance visualize`",
+                    ce,
+                )
+            })
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Leave the alliance you're in
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    if alliance.owner == ctx.author().id.0 as i64 {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You own this alliance. Hand it over with `/alliance transfer` or delete it with `/alliance delete`", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let message = format!("Leave **`{}`**?", alliance.name);
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You stayed in the alliance.", ce)))
+            .await?;
+        return Ok(());
+    }
+    if !ctx.data().alliances.leave_alliance(ctx.author().id).await? {
+        // kicked, or handed the alliance, while the confirmation was open
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::error(
+                    "You couldn't leave: you're no longer a member, or you own the alliance now.",
+                    ce,
+                )
+            })
         })
         .await?;
+        return Ok(());
+    }
+    ctx.data()
+        .check_cache
+        .insert_in_alliance(ctx.author().id, false)
+        .await;
+    ctx.send(|cr| cr.embed(|ce| fmt::success("You left the alliance.", ce)))
+        .await?;
+
+    Ok(())
+}
+
+/// Remove someone of a lower role from your alliance
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn kick(
+    ctx: Context<'_>,
+    #[description = "The member to remove"] member: serenity::User,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(membership) = require_permission(ctx, AlliancePermission::Kick).await? else {
+        return Ok(());
+    };
+    let Some(target) = get_fellow_member(ctx, &membership, member.id).await? else {
+        return Ok(());
+    };
+    if target.role() >= membership.role() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You can only kick people below your role.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let kicked = ctx
+        .data()
+        .alliances
+        .kick_member(membership.alliance_id, member.id)
+        .await?;
+    if kicked {
+        ctx.data()
+            .check_cache
+            .insert_in_alliance(member.id, false)
+            .await;
+        let message = format!("Removed **`{}`** from the alliance.", member.name);
+        ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+            .await?;
     } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This user isn't in your alliance.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Make a member of your alliance an officer
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn promote(
+    ctx: Context<'_>,
+    #[description = "The member to promote"] member: serenity::User,
+) -> Result<(), Error> {
+    change_role(ctx, member, AllianceRole::Member, AllianceRole::Officer).await
+}
+
+/// Make an officer of your alliance a regular member again
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn demote(
+    ctx: Context<'_>,
+    #[description = "The officer to demote"] member: serenity::User,
+) -> Result<(), Error> {
+    change_role(ctx, member, AllianceRole::Officer, AllianceRole::Member).await
+}
+
+async fn change_role(
+    ctx: Context<'_>,
+    member: serenity::User,
+    from: AllianceRole,
+    to: AllianceRole,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(membership) = require_permission(ctx, AlliancePermission::ManageRoles).await? else {
+        return Ok(());
+    };
+    let Some(target) = get_fellow_member(ctx, &membership, member.id).await? else {
+        return Ok(());
+    };
+    if target.role() != from {
         let message = format!(
-            "**`{}`**, would you like to join **`{}`**'s alliance?",
-            member.display_name(),
-            ctx.author().name
+            "**`{}`** doesn't have the {} role.",
+            member.name,
+            from.name()
         );
-        let confirmed = ConfirmMenu::start(ctx, member.user.id, &message).await?;
-        if confirmed {
-            ctx.data()
-                .postgres
-                .join_alliance(ctx.author().id, member.user.id)
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let changed = ctx
+        .data()
+        .alliances
+        .set_member_role(membership.alliance_id, member.id, to)
+        .await?;
+    if changed {
+        let message = format!("**`{}`** now has the {} role.", member.name, to.name());
+        ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+            .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This user isn't in your alliance.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Give your alliance a new name
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn rename(ctx: Context<'_>, name: String) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    if name.len() > 15 {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("Alliance name must be below 15 characters long.", ce))
+        })
+        .await?;
+        return Ok(());
+    }
+    let Some(membership) = require_permission(ctx, AlliancePermission::Rename).await? else {
+        return Ok(());
+    };
+
+    ctx.data()
+        .alliances
+        .rename_alliance(membership.alliance_id, &name)
+        .await?;
+    let message = format!("Your alliance is now called **`{}`**.", name);
+    ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+        .await?;
+
+    Ok(())
+}
+
+/// Hand your alliance over to one of its members
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn transfer(
+    ctx: Context<'_>,
+    #[description = "The member who becomes the new owner"] member: serenity::User,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    if alliance.owner != ctx.author().id.0 as i64 {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You must own the alliance to transfer it", ce)))
+            .await?;
+        return Ok(());
+    }
+    if !alliance.members.contains(&(member.id.0 as i64)) {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This user isn't in your alliance.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let message = format!(
+        "Make **`{}`** the owner of **`{}`**? You'll stay on as an officer.",
+        member.name, alliance.name
+    );
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Transfer cancelled.", ce)))
+            .await?;
+        return Ok(());
+    }
+    // both stay in the alliance, so the in-alliance cache doesn't change
+    let transferred = ctx
+        .data()
+        .alliances
+        .transfer_alliance(ctx.author().id, member.id)
+        .await?;
+    if transferred {
+        let message = format!("**`{}`** now owns the alliance.", member.name);
+        ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+            .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This user is no longer in your alliance.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// The author's membership if their role grants the permission, otherwise tells them why not
+async fn require_permission(
+    ctx: Context<'_>,
+    permission: AlliancePermission,
+) -> Result<Option<AllianceMember>, Error> {
+    let membership = ctx.data().alliances.get_membership(ctx.author().id).await?;
+    match membership {
+        Some(membership) if membership.role().can(permission) => Ok(Some(membership)),
+        Some(membership) => {
+            let message = format!(
+                "{}s can't {}.",
+                membership.role().name(),
+                permission.describe()
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)).ephemeral(true))
                 .await?;
+            Ok(None)
+        }
+        None => {
             ctx.send(|cr| {
-                cr.embed(|ce| {
-                    fmt::success(
-                        "Joined alliance. Check out your new alliance with `/alliance visualize`",
-                        ce,
-                    )
-                })
+                cr.embed(|ce| fmt::error("You aren't in an alliance.", ce))
+                    .ephemeral(true)
             })
             .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The user's membership if they're in the same alliance as `membership`, otherwise tells the author
+async fn get_fellow_member(
+    ctx: Context<'_>,
+    membership: &AllianceMember,
+    user_id: serenity::UserId,
+) -> Result<Option<AllianceMember>, Error> {
+    let target = ctx.data().alliances.get_membership(user_id).await?;
+    match target {
+        Some(target) if target.alliance_id == membership.alliance_id => Ok(Some(target)),
+        _ => {
+            ctx.send(|cr| cr.embed(|ce| fmt::error("This user isn't in your alliance.", ce)))
+                .await?;
+            Ok(None)
         }
     }
+}
+
+/// How many treasury movements `/alliance treasury` shows
+const MOVEMENT_LIMIT: i64 = 10;
+
+/// See your alliance's treasury, its upgrades and who moved currency in or out
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn treasury(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    let upgrades = ctx
+        .data()
+        .treasury
+        .get_alliance_upgrades(alliance.alliance_id)
+        .await?;
+    let movements = ctx
+        .data()
+        .treasury
+        .get_treasury_movements(alliance.alliance_id, MOVEMENT_LIMIT)
+        .await?;
+
+    let upgrade_lines: Vec<String> = AllianceUpgrade::ALL
+        .iter()
+        .map(|u| {
+            let level = upgrades.get(u).copied().unwrap_or(0);
+            let next = if level < AllianceUpgrade::MAX_LEVEL {
+                format!("Next level: **{}** :coin:", u.cost(level + 1))
+            } else {
+                String::from("Fully upgraded")
+            };
+            format!(
+                "**{}** - level {}/{}\n{}\n{}",
+                u.name(),
+                level,
+                AllianceUpgrade::MAX_LEVEL,
+                u.describe(level),
+                next
+            )
+        })
+        .collect();
+    let movement_lines: Vec<String> = movements
+        .iter()
+        .map(|m| {
+            format!(
+                "<@{}> {} **{}** :coin: <t:{}:R>",
+                m.user_id,
+                m.describe(),
+                m.delta.abs(),
+                m.created_at.timestamp()
+            )
+        })
+        .collect();
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(format!("{}'s Treasury", alliance.name))
+                .description(format!(":coin: **{}**", alliance.treasury))
+                .field("Upgrades", upgrade_lines.join("\n"), false)
+                .colour(serenity::Colour::GOLD);
+            if !movement_lines.is_empty() {
+                ce.field("Recent Movements", movement_lines.join("\n"), false);
+            }
+            ce
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Put some of your currency into your alliance's treasury
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn deposit(
+    ctx: Context<'_>,
+    #[description = "How much currency to deposit"]
+    #[min = 1]
+    amount: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    let action = LedgerAction::new(LedgerReason::AllianceDeposit, &ctx.command().qualified_name)
+        .currencies(ctx.author().id, -amount, 0)
+        .treasury(ctx.author().id, alliance.alliance_id, amount);
+    match ctx.data().accounts.apply_ledger(&action).await {
+        Ok(()) => {
+            let message = format!(
+                "Deposited **{}** :coin: into **`{}`**'s treasury.",
+                amount, alliance.name
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("You don't have enough currency to deposit this.", ce))
+            })
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+/// Take currency out of your alliance's treasury
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn withdraw(
+    ctx: Context<'_>,
+    #[description = "How much currency to withdraw"]
+    #[min = 1]
+    amount: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(membership) = require_permission(ctx, AlliancePermission::Spend).await? else {
+        return Ok(());
+    };
+    let action = LedgerAction::new(
+        LedgerReason::AllianceWithdrawal,
+        &ctx.command().qualified_name,
+    )
+    .treasury(ctx.author().id, membership.alliance_id, -amount)
+    .currencies(ctx.author().id, amount, 0);
+    match ctx.data().accounts.apply_ledger(&action).await {
+        Ok(()) => {
+            let message = format!("Withdrew **{}** :coin: from the treasury.", amount);
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("The treasury doesn't have that much currency.", ce))
+            })
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+/// Spend your alliance's treasury on an upgrade for every member
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn upgrade(
+    ctx: Context<'_>,
+    #[description = "What to upgrade"] upgrade: AllianceUpgrade,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(membership) = require_permission(ctx, AlliancePermission::Spend).await? else {
+        return Ok(());
+    };
+    let upgrades = ctx
+        .data()
+        .treasury
+        .get_alliance_upgrades(membership.alliance_id)
+        .await?;
+    let level = upgrades.get(&upgrade).copied().unwrap_or(0);
+    if level >= AllianceUpgrade::MAX_LEVEL {
+        let message = format!("**{}** is already fully upgraded.", upgrade.name());
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let cost = upgrade.cost(level + 1);
+    let message = format!(
+        "Spend **{}** :coin: from the treasury to raise **{}** to level {}? {}.",
+        cost,
+        upgrade.name(),
+        level + 1,
+        upgrade.describe(level + 1)
+    );
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Upgrade cancelled.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let action = LedgerAction::new(LedgerReason::AllianceUpgrade, &ctx.command().qualified_name)
+        .treasury(ctx.author().id, membership.alliance_id, -cost);
+    let bought = ctx
+        .data()
+        .treasury
+        .buy_alliance_upgrade(membership.alliance_id, upgrade, level, &action)
+        .await;
+    match bought {
+        Ok(true) => {
+            let message = format!("**{}** is now level {}.", upgrade.name(), level + 1);
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Ok(false) => {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("Someone else just upgraded this. Try again.", ce))
+            })
+            .await?;
+        }
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| cr.embed(|ce| fmt::error("The treasury can't afford this upgrade.", ce)))
+                .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+/// How many lines of the battle log the result shows
+const BATTLE_LOG_LINES: usize = 10;
+
+/// Challenge another alliance to a battle, its owner has to accept
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn challenge(
+    ctx: Context<'_>,
+    #[description = "The owner of the alliance to challenge"] opponent: serenity::User,
+    #[description = "Currency each treasury puts up, the winner takes it"]
+    #[min = 0]
+    wager: Option<i32>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let wager = wager.unwrap_or(0);
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    if alliance.owner != ctx.author().id.0 as i64 {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("You must own the alliance to challenge others", ce))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let opposing = ctx.data().alliances.get_membership(opponent.id).await?;
+    let Some(opposing) = opposing.filter(|m| m.role() == AllianceRole::Owner) else {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("This user doesn't own an alliance.", ce))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    };
+    if opposing.alliance_id == alliance.alliance_id {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("You can't challenge your own alliance.", ce))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let opposing = ctx.data().alliances.get_alliance(opponent.id).await?;
+    if alliance.treasury < wager || opposing.treasury < wager {
+        let message = format!(
+            "Both treasuries need at least **{}** :coin: to cover the wager.",
+            wager
+        );
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)).ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let challengers = field_roster(ctx, &alliance).await?;
+    let defenders = field_roster(ctx, &opposing).await?;
+    if challengers.is_empty() || defenders.is_empty() {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("Both alliances need at least one waifu to fight.", ce))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    // each owner sees who fights for them before anything is decided
+    let message = format!(
+        "**`{}`** would field:\n{}\n\nSend the challenge?",
+        alliance.name,
+        roster_lines(&challengers)
+    );
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("The challenge was called off.", ce)))
+            .await?;
+        return Ok(());
+    }
+    let mut message = format!(
+        "**`{}`**, **`{}`** challenges **`{}`** to a battle of up to {} waifus each.",
+        opponent.name, alliance.name, opposing.name, ROSTER_SIZE
+    );
+    if wager > 0 {
+        message.push_str(&format!(
+            " The winner takes **{}** :coin: from the loser's treasury.",
+            wager
+        ));
+    }
+    message.push_str(&format!(
+        "\n\n**`{}`** fields:\n{}\n\n**`{}`** would field:\n{}\n\nDo you accept?",
+        alliance.name,
+        roster_lines(&challengers),
+        opposing.name,
+        roster_lines(&defenders)
+    ));
+    if !ConfirmMenu::start(ctx, opponent.id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("The challenge was declined.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let seed: u64 = thread_rng().gen();
+    let outcome = simulate(&challengers, &defenders, seed);
+    let winner = outcome.winner.pick(&alliance, &opposing);
+    let loser = outcome.winner.pick(&opposing, &alliance);
+    let action = LedgerAction::new(LedgerReason::AllianceBattle, &ctx.command().qualified_name)
+        .treasury(
+            serenity::UserId(loser.owner as u64),
+            loser.alliance_id,
+            -wager,
+        )
+        .treasury(
+            serenity::UserId(winner.owner as u64),
+            winner.alliance_id,
+            wager,
+        );
+    let recorded = ctx
+        .data()
+        .battles
+        .record_battle(
+            alliance.alliance_id,
+            opposing.alliance_id,
+            seed,
+            wager,
+            &outcome,
+            &action,
+        )
+        .await;
+    let battle = match recorded {
+        Ok(battle) => battle,
+        Err(e) if e.is::<LedgerError>() => {
+            let message = format!(
+                "**`{}`**'s treasury can no longer cover the wager.",
+                loser.name
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+                .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut description = format!("**`{}`** won after {} rounds!", winner.name, battle.rounds);
+    if wager > 0 {
+        description.push_str(&format!(
+            "\nThey take **{}** :coin: from **`{}`**'s treasury.",
+            wager, loser.name
+        ));
+    }
+    // the end of the fight, the full log is kept with the battle
+    let log_start = battle.log.len().saturating_sub(BATTLE_LOG_LINES);
+    description.push_str("\n\n");
+    description.push_str(&battle.log[log_start..].join("\n"));
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(format!("⚔️ {} vs {}", alliance.name, opposing.name))
+                .description(description)
+                .field(&alliance.name, roster_lines(&challengers), true)
+                .field(&opposing.name, roster_lines(&defenders), true)
+                .footer(|cf| cf.text(format!("Battle #{} • Seed {}", battle.battle_id, seed)))
+                .colour(serenity::Colour::RED)
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// The alliance's strongest waifus out of everything its members own
+async fn field_roster(ctx: Context<'_>, alliance: &Alliance) -> Result<Vec<Fighter>, Error> {
+    let user_ids: Vec<serenity::UserId> = std::iter::once(alliance.owner)
+        .chain(alliance.members.iter().copied())
+        .map(|user_id| serenity::UserId(user_id as u64))
+        .collect();
+    // the whole alliance is loaded in a few queries, however many members it has
+    let experience: HashMap<i64, i32> = ctx
+        .data()
+        .accounts
+        .get_accounts(&user_ids)
+        .await?
+        .into_iter()
+        .map(|a| (a.user_id, a.experience))
+        .collect();
+    let owned_waifus = ctx.data().accounts.get_waifus_of(&user_ids).await?;
+    let mut waifu_ids: Vec<i32> = owned_waifus.iter().map(|w| w.waifu_id as i32).collect();
+    waifu_ids.sort();
+    waifu_ids.dedup();
+    let waifus = ctx.data().catalog.get_waifus(waifu_ids).await?;
+    let fighters = InventoryWaifu::join(owned_waifus, &waifus)
+        .iter()
+        .filter_map(|w| {
+            let experience = experience.get(&w.owned.owner_id)?;
+            Some(Fighter::new(w.owned.instance_id, &w.waifu, *experience))
+        })
+        .collect();
+
+    Ok(pick_roster(fighters))
+}
+
+fn roster_lines(fighters: &[Fighter]) -> String {
+    fighters
+        .iter()
+        .map(|f| format!("**{}** - ⚔️ {} ❤️ {}", f.name, f.power, f.health))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// What the author gets from their alliance's upgrade, nothing if they aren't in an alliance
+pub async fn upgrade_bonus(ctx: Context<'_>, upgrade: AllianceUpgrade) -> Result<i32, Error> {
+    let Some(membership) = ctx.data().alliances.get_membership(ctx.author().id).await? else {
+        return Ok(0);
+    };
+    let upgrades = ctx
+        .data()
+        .treasury
+        .get_alliance_upgrades(membership.alliance_id)
+        .await?;
+
+    Ok(upgrade.bonus(upgrades.get(&upgrade).copied().unwrap_or(0)))
+}
+
+/// The `/shop packs` discount the author's alliance gives them, in percent
+pub async fn author_pack_discount(ctx: Context<'_>) -> Result<i32, Error> {
+    if ctx
+        .data()
+        .alliances
+        .get_membership(ctx.author().id)
+        .await?
+        .is_none()
+    {
+        return Ok(0);
+    }
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+
+    Ok(pack_discount(alliance.level()))
+}
+
+/// See your alliance's level, perks and members
+#[poise::command(slash_command, check = "crate::checks::in_alliance")]
+pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
+    let members = ctx
+        .data()
+        .alliances
+        .get_alliance_members(alliance.alliance_id)
+        .await?;
+
+    let level = alliance.level();
+    let member_lines: Vec<String> = members
+        .iter()
+        .map(|m| format!("<@{}> - {}", m.user_id, m.role().name()))
+        .collect();
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(&alliance.name)
+                .description(format!(
+                    "Level **{}**\n{}/{} XP to level {}",
+                    level,
+                    alliance.experience % ALLIANCE_LEVEL_EXPERIENCE,
+                    ALLIANCE_LEVEL_EXPERIENCE,
+                    level + 1
+                ))
+                .field(
+                    "Members",
+                    format!("{}/{}", alliance.size(), member_cap(level)),
+                    true,
+                )
+                .field("Treasury", format!(":coin: {}", alliance.treasury), true)
+                .field("Pack Discount", format!("{}%", pack_discount(level)), true)
+                .field("Roster", member_lines.join("\n"), false)
+                .footer(|cf| {
+                    cf.text("Members earn alliance XP by feeding, summoning and claiming dailies")
+                })
+                .colour(serenity::Colour::BLITZ_BLUE)
+        })
+    })
+    .await?;
 
     Ok(())
 }
@@ -135,7 +943,7 @@ pub async fn visualize(ctx: Context<'_>) -> Result<(), Error> {
     // TODO: optimise
     ctx.defer_ephemeral().await?;
 
-    let alliance = ctx.data().postgres.get_alliance(ctx.author().id).await?;
+    let alliance = ctx.data().alliances.get_alliance(ctx.author().id).await?;
 
     let mut graph = Graph::<&str, &str>::new();
 
@@ -152,18 +960,21 @@ pub async fn visualize(ctx: Context<'_>) -> Result<(), Error> {
     }
 
     for (user_id, member_string) in name_bindings.iter() {
-        let waifu_ids = ctx
+        let owned_waifus = ctx
             .data()
-            .postgres
+            .accounts
             .get_waifus(serenity::UserId(user_id.clone() as u64))
             .await?;
         let waifus = ctx
             .data()
-            .mongo
-            .get_waifus(waifu_ids.iter().map(|el| el.clone() as i32).collect())
+            .catalog
+            .get_waifus(owned_waifus.iter().map(|w| w.waifu_id as i32).collect())
             .await?;
 
-        let waifu_names: Vec<String> = waifus.iter().map(|w| w.name.clone()).collect();
+        let waifu_names: Vec<String> = InventoryWaifu::join(owned_waifus, &waifus)
+            .iter()
+            .map(|w| w.waifu.name.clone())
+            .collect();
         user_waifu_names.push((member_string.clone(), waifu_names));
     }
 
@@ -215,8 +1026,9 @@ pub async fn visualize(ctx: Context<'_>) -> Result<(), Error> {
             ce.title(&alliance.name)
                 .image("attachment://graph.png")
                 .description(format!(
-                    "This alliance has {} members",
-                    alliance.members.len() + 1 // to include the owner
+                    "This level {} alliance has {} members",
+                    alliance.level(),
+                    alliance.size()
                 ))
                 .colour(serenity::Colour::BLITZ_BLUE)
         })
@@ -0,0 +1,73 @@
+//! Autocomplete shared by every command taking a catalog waifu, backed by [`crate::models::catalog_index`]
+
+use crate::{models::waifu::OwnedWaifu, Context, Error};
+
+/// Discord shows at most this many choices
+const MAX_CHOICES: usize = 25;
+
+/// The caller's inventory, cached in [`crate::checks::CheckCache`] until a command changes it
+pub async fn cached_owned_waifus(ctx: Context<'_>) -> Result<Vec<OwnedWaifu>, Error> {
+    let cache = &ctx.data().check_cache;
+    if let Some(owned_waifus) = cache.get_owned_waifus(ctx.author().id).await {
+        return Ok(owned_waifus);
+    }
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    cache
+        .insert_owned_waifus(ctx.author().id, owned_waifus.clone())
+        .await;
+
+    Ok(owned_waifus)
+}
+
+/// [`cached_owned_waifus`] for autocompletes, which have nowhere to report errors
+pub async fn owned_waifus(ctx: Context<'_>) -> Vec<OwnedWaifu> {
+    cached_owned_waifus(ctx).await.unwrap_or_default()
+}
+
+/// Ids of the waifus the caller owns, which autocomplete ranks first
+async fn owned_waifu_ids(ctx: Context<'_>) -> Vec<i16> {
+    let owned_waifus = owned_waifus(ctx).await;
+
+    owned_waifus.iter().map(|w| w.waifu_id).collect()
+}
+
+/// Any waifu in the catalog, by id
+pub async fn autocomplete_catalog_waifu<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = poise::AutocompleteChoice<i32>> {
+    let owned = owned_waifu_ids(ctx).await;
+    let matches = ctx
+        .data()
+        .catalog_index
+        .complete(partial, &owned, MAX_CHOICES)
+        .await;
+
+    matches
+        .into_iter()
+        .map(|(waifu_id, name)| poise::AutocompleteChoice {
+            name,
+            value: waifu_id as i32,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Any waifu in the catalog, by name, for commands searching by text
+pub async fn autocomplete_catalog_name<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> {
+    let owned = owned_waifu_ids(ctx).await;
+    let matches = ctx
+        .data()
+        .catalog_index
+        .complete(partial, &owned, MAX_CHOICES)
+        .await;
+
+    matches
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
@@ -1,6 +1,19 @@
+use std::collections::HashMap;
+
 use rand::{thread_rng, Rng};
 
-use crate::{utils::fmt, Context, Error};
+use super::{alliances, autocomplete};
+use crate::{
+    models::{
+        alliance::AllianceUpgrade,
+        catalog_index::match_rank,
+        ledger::{LedgerAction, LedgerReason},
+        price::compute_price,
+        waifu::{level_for, InventoryWaifu, Waifu},
+    },
+    utils::fmt,
+    Context, Error,
+};
 
 #[derive(Debug, poise::ChoiceParameter)]
 pub enum FoodChoice {
@@ -51,39 +64,107 @@ pub async fn interact(_: Context<'_>) -> Result<(), Error> {
 pub async fn autocomplete_waifu_name<'a>(
     ctx: Context<'_>,
     partial: &'a str,
-) -> impl Iterator<Item = poise::AutocompleteChoice<u16>> {
-    let waifu_ids = ctx
-        .data()
-        .postgres
-        .get_waifus(ctx.author().id)
-        .await
-        .unwrap_or(vec![]);
-    let waifu_ids = waifu_ids.iter().map(|el| el.clone() as i32).collect();
-    let waifus = ctx
-        .data()
-        .mongo
-        .get_waifus(waifu_ids)
-        .await
-        .unwrap_or(vec![]);
+) -> impl Iterator<Item = poise::AutocompleteChoice<i64>> {
+    let owned_waifus = autocomplete::owned_waifus(ctx).await;
+    let waifu_ids: Vec<i16> = owned_waifus.iter().map(|w| w.waifu_id).collect();
+    let names = ctx.data().catalog_index.names(&waifu_ids).await;
     let mut possible_options = vec![];
 
-    for waifu in waifus.iter() {
-        if waifu
-            .name
-            .to_lowercase()
-            .starts_with(&partial.to_lowercase())
-        {
-            possible_options.push((waifu.name.clone(), waifu._id));
+    for owned in owned_waifus {
+        let Some((_, name)) = names
+            .iter()
+            .find(|(waifu_id, _)| *waifu_id == owned.waifu_id)
+        else {
+            continue;
+        };
+        if let Some(rank) = match_rank(name, partial) {
+            // the instance id tells apart copies of the same waifu
+            let name = format!("{} (#{})", name, owned.instance_id);
+            possible_options.push((rank, name, owned.instance_id));
         }
     }
+    possible_options.sort_by_key(|(rank, _, _)| *rank);
+
+    possible_options
+        .into_iter()
+        .take(25)
+        .map(|(_, name, id)| poise::AutocompleteChoice { name, value: id })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Looks up a waifu instance owned by the author, `None` if they don't own it
+pub async fn get_owned_waifu(
+    ctx: Context<'_>,
+    instance_id: i64,
+) -> Result<Option<InventoryWaifu>, Error> {
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    let Some(owned) = owned_waifus
+        .into_iter()
+        .find(|w| w.instance_id == instance_id)
+    else {
+        return Ok(None);
+    };
+    let waifu = ctx.data().catalog.get_waifu(owned.waifu_id.into()).await?;
+
+    Ok(Some(InventoryWaifu { owned, waifu }))
+}
 
-    let mut autocomplete_options = vec![];
-    for (name, id) in possible_options {
-        let ao = poise::AutocompleteChoice { name, value: id };
-        autocomplete_options.push(ao);
+/// The waifu's latest computed price, or a fresh one if prices haven't been computed since it was added
+pub async fn current_price(ctx: Context<'_>, waifu: &Waifu) -> Result<i32, Error> {
+    if let Some(price) = ctx.data().prices.get_price(waifu._id as i16).await? {
+        return Ok(price.price);
     }
+    let copies = ctx
+        .data()
+        .prices
+        .get_copy_counts()
+        .await?
+        .into_iter()
+        .find(|(waifu_id, _)| *waifu_id == waifu._id as i16)
+        .map_or(0, |(_, count)| count);
+
+    Ok(compute_price(waifu, copies))
+}
+
+/// [`current_price`] of several waifus at once, keyed by waifu id
+pub async fn current_prices(
+    ctx: Context<'_>,
+    waifus: &[Waifu],
+) -> Result<HashMap<i16, i32>, Error> {
+    let waifu_ids: Vec<i16> = waifus.iter().map(|w| w._id as i16).collect();
+    let mut prices: HashMap<i16, i32> = ctx
+        .data()
+        .prices
+        .get_prices(&waifu_ids)
+        .await?
+        .into_iter()
+        .map(|p| (p.waifu_id, p.price))
+        .collect();
+    if prices.len() < waifus.len() {
+        let copies: HashMap<i16, i64> = ctx
+            .data()
+            .prices
+            .get_copy_counts()
+            .await?
+            .into_iter()
+            .collect();
+        for waifu in waifus {
+            let copies = copies.get(&(waifu._id as i16)).copied().unwrap_or(0);
+            prices
+                .entry(waifu._id as i16)
+                .or_insert_with(|| compute_price(waifu, copies));
+        }
+    }
+
+    Ok(prices)
+}
+
+async fn send_not_owned(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.send(|cr| cr.embed(|ce| fmt::error("You don't own this waifu.", ce)))
+        .await?;
 
-    autocomplete_options.into_iter()
+    Ok(())
 }
 
 /// Feed your waifus
@@ -92,12 +173,12 @@ pub async fn feed(
     ctx: Context<'_>,
     #[autocomplete = "autocomplete_waifu_name"]
     #[description = "Which waifu to feed"]
-    waifu: u16,
+    waifu: i64,
     #[description = "What to feed your waifu"] food: FoodChoice,
 ) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
-    let account = ctx.data().postgres.get_account(ctx.author().id).await?;
+    let account = ctx.data().accounts.get_account(ctx.author().id).await?;
     let price = food.price();
 
     if account.currency < price as i32 {
@@ -111,33 +192,38 @@ pub async fn feed(
         })
         .await?;
     } else {
-        let waifu = ctx.data().mongo.get_waifu(waifu as i32).await?;
-        let experience = food.random_experience();
-        ctx.data()
-            .postgres
-            .update_experience(ctx.author().id, experience as i32)
-            .await?;
+        let Some(owned) = get_owned_waifu(ctx, waifu).await? else {
+            return send_not_owned(ctx).await;
+        };
+        let mut experience = food.random_experience() as i32;
+        experience +=
+            experience * alliances::upgrade_bonus(ctx, AllianceUpgrade::Pantry).await? / 100;
+        // the waifu gains the experience, and so do the account feeding it and its alliance
+        let action = LedgerAction::new(LedgerReason::WaifuFeed, &ctx.command().qualified_name)
+            .currencies(ctx.author().id, -(price as i32), 0)
+            .waifu_experience(ctx.author().id, owned.owned.instance_id, experience)
+            .experience(ctx.author().id, experience)
+            .alliance_experience(ctx.author().id, experience);
+        ctx.data().accounts.apply_ledger(&action).await?;
 
-        ctx.data()
-            .postgres
-            .update_currencies(ctx.author().id, -(price as i32), 0)
-            .await?;
+        let mut message = format!(
+            "You fed {} some {} for {} :coin: - {} gained {} experience",
+            &owned.waifu.name,
+            food.icon(),
+            price,
+            &owned.waifu.name,
+            experience
+        );
+        let new_level = level_for(owned.owned.experience + experience);
+        if new_level > owned.owned.level() {
+            message.push_str(&format!(
+                "\n\n:tada: **`{}`** reached level **{}**!",
+                &owned.waifu.name, new_level
+            ));
+        }
 
-        ctx.send(|cr| {
-            cr.embed(|ce| {
-                fmt::success(
-                    &format!(
-                        "You fed {} some {} for {} :coin: - You gained {} experience",
-                        &waifu.name,
-                        food.icon(),
-                        price,
-                        experience
-                    ),
-                    ce,
-                )
-            })
-        })
-        .await?;
+        ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+            .await?;
     }
 
     Ok(())
@@ -149,27 +235,27 @@ pub async fn sell(
     ctx: Context<'_>,
     #[autocomplete = "autocomplete_waifu_name"]
     #[description = "Which waifu to sell"]
-    waifu: u16,
+    waifu: i64,
 ) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
 
-    let waifu = ctx.data().mongo.get_waifu(waifu as i32).await?;
-    let price = waifu.price();
-
-    ctx.data()
-        .postgres
-        .remove_waifu(ctx.author().id, waifu._id)
-        .await?;
+    let Some(owned) = get_owned_waifu(ctx, waifu).await? else {
+        return send_not_owned(ctx).await;
+    };
+    let price = current_price(ctx, &owned.waifu).await?;
 
-    ctx.data()
-        .postgres
-        .update_currencies(ctx.author().id, price as i32, 0)
-        .await?;
+    let action = LedgerAction::new(LedgerReason::WaifuSale, &ctx.command().qualified_name)
+        .remove_waifu(ctx.author().id, owned.owned.instance_id)
+        .currencies(ctx.author().id, price, 0);
+    ctx.data().accounts.apply_ledger(&action).await?;
 
     ctx.send(|cr| {
         cr.embed(|ce| {
             fmt::success(
-                &format!("Sold **`{}`** for **`{}`** :coin:", &waifu.name, price),
+                &format!(
+                    "Sold **`{}`** for **`{}`** :coin:",
+                    &owned.waifu.name, price
+                ),
                 ce,
             )
         })
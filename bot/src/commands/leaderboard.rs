@@ -0,0 +1,101 @@
+use crate::{
+    components::paginator::EmbedPaginator,
+    models::leaderboard::{LeaderboardCategory, LeaderboardPage},
+    utils::fmt,
+    Context, Error,
+};
+
+/// How many entries a leaderboard goes down to
+const LEADERBOARD_SIZE: i64 = 100;
+
+#[derive(poise::ChoiceParameter)]
+pub enum LeaderboardScope {
+    Global,
+    #[name = "This Server"]
+    Server,
+}
+
+/// See the best players, globally or in this server
+#[poise::command(slash_command)]
+pub async fn leaderboard(
+    ctx: Context<'_>,
+    #[description = "What to rank players by"] category: LeaderboardCategory,
+    #[description = "Rank every player, or only the ones in this server"] scope: Option<
+        LeaderboardScope,
+    >,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let (guild_id, title) = match scope.unwrap_or(LeaderboardScope::Global) {
+        LeaderboardScope::Global => (None, String::from("Global Leaderboard")),
+        LeaderboardScope::Server => {
+            let Some(guild_id) = ctx.guild_id() else {
+                ctx.send(|cr| {
+                    cr.embed(|ce| {
+                        fmt::error("Server leaderboards can only be viewed in a server.", ce)
+                    })
+                })
+                .await?;
+                return Ok(());
+            };
+            let title = match ctx.guild() {
+                Some(guild) => format!("{} Leaderboard", guild.name),
+                None => String::from("Server Leaderboard"),
+            };
+            (Some(guild_id), title)
+        }
+    };
+
+    let entries = ctx
+        .data()
+        .leaderboards
+        .get_leaderboard(category, guild_id, LEADERBOARD_SIZE)
+        .await?;
+    if entries.is_empty() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Nobody is on this leaderboard yet.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let pages = LeaderboardPage::paginate(&title, category, entries);
+    let mut paginator = EmbedPaginator::new(pages);
+    paginator.start(ctx, false).await?;
+
+    Ok(())
+}
+
+/// Records the author as a player of the current guild, so they show up on its leaderboards.
+/// Runs before every command, the cache keeps it to one query per player and guild.
+pub async fn track_guild_member(ctx: Context<'_>) {
+    let Some(guild_id) = ctx.guild_id() else {
+        return;
+    };
+    let user_id = ctx.author().id;
+    if ctx
+        .data()
+        .check_cache
+        .has_guild_member(guild_id, user_id)
+        .await
+    {
+        return;
+    }
+
+    match ctx
+        .data()
+        .leaderboards
+        .record_guild_member(guild_id, user_id)
+        .await
+    {
+        Ok(()) => {
+            ctx.data()
+                .check_cache
+                .insert_guild_member(guild_id, user_id)
+                .await
+        }
+        Err(e) => println!("Failed to record guild member: {e}"),
+    }
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [leaderboard()]
+}
@@ -0,0 +1,322 @@
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{Duration, Utc};
+
+use super::interactions::{autocomplete_waifu_name, get_owned_waifu};
+use crate::{
+    components::{confirm::ConfirmMenu, paginator::EmbedPaginator},
+    models::{
+        ledger::{LedgerAction, LedgerError, LedgerReason},
+        market::{listing_fee, ListingFilter, ListingUnavailable, MarketEntry},
+        waifu::Rarity,
+    },
+    utils::fmt,
+    Context, Error,
+};
+
+/// How many listings `/market browse` goes through
+const BROWSE_LIMIT: i64 = 100;
+
+/// Buy and sell waifus with other players
+#[poise::command(
+    slash_command,
+    subcommands("list", "browse", "buy", "cancel"),
+    check = "crate::checks::has_account"
+)]
+pub async fn market(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn autocomplete_own_listing<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = poise::AutocompleteChoice<i64>> {
+    let filter = ListingFilter {
+        seller_id: Some(ctx.author().id),
+        ..Default::default()
+    };
+    let listings = ctx
+        .data()
+        .market
+        .get_listings(&filter, BROWSE_LIMIT)
+        .await
+        .unwrap_or(vec![]);
+    let waifu_ids = listings.iter().map(|l| l.waifu_id as i32).collect();
+    let waifus = ctx
+        .data()
+        .catalog
+        .get_waifus(waifu_ids)
+        .await
+        .unwrap_or(vec![]);
+
+    MarketEntry::join(listings, &waifus)
+        .into_iter()
+        .filter(|e| {
+            e.waifu
+                .name
+                .to_lowercase()
+                .starts_with(&partial.to_lowercase())
+        })
+        .map(|e| poise::AutocompleteChoice {
+            name: format!(
+                "{} - {} coins (#{})",
+                e.waifu.name, e.listing.price, e.listing.listing_id
+            ),
+            value: e.listing.listing_id,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Put one of your waifus up for sale
+#[poise::command(slash_command)]
+pub async fn list(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_waifu_name"]
+    #[description = "Which waifu to sell"]
+    waifu: i64,
+    #[description = "How much currency you want for it"]
+    #[min = 1]
+    #[max = 1000000]
+    price: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(owned) = get_owned_waifu(ctx, waifu).await? else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You don't own this waifu.", ce)))
+            .await?;
+        return Ok(());
+    };
+    let market_conf = &ctx.data().conf.market;
+    let fee = listing_fee(price, market_conf.listing_fee_percent);
+    if fee > 0 {
+        let message = format!(
+            "Listing **`{}`** for **{}** :coin: costs a fee of **{}** :coin:, which is **not** refunded if the listing is cancelled or expires.",
+            owned.waifu.name, price, fee
+        );
+        if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+            ctx.send(|cr| cr.embed(|ce| fmt::error("The waifu was not listed.", ce)))
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let expires_at = Utc::now() + Duration::hours(market_conf.listing_hours);
+    let fee_action = LedgerAction::new(LedgerReason::MarketListing, &ctx.command().qualified_name)
+        .currencies(ctx.author().id, -fee, 0);
+    let created = ctx
+        .data()
+        .market
+        .create_listing(ctx.author().id, waifu, price, expires_at, &fee_action)
+        .await;
+    match created {
+        Ok(listing) => {
+            let message = format!(
+                "Listed **`{}`** for **{}** :coin: as listing **#{}**. It expires <t:{}:R>, cancel it any time with `/market cancel`",
+                owned.waifu.name,
+                listing.price,
+                listing.listing_id,
+                listing.expires_at.timestamp()
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| {
+                cr.embed(|ce| {
+                    fmt::error(
+                        "You can't pay the listing fee, or this waifu is no longer yours to list.",
+                        ce,
+                    )
+                })
+            })
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+/// Browse the waifus for sale
+#[poise::command(slash_command)]
+pub async fn browse(
+    ctx: Context<'_>,
+    #[description = "Only show waifus with this name"] name: Option<String>,
+    #[description = "Only show waifus of this rarity"] rarity: Option<Rarity>,
+    #[description = "Only show waifus sold by this player"] seller: Option<serenity::User>,
+    #[description = "The lowest price to show"]
+    #[min = 1]
+    min_price: Option<i32>,
+    #[description = "The highest price to show"]
+    #[min = 1]
+    max_price: Option<i32>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    // both filters become waifu ids, so the listings query narrows them down itself
+    let waifu_ids = match (name, rarity) {
+        (Some(name), rarity) => {
+            let waifus = ctx
+                .data()
+                .catalog
+                .search_waifus(&name, rarity, 0, BROWSE_LIMIT as usize)
+                .await?;
+            Some(waifus.iter().map(|w| w._id as i16).collect())
+        }
+        (None, Some(rarity)) => Some(ctx.data().catalog_index.ids_with_rarity(rarity).await),
+        (None, None) => None,
+    };
+    let filter = ListingFilter {
+        waifu_ids,
+        seller_id: seller.map(|s| s.id),
+        min_price,
+        max_price,
+    };
+    let listings = ctx
+        .data()
+        .market
+        .get_listings(&filter, BROWSE_LIMIT)
+        .await?;
+    let waifu_ids = listings.iter().map(|l| l.waifu_id as i32).collect();
+    let waifus = ctx.data().catalog.get_waifus(waifu_ids).await?;
+    let entries = MarketEntry::join(listings, &waifus);
+    if entries.is_empty() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("No listings found.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let mut paginator = EmbedPaginator::new(entries);
+    if let Some(entry) = paginator.start(ctx, true).await? {
+        purchase(ctx, entry).await?;
+    }
+
+    Ok(())
+}
+
+/// Buy a waifu from the market
+#[poise::command(slash_command)]
+pub async fn buy(
+    ctx: Context<'_>,
+    #[description = "The listing number, shown when browsing the market"] listing: i64,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(listing) = ctx.data().market.get_listing(listing).await? else {
+        return send_unavailable(ctx).await;
+    };
+    let waifu = ctx
+        .data()
+        .catalog
+        .get_waifu(listing.waifu_id.into())
+        .await?;
+
+    purchase(ctx, &MarketEntry { listing, waifu }).await
+}
+
+/// Take one of your listings off the market
+#[poise::command(slash_command)]
+pub async fn cancel(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_own_listing"]
+    #[description = "Which listing to cancel"]
+    listing: i64,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let cancelled = ctx
+        .data()
+        .market
+        .cancel_listing(listing, ctx.author().id)
+        .await?;
+    if cancelled.is_some() {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::success(
+                    "Listing cancelled, the waifu is back in your collection.",
+                    ce,
+                )
+            })
+        })
+        .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You don't have a listing with this number.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn purchase(ctx: Context<'_>, entry: &MarketEntry) -> Result<(), Error> {
+    if entry.listing.seller() == ctx.author().id {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::error(
+                    "You can't buy your own listing. Take it down with `/market cancel`",
+                    ce,
+                )
+            })
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let message = format!(
+        "Buy **`{}`** from <@{}> for **{}** :coin:?",
+        entry.waifu.name, entry.listing.seller_id, entry.listing.price
+    );
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Purchase cancelled.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let bought = ctx
+        .data()
+        .market
+        .buy_listing(
+            entry.listing.listing_id,
+            ctx.author().id,
+            &ctx.command().qualified_name,
+        )
+        .await;
+    match bought {
+        Ok(listing) => {
+            // the seller's inventory changed too, not only the buyer's
+            let sale = listing.to_sale_action(ctx.author().id, &ctx.command().qualified_name);
+            ctx.data()
+                .check_cache
+                .invalidate_owned_waifus(&sale.inventory_owners())
+                .await;
+            let message = format!(
+                "You bought **`{}`** for **{}** :coin:! Check it out with `/account waifus`",
+                entry.waifu.name, listing.price
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Err(e) if e.is::<ListingUnavailable>() => send_unavailable(ctx).await?,
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("You don't have enough currency to buy this.", ce))
+            })
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+async fn send_unavailable(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.send(|cr| {
+        cr.embed(|ce| fmt::error("This listing was sold, cancelled or has expired.", ce))
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [market()]
+}
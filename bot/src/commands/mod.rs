@@ -1,10 +1,33 @@
 mod accounts;
+mod achievements;
 mod alliances;
+mod autocomplete;
 mod interactions;
+mod leaderboard;
+mod market;
+mod rewards;
 mod shop;
 mod summon;
+mod trade;
+mod vote;
+mod waifu;
+mod wishlist;
 
-use crate::{components::paginator::EmbedPaginator, utils::fmt};
+pub use leaderboard::track_guild_member;
+
+use autocomplete::autocomplete_catalog_name;
+
+use crate::{
+    components::paginator::EmbedPaginator,
+    models::{
+        price::PricedWaifu,
+        waifu::{Rarity, SEARCH_LIMIT},
+    },
+    utils::fmt,
+};
+
+/// How many catalog matches `/search` fetches at a time when filtering by ownership or price
+const SEARCH_PAGE: usize = 100;
 
 pub fn commands() -> Vec<crate::Command> {
     accounts::commands()
@@ -13,10 +36,28 @@ pub fn commands() -> Vec<crate::Command> {
         .chain(shop::commands())
         .chain(interactions::commands())
         .chain(alliances::commands())
+        .chain(trade::commands())
+        .chain(rewards::commands())
+        .chain(leaderboard::commands())
+        .chain(market::commands())
+        .chain(wishlist::commands())
+        .chain(vote::commands())
+        .chain(waifu::commands())
+        .chain(achievements::commands())
         .chain([hello(), search()])
         .collect()
 }
 
+/// Runs after every command. Drops the author's cached inventory, since most commands can
+/// change it, then checks for newly earned achievements.
+pub async fn after_command(ctx: crate::Context<'_>) {
+    ctx.data()
+        .check_cache
+        .invalidate_owned_waifus(&[ctx.author().id])
+        .await;
+    achievements::track_achievements(ctx).await;
+}
+
 /// Make the bot say hi. Simply a debug command.
 #[poise::command(slash_command)]
 pub async fn hello(ctx: crate::Context<'_>) -> Result<(), crate::Error> {
@@ -25,16 +66,79 @@ pub async fn hello(ctx: crate::Context<'_>) -> Result<(), crate::Error> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum OwnedFilter {
+    Owned,
+    #[name = "Not owned"]
+    Unowned,
+}
+
 /// Search for waifus. Useful for checking if a waifu exists
 #[poise::command(slash_command)]
-pub async fn search(ctx: crate::Context<'_>, name: String) -> Result<(), crate::Error> {
+pub async fn search(
+    ctx: crate::Context<'_>,
+    #[autocomplete = "autocomplete_catalog_name"]
+    #[description = "Part of the waifu's name"]
+    name: String,
+    #[description = "Only show waifus of this rarity"] rarity: Option<Rarity>,
+    #[description = "Only show waifus you own, or don't"] owned: Option<OwnedFilter>,
+    #[description = "The lowest sell price to show"]
+    #[min = 0]
+    min_price: Option<i32>,
+    #[description = "The highest sell price to show"]
+    #[min = 0]
+    max_price: Option<i32>,
+) -> Result<(), crate::Error> {
     ctx.defer_ephemeral().await?;
-    let waifus = ctx.data().mongo.search_waifus(&name).await?;
-    if waifus.len() <= 0 {
-        ctx.send(|cr| cr.embed(|ce| fmt::error("No results found with that name.", ce)))
+    let owned_filter = match owned {
+        Some(owned) => {
+            let owned_waifus = autocomplete::cached_owned_waifus(ctx).await?;
+            let owned_ids: Vec<i16> = owned_waifus.iter().map(|o| o.waifu_id).collect();
+            Some((owned, owned_ids))
+        }
+        None => None,
+    };
+    let filtered = owned.is_some() || min_price.is_some() || max_price.is_some();
+    let page_size = if filtered { SEARCH_PAGE } else { SEARCH_LIMIT };
+
+    // owned and price filters run on each page of matches, so pages are fetched until enough pass them
+    let mut results: Vec<PricedWaifu> = vec![];
+    let mut offset = 0;
+    loop {
+        let mut waifus = ctx
+            .data()
+            .catalog
+            .search_waifus(&name, rarity, offset, page_size)
+            .await?;
+        let fetched = waifus.len();
+        offset += fetched;
+        if let Some((owned, owned_ids)) = &owned_filter {
+            waifus.retain(|w| {
+                let is_owned = owned_ids.contains(&(w._id as i16));
+                is_owned == (*owned == OwnedFilter::Owned)
+            });
+        }
+        let prices = interactions::current_prices(ctx, &waifus).await?;
+        results.extend(
+            waifus
+                .into_iter()
+                .filter_map(|waifu| {
+                    let price = *prices.get(&(waifu._id as i16))?;
+                    Some(PricedWaifu { waifu, price })
+                })
+                .filter(|r| min_price.map_or(true, |min| r.price >= min))
+                .filter(|r| max_price.map_or(true, |max| r.price <= max)),
+        );
+        if results.len() >= SEARCH_LIMIT || fetched < page_size {
+            break;
+        }
+    }
+    results.truncate(SEARCH_LIMIT);
+    if results.is_empty() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("No waifus match your search.", ce)))
             .await?;
     } else {
-        let mut paginator = EmbedPaginator::new(waifus);
+        let mut paginator = EmbedPaginator::new(results);
         paginator.start(ctx, false).await?;
     }
 
@@ -0,0 +1,97 @@
+use sqlx::types::chrono::Utc;
+
+use super::alliances;
+use crate::{
+    models::{
+        alliance::{AllianceUpgrade, DAILY_ALLIANCE_EXPERIENCE},
+        ledger::{LedgerAction, LedgerReason},
+        reward::{AlreadyClaimed, ClaimStatus, RewardKind},
+    },
+    utils::fmt,
+    Context, Error,
+};
+
+/// Claim your daily reward. Claim every day to build a streak!
+#[poise::command(slash_command, check = "crate::checks::has_account")]
+pub async fn daily(ctx: Context<'_>) -> Result<(), Error> {
+    claim(ctx, RewardKind::Daily).await
+}
+
+/// Claim your weekly reward. Claim every week to build a streak!
+#[poise::command(slash_command, check = "crate::checks::has_account")]
+pub async fn weekly(ctx: Context<'_>) -> Result<(), Error> {
+    claim(ctx, RewardKind::Weekly).await
+}
+
+async fn claim(ctx: Context<'_>, kind: RewardKind) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let user_id = ctx.author().id;
+    let now = Utc::now();
+    let previous = ctx.data().rewards.get_reward_claim(user_id, kind).await?;
+    let streak = match kind.status(previous.as_ref(), now) {
+        ClaimStatus::Ready { streak } => streak,
+        ClaimStatus::Cooldown { available_at } => {
+            let message = format!(
+                "You already claimed your {} reward. Come back <t:{}:R>",
+                kind.as_str(),
+                available_at.timestamp()
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut reward = kind.reward(streak);
+    if kind == RewardKind::Daily {
+        reward.currency += alliances::upgrade_bonus(ctx, AllianceUpgrade::Stipend).await?;
+    }
+    let mut action = LedgerAction::new(LedgerReason::Reward, &ctx.command().qualified_name)
+        .currencies(user_id, reward.currency, 0)
+        .packs(user_id, reward.packs, reward.premium_one_packs);
+    if kind == RewardKind::Daily {
+        action = action.alliance_experience(user_id, DAILY_ALLIANCE_EXPERIENCE);
+    }
+    let claimed = ctx
+        .data()
+        .rewards
+        .claim_reward(
+            user_id,
+            kind,
+            previous.map(|c| c.last_claimed_at),
+            now,
+            streak,
+            &action,
+        )
+        .await;
+    if let Err(error) = claimed {
+        if error.is::<AlreadyClaimed>() {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("This reward was just claimed. Try again later.", ce))
+            })
+            .await?;
+            return Ok(());
+        }
+        return Err(error);
+    }
+
+    let next_claim = now + kind.period();
+    let message = format!(
+        "You claimed {}!\n\nStreak: **{}**\nNext claim <t:{}:R>, before <t:{}:f> to keep your streak.",
+        reward.describe(),
+        streak,
+        next_claim.timestamp(),
+        (next_claim + kind.grace()).timestamp()
+    );
+    ctx.send(|cr| {
+        cr.embed(|ce| fmt::success(&message, ce).title(format!("{} Reward", kind.name())))
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 2] {
+    [daily(), weekly()]
+}
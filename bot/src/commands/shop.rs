@@ -1,10 +1,17 @@
 use poise::serenity_prelude::{ButtonStyle, CacheHttp};
 
+use super::{alliances, autocomplete::autocomplete_catalog_waifu, interactions::current_price};
 use crate::{
     components::{
         choice::ChoicePrompt,
+        confirm::ConfirmMenu,
         shop::{Item, Shop},
     },
+    models::{
+        alliance::apply_discount,
+        ledger::{LedgerAction, LedgerError, LedgerReason},
+        price::shop_price,
+    },
     utils::fmt,
     Context, Error,
 };
@@ -13,7 +20,7 @@ const SCINE_INVITE: &str = "https://discord.gg/2RTEu23AZP";
 
 #[poise::command(
     slash_command,
-    subcommands("packs", "premium", "exchange"),
+    subcommands("packs", "waifu", "premium", "exchange"),
     check = "crate::checks::has_account"
 )]
 pub async fn shop(_: Context<'_>) -> Result<(), Error> {
@@ -24,25 +31,35 @@ pub async fn shop(_: Context<'_>) -> Result<(), Error> {
 #[poise::command(slash_command)]
 pub async fn packs(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
-    let account = ctx.data().postgres.get_account(ctx.author().id).await?;
+    let account = ctx.data().accounts.get_account(ctx.author().id).await?;
+    let discount = alliances::author_pack_discount(ctx).await?;
+    let price = |currency: i32, premium_currency: i32| {
+        (
+            apply_discount(currency, discount),
+            Some(apply_discount(premium_currency, discount)),
+        )
+    };
 
     let items = vec![
         Item::new(
             "Standard Pack",
-            "Comes with 3 waifus (any rarity)",
-            (250, Some(50)),
+            "Comes with 3 waifus (any rarity, see `/odds`)",
+            price(250, 50),
         ),
         Item::new(
             "Gold Pack",
-            "Comes with 5 waifus (any rarity)",
-            (475, Some(100)),
+            "Comes with 5 waifus with better odds of rare ones (see `/odds`)",
+            price(475, 100),
         ),
     ];
-    let shop = Shop::new(
-        "Pack Shop",
-        "Purchase some packs with currency or premium currency. You can purchase waifus directly with premium currency.",
-        items,
-    );
+    let mut description = String::from("Purchase some packs with currency or premium currency. You can purchase waifus directly with premium currency using `/shop waifu`.");
+    if discount > 0 {
+        description.push_str(&format!(
+            "\n\nYour alliance's level takes **{}%** off every pack.",
+            discount
+        ));
+    }
+    let shop = Shop::new("Pack Shop", &description, items);
     let chosen_item = shop.start(ctx).await?;
     if let Some(chosen_item) = chosen_item {
         let choice_menu = ChoicePrompt::new(vec![("Standard", 1), ("Premium", 2)]);
@@ -76,22 +93,16 @@ pub async fn packs(ctx: Context<'_>) -> Result<(), Error> {
                 }
             }
 
-            ctx.data()
-                .postgres
-                .update_currencies(ctx.author().id, -currency, -premium_currency)
-                .await?;
-
-            if chosen_item.name.as_str() == "Standard Pack" {
-                ctx.data()
-                    .postgres
-                    .update_packs(ctx.author().id, 1, 0)
-                    .await?;
+            let (packs, premium_one_packs) = if chosen_item.name.as_str() == "Standard Pack" {
+                (1, 0)
             } else {
-                ctx.data()
-                    .postgres
-                    .update_packs(ctx.author().id, 0, 1)
-                    .await?;
-            }
+                (0, 1)
+            };
+            let action =
+                LedgerAction::new(LedgerReason::PackPurchase, &ctx.command().qualified_name)
+                    .currencies(ctx.author().id, -currency, -premium_currency)
+                    .packs(ctx.author().id, packs, premium_one_packs);
+            ctx.data().accounts.apply_ledger(&action).await?;
 
             ctx.send(|cr| {
                 cr.embed(|ce| {
@@ -109,6 +120,66 @@ pub async fn packs(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Buy a waifu straight from the catalog with premium currency
+#[poise::command(slash_command)]
+pub async fn waifu(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_catalog_waifu"]
+    #[description = "The waifu to buy"]
+    waifu: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Ok(waifu) = ctx.data().catalog.get_waifu(waifu).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu doesn't exist.", ce)))
+            .await?;
+        return Ok(());
+    };
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    if owned_waifus.iter().any(|w| w.waifu_id == waifu._id as i16) {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You already own this waifu.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let price = shop_price(current_price(ctx, &waifu).await?);
+    let message = format!(
+        "Buy **`{}`** ({} {}) for **{}** 🍪?",
+        waifu.name,
+        waifu.rarity.icon(),
+        waifu.rarity.name(),
+        price
+    );
+    if !ConfirmMenu::start(ctx, ctx.author().id, &message).await? {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Purchase cancelled.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let action = LedgerAction::new(LedgerReason::WaifuPurchase, &ctx.command().qualified_name)
+        .currencies(ctx.author().id, 0, -price)
+        .add_waifu(ctx.author().id, waifu._id);
+    match ctx.data().accounts.apply_ledger(&action).await {
+        Ok(()) => {
+            let message = format!(
+                "You bought **`{}`** for **{}** 🍪! Check it out with `/account waifus`",
+                waifu.name, price
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        Err(e) if e.is::<LedgerError>() => {
+            ctx.send(|cr| {
+                cr.embed(|ce| fmt::error("You don't have enough premium currency to buy this.", ce))
+            })
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
 #[derive(serde::Deserialize)]
 pub struct UrlKey {
     pub url: String,
@@ -223,18 +294,14 @@ pub async fn exchange(ctx: Context<'_>, code: String) -> Result<(), Error> {
         let data: Metadata = resp.json().await?;
         let product = ctx
             .data()
-            .postgres
+            .products
             .get_premium_product(&data.price_id)
             .await?;
 
-        ctx.data()
-            .postgres
-            .update_packs(ctx.author().id, product.packs, product.premium_one_packs)
-            .await?;
-        ctx.data()
-            .postgres
-            .update_currencies(ctx.author().id, product.currency, product.premium_currency)
-            .await?;
+        let action = LedgerAction::new(LedgerReason::PremiumRedeem, &ctx.command().qualified_name)
+            .packs(ctx.author().id, product.packs, product.premium_one_packs)
+            .currencies(ctx.author().id, product.currency, product.premium_currency);
+        ctx.data().accounts.apply_ledger(&action).await?;
 
         ctx.send(|cr| {
             cr.embed(|ce| fmt::success("Successfully redeemed items. Enjoy and thank you!", ce))
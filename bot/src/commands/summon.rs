@@ -1,5 +1,20 @@
+use poise::serenity_prelude as serenity;
+use rand::{seq::SliceRandom, thread_rng};
+use sqlx::types::chrono::Utc;
+
+use super::achievements;
 use crate::{
     components::paginator::EmbedPaginator,
+    config,
+    models::{
+        account::Account,
+        achievement::Achievement,
+        alliance::SUMMON_ALLIANCE_EXPERIENCE,
+        banner::{Banner, BannerEntry, BannerPack},
+        ledger::{LedgerAction, LedgerError, LedgerReason},
+        pity::Pity,
+        waifu::{DropTable, Rarity, Waifu},
+    },
     utils::{fmt, ToEmbed},
     Context, Error,
 };
@@ -12,86 +27,509 @@ pub enum PackChoice {
     GoldPack,
 }
 impl PackChoice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::StandardPack => "Standard Pack",
+            Self::GoldPack => "Gold Pack",
+        }
+    }
+    /// The key of the pack's pity counter
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StandardPack => "standard",
+            Self::GoldPack => "gold",
+        }
+    }
     pub fn waifu_count(&self) -> u32 {
         match self {
             Self::StandardPack => 3,
             Self::GoldPack => 5,
         }
     }
+    pub fn drop_table<'a>(&self, packs: &'a config::Packs) -> &'a DropTable {
+        match self {
+            Self::StandardPack => &packs.standard,
+            Self::GoldPack => &packs.gold,
+        }
+    }
+    pub fn pity<'a>(&self, pity: &'a config::PityConfig) -> &'a Pity {
+        match self {
+            Self::StandardPack => &pity.standard,
+            Self::GoldPack => &pity.gold,
+        }
+    }
+}
+
+/// What a summon opens, one of the player's packs or a banner's own pack bought on the spot
+enum SummonPack {
+    Owned(PackChoice),
+    Banner { banner_id: i32, pack: BannerPack },
+}
+impl SummonPack {
+    fn name(&self) -> &str {
+        match self {
+            Self::Owned(pack) => pack.name(),
+            Self::Banner { pack, .. } => &pack.name,
+        }
+    }
+    fn waifu_count(&self) -> u32 {
+        match self {
+            Self::Owned(pack) => pack.waifu_count(),
+            Self::Banner { pack, .. } => pack.size as u32,
+        }
+    }
+    /// Banner packs roll and pity like gold packs, with a pity counter of their own
+    fn drop_table<'a>(&self, conf: &'a config::Config) -> &'a DropTable {
+        match self {
+            Self::Owned(pack) => pack.drop_table(&conf.packs),
+            Self::Banner { .. } => &conf.packs.gold,
+        }
+    }
+    fn pity<'a>(&self, conf: &'a config::Config) -> &'a Pity {
+        match self {
+            Self::Owned(pack) => pack.pity(&conf.pity),
+            Self::Banner { .. } => &conf.pity.gold,
+        }
+    }
+    fn pity_key(&self) -> String {
+        match self {
+            Self::Owned(pack) => pack.as_str().into(),
+            Self::Banner { banner_id, .. } => format!("banner-{banner_id}"),
+        }
+    }
+    fn can_afford(&self, account: &Account, amount: u8) -> bool {
+        match self {
+            Self::Owned(PackChoice::StandardPack) => account.packs >= amount.into(),
+            Self::Owned(PackChoice::GoldPack) => account.premium_one_packs >= amount.into(),
+            Self::Banner { pack, .. } => account.currency >= pack.price * i32::from(amount),
+        }
+    }
+    fn charge(&self, action: LedgerAction, user_id: serenity::UserId) -> LedgerAction {
+        match self {
+            Self::Owned(PackChoice::StandardPack) => action.packs(user_id, -1, 0),
+            Self::Owned(PackChoice::GoldPack) => action.packs(user_id, 0, -1),
+            Self::Banner { pack, .. } => action.currencies(user_id, -pack.price, 0),
+        }
+    }
+}
+
+async fn autocomplete_banner<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = poise::AutocompleteChoice<i32>> {
+    let now = Utc::now();
+    let banners = ctx.data().banners.get_banners().await.unwrap_or(vec![]);
+
+    banners
+        .into_iter()
+        .filter(|b| b.is_active(now) && b.name.to_lowercase().starts_with(&partial.to_lowercase()))
+        .map(|b| poise::AutocompleteChoice {
+            name: b.name,
+            value: b.banner_id,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// How the waifu kept from each pack is chosen
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq, Eq)]
+pub enum PickPreference {
+    #[name = "Choose myself"]
+    Manual,
+    #[name = "Rarest"]
+    Rarest,
+    #[name = "Most liked"]
+    MostLiked,
+}
+impl PickPreference {
+    /// The waifu to keep without asking, `None` if the player picks themselves
+    fn auto_pick<'a>(&self, waifus: &'a [Waifu]) -> Option<&'a Waifu> {
+        match self {
+            Self::Manual => None,
+            Self::Rarest => waifus.iter().max_by_key(|w| (w.rarity, w.likes)),
+            Self::MostLiked => waifus
+                .iter()
+                .max_by_key(|w| (i64::from(w.likes) - i64::from(w.trash), w.rarity)),
+        }
+    }
 }
 
-/// Summon a pack of waifus
+/// Summon packs of waifus
 #[poise::command(slash_command, check = "crate::checks::has_account")]
-pub async fn summon(ctx: Context<'_>, pack: PackChoice) -> Result<(), Error> {
+pub async fn summon(
+    ctx: Context<'_>,
+    #[description = "Which of your packs to open, standard packs by default"] pack: Option<
+        PackChoice,
+    >,
+    #[autocomplete = "autocomplete_banner"]
+    #[description = "A running banner to summon on, see `/banners`"]
+    banner: Option<i32>,
+    #[description = "How many packs to open, one by default"]
+    #[min = 1]
+    #[max = 10]
+    amount: Option<u8>,
+    #[description = "How to pick the waifu you keep from each pack, you choose by default"]
+    pick: Option<PickPreference>,
+) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
-    let account = ctx.data().postgres.get_account(ctx.author().id).await?;
-    let has_packs = match pack {
-        PackChoice::StandardPack => account.packs > 0,
-        PackChoice::GoldPack => account.premium_one_packs > 0,
+    let amount = amount.unwrap_or(1).clamp(1, 10);
+    let pick = pick.unwrap_or(PickPreference::Manual);
+
+    let banner = match banner {
+        Some(banner_id) => {
+            let banner = ctx.data().banners.get_banner(banner_id).await?;
+            let Some(banner) = banner.filter(|b| b.is_active(Utc::now())) else {
+                ctx.send(|cr| {
+                    cr.embed(|ce| {
+                        fmt::error(
+                            "This banner isn't running. Check the current banners with `/banners`",
+                            ce,
+                        )
+                    })
+                })
+                .await?;
+                return Ok(());
+            };
+            Some(banner)
+        }
+        None => None,
+    };
+    let summon_pack = match banner.as_ref().and_then(|b| Some((b.banner_id, b.pack()?))) {
+        Some((banner_id, pack)) => SummonPack::Banner { banner_id, pack },
+        None => SummonPack::Owned(pack.unwrap_or(PackChoice::StandardPack)),
     };
-    if !has_packs {
+
+    let account = ctx.data().accounts.get_account(ctx.author().id).await?;
+    if !summon_pack.can_afford(&account, amount) {
+        let message = match summon_pack {
+            SummonPack::Owned(_) => format!(
+                "You don't have {} packs of this type. Buy more in the pack shop (`/shop packs`)",
+                amount
+            ),
+            SummonPack::Banner { ref pack, .. } => format!(
+                "A **{}** costs **{}** :coin:, you don't have enough currency for {}.",
+                pack.name, pack.price, amount
+            ),
+        };
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)).ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let mut excluded_ids: Vec<i16> = ctx
+        .data()
+        .accounts
+        .get_waifus(ctx.author().id)
+        .await?
+        .iter()
+        .map(|w| w.waifu_id)
+        .collect();
+    let pity = summon_pack.pity(&ctx.data().conf);
+    let pity_key = summon_pack.pity_key();
+    let mut pulls = ctx.data().pity.get_pity(ctx.author().id, &pity_key).await?;
+    let wishlist = ctx.data().wishlists.get_wishlist(ctx.author().id).await?;
+
+    let mut gained = vec![];
+    let mut resets = 0;
+    let mut stopped_early = false;
+    let mut guarantee_pending = false;
+    let mut wishlist_hits = vec![];
+    for _ in 0..amount {
+        let guaranteed = pity.guarantees_next(pulls);
+        let waifus = draw_pack(
+            ctx,
+            &summon_pack,
+            banner.as_ref(),
+            guaranteed,
+            &wishlist,
+            &mut excluded_ids,
+        )
+        .await?;
+        let Some(first) = waifus.first() else {
+            stopped_early = true;
+            break;
+        };
+        let hit_premium = pity.hits_premium(&waifus);
+        let wishlisted: Vec<&Waifu> = waifus
+            .iter()
+            .filter(|w| wishlist.contains(&(w._id as i16)))
+            .collect();
+        if !wishlisted.is_empty() && pick == PickPreference::Manual {
+            send_wishlist_notice(ctx, &wishlisted).await?;
+        }
+        wishlist_hits.extend(wishlisted.iter().map(|w| w.name.clone()));
+
+        let kept = match pick.auto_pick(&waifus) {
+            Some(waifu) => waifu.clone(),
+            None => {
+                let mut paginator = EmbedPaginator::new(waifus.clone());
+                // packs that weren't picked from keep their first waifu
+                paginator.start(ctx, true).await?.unwrap_or(first).clone()
+            }
+        };
+
+        let action = summon_pack
+            .charge(
+                LedgerAction::new(LedgerReason::Summon, &ctx.command().qualified_name),
+                ctx.author().id,
+            )
+            .add_waifu(ctx.author().id, kept._id)
+            .alliance_experience(ctx.author().id, SUMMON_ALLIANCE_EXPERIENCE);
+        let recorded = ctx
+            .data()
+            .pity
+            .record_summon(ctx.author().id, &pity_key, hit_premium, &action)
+            .await;
+        match recorded {
+            Ok(new_pulls) => pulls = new_pulls,
+            // the packs or currency were spent elsewhere in the meantime
+            Err(e) if e.is::<LedgerError>() => {
+                stopped_early = true;
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+        if hit_premium {
+            resets += 1;
+        }
+        // every premium waifu of the pack is owned already, the counter isn't reset so the next pack tries again
+        guarantee_pending = guaranteed && !hit_premium;
+        gained.push((kept, guaranteed && hit_premium));
+    }
+
+    if gained.is_empty() {
         ctx.send(|cr| {
-            cr.embed(|ce| {
-                fmt::error(
-                    "You don't have any packs of this type. Buy one in the pack shop (`/shop packs`)",
-                    ce,
-                )
-            })
-            .ephemeral(true)
+            cr.embed(|ce| fmt::error("Nothing was summoned, you weren't charged.", ce))
+                .ephemeral(true)
         })
         .await?;
-    } else {
-        ctx.defer_ephemeral().await?;
-        let waifus = ctx
-            .data()
-            .mongo
-            .get_random_waifus(pack.waifu_count(), &account.waifus)
-            .await?;
-        let mut paginator = EmbedPaginator::new(waifus.clone());
-        let selected_waifu = paginator.start(ctx, true).await?;
-        if let Some(waifu) = selected_waifu {
-            ctx.send(|cr| {
-                cr.content(format!(
-                    "**`{}`** has been added to your inventory.",
-                    &waifu.name
+        return Ok(());
+    }
+
+    let lines: Vec<String> = gained
+        .iter()
+        .enumerate()
+        .map(|(index, (waifu, guaranteed))| {
+            let mut line = format!(
+                "**{}.** {} **`{}`** - {}",
+                index + 1,
+                waifu.rarity.icon(),
+                waifu.name,
+                waifu.rarity.name()
+            );
+            if *guaranteed {
+                line.push_str(" *(pity)*");
+            }
+            if wishlist.contains(&(waifu._id as i16)) {
+                line.push_str(" :star:");
+            }
+            line
+        })
+        .collect();
+    let mut pity_info = pity.describe(pulls);
+    if resets > 0 {
+        pity_info = format!("Your pity counter was reset {resets} times.\n{pity_info}");
+    }
+    if guarantee_pending {
+        pity_info.push_str(
+            "\nYou already own every waifu your guarantee could give, so it carries over to your next pack.",
+        );
+    }
+    let best = gained
+        .iter()
+        .map(|(waifu, _)| waifu)
+        .max_by_key(|w| (w.rarity, w.likes))
+        .unwrap();
+    let mut description = format!(
+        "Opened **{}** {}. Everything below was added to your inventory.\n\n{}",
+        gained.len(),
+        summon_pack.name(),
+        lines.join("\n")
+    );
+    if stopped_early {
+        description.push_str(
+            "\n\nSummoning stopped early, you weren't charged for the packs that weren't opened.",
+        );
+    }
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title("Summon Summary")
+                .description(description)
+                .thumbnail(best.download_url());
+            if !wishlist_hits.is_empty() {
+                ce.field(
+                    ":star: From your wishlist",
+                    format!("**`{}`**", wishlist_hits.join("`**, **`")),
+                    false,
+                );
+            }
+            ce.field("Pity", pity_info, false)
+                .colour(serenity::Colour::FABLED_PINK)
+        })
+        .ephemeral(true)
+    })
+    .await?;
+    achievements::award(ctx, ctx.author().id, Achievement::FirstSummon).await?;
+
+    Ok(())
+}
+
+async fn send_wishlist_notice(ctx: Context<'_>, wishlisted: &[&Waifu]) -> Result<(), Error> {
+    let names: Vec<String> = wishlisted
+        .iter()
+        .map(|w| format!("{} **`{}`**", w.rarity.icon(), w.name))
+        .collect();
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(":star: Wishlist hit!")
+                .description(format!(
+                    "This pack has a waifu from your wishlist:\n{}",
+                    names.join("\n")
                 ))
-                .embed(|ce| waifu.to_embed(ce))
-                .ephemeral(true)
-            })
-            .await?;
-        } else {
-            ctx.send(|cr| {
-                cr.ephemeral(true).embed(|ce| {
-                    fmt::error(
-                        "You did not select a waifu. A random one has been added to your inventory",
-                        ce,
-                    )
-                })
-            })
-            .await?;
-        }
+                .colour(serenity::Colour::GOLD)
+        })
+        .ephemeral(true)
+    })
+    .await?;
 
-        let (standard_packs, premium_packs) = match pack {
-            PackChoice::StandardPack => (-1, 0),
-            PackChoice::GoldPack => (0, -1),
-        };
+    Ok(())
+}
 
-        ctx.data()
-            .postgres
-            .update_packs(ctx.author().id, standard_packs, premium_packs)
+/// Draws the waifus of one pack: the pity guarantee first, then the banner's featured waifus,
+/// then the rest from the pack's drop table. Drawn waifus are added to `excluded_ids`.
+/// Wishlisted waifus get their boost in every draw but the featured one.
+async fn draw_pack(
+    ctx: Context<'_>,
+    pack: &SummonPack,
+    banner: Option<&Banner>,
+    guaranteed: bool,
+    wishlist: &[i16],
+    excluded_ids: &mut Vec<i16>,
+) -> Result<Vec<Waifu>, Error> {
+    let conf = &ctx.data().conf;
+    let table = pack.drop_table(conf);
+    let mut waifus = vec![];
+
+    if guaranteed {
+        let premium_table = pack.pity(conf).premium_table(table);
+        let mut drawn = ctx
+            .data()
+            .catalog
+            .get_random_waifus(1, excluded_ids, &premium_table, wishlist)
             .await?;
+        excluded_ids.extend(drawn.iter().map(|w| w._id as i16));
+        waifus.append(&mut drawn);
+    }
+
+    if let Some(banner) = banner {
+        let featured_count = banner.roll_featured(pack.waifu_count() - waifus.len() as u32);
+        if featured_count > 0 {
+            let featured_ids = banner
+                .featured_waifus
+                .iter()
+                .filter(|id| !excluded_ids.contains(*id))
+                .map(|id| *id as i32)
+                .collect();
+            let mut featured = ctx.data().catalog.get_waifus(featured_ids).await?;
+            featured.shuffle(&mut thread_rng());
+            featured.truncate(featured_count as usize);
+            excluded_ids.extend(featured.iter().map(|w| w._id as i16));
+            waifus.append(&mut featured);
+        }
+    }
 
-        let first = waifus.get(0).unwrap();
-        let to_add = selected_waifu.unwrap_or(first);
-        ctx.data()
-            .postgres
-            .add_waifu(ctx.author().id, to_add._id)
+    let remaining = pack.waifu_count() - waifus.len() as u32;
+    if remaining > 0 {
+        let mut drawn = ctx
+            .data()
+            .catalog
+            .get_random_waifus(remaining, excluded_ids, table, wishlist)
             .await?;
+        excluded_ids.extend(drawn.iter().map(|w| w._id as i16));
+        waifus.append(&mut drawn);
+    }
+
+    Ok(waifus)
+}
+
+/// See the running and upcoming banners
+#[poise::command(slash_command)]
+pub async fn banners(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let banners = ctx.data().banners.get_banners().await?;
+    if banners.is_empty() {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("There are no banners right now, check back later!", ce))
+        })
+        .await?;
+        return Ok(());
     }
 
+    let featured_ids = banners
+        .iter()
+        .flat_map(|b| b.featured_waifus.iter().map(|id| *id as i32))
+        .collect();
+    let waifus = ctx.data().catalog.get_waifus(featured_ids).await?;
+    let entries: Vec<BannerEntry> = banners
+        .into_iter()
+        .map(|banner| {
+            let featured = waifus
+                .iter()
+                .filter(|w| banner.featured_waifus.contains(&(w._id as i16)))
+                .cloned()
+                .collect();
+            BannerEntry { banner, featured }
+        })
+        .collect();
+
+    let mut paginator = EmbedPaginator::new(entries);
+    paginator.start(ctx, false).await?;
+
+    Ok(())
+}
+
+/// View the odds of each rarity in a pack
+#[poise::command(slash_command)]
+pub async fn odds(ctx: Context<'_>, pack: PackChoice) -> Result<(), Error> {
+    let table = pack.drop_table(&ctx.data().conf.packs);
+    let pulls = ctx
+        .data()
+        .pity
+        .get_pity(ctx.author().id, pack.as_str())
+        .await?;
+    let pity_info = pack.pity(&ctx.data().conf.pity).describe(pulls);
+    let odds: Vec<String> = Rarity::ALL
+        .iter()
+        .map(|rarity| {
+            format!(
+                "{} **{}** - {:.1}%",
+                rarity.icon(),
+                rarity.name(),
+                table.chance(*rarity)
+            )
+        })
+        .collect();
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(format!("{} Odds", pack.name()))
+                .description(format!(
+                    "Each of the {} waifus in this pack rolls its rarity separately.\n\n{}",
+                    pack.waifu_count(),
+                    odds.join("\n")
+                ))
+                .field("Pity", pity_info, false)
+                .colour(serenity::Colour::FABLED_PINK)
+        })
+        .ephemeral(true)
+    })
+    .await?;
+
     Ok(())
 }
 
-pub fn commands() -> [crate::Command; 1] {
-    [summon()]
+pub fn commands() -> [crate::Command; 3] {
+    [summon(), odds(), banners()]
 }
@@ -0,0 +1,447 @@
+use poise::serenity_prelude as serenity;
+
+use super::{achievements, interactions::autocomplete_waifu_name};
+use crate::{
+    components::confirm::ConfirmMenu,
+    models::{
+        achievement::Achievement,
+        ledger::LedgerError,
+        trade::{Trade, TradeConfirmation, TradeOffer, MAX_TRADE_CURRENCY, MAX_TRADE_PACKS},
+        waifu::InventoryWaifu,
+    },
+    utils::fmt,
+    Context, Error,
+};
+
+const NOT_TRADING_MESSAGE: &str =
+    "You're not in a trade. Start one with another player using `/trade start`";
+
+/// Trade waifus, currency and packs with other players
+#[poise::command(
+    slash_command,
+    subcommands("start", "add", "remove", "view", "confirm", "cancel"),
+    check = "crate::checks::has_account"
+)]
+pub async fn trade(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Start a trade with another player
+#[poise::command(slash_command)]
+pub async fn start(
+    ctx: Context<'_>,
+    #[description = "Who you want to trade with"] member: serenity::Member,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    if member.user.id == ctx.author().id {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("You can't trade with yourself.", ce))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    if ctx
+        .data()
+        .accounts
+        .get_account(member.user.id)
+        .await
+        .is_err()
+    {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::error(
+                    "This user does not have an account. Tell them to make one to trade with them",
+                    ce,
+                )
+            })
+            .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let message = format!(
+        "**`{}`**, would you like to trade with **`{}`**?",
+        member.display_name(),
+        ctx.author().name
+    );
+    let confirmed = ConfirmMenu::start(ctx, member.user.id, &message).await?;
+    if !confirmed {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("The trade was not accepted.", ce)))
+            .await?;
+    } else if ctx
+        .data()
+        .trade_book
+        .open(ctx.author().id, member.user.id)
+        .await
+    {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::success(
+                    "Trade started! Put up waifus, currency or packs with `/trade add`, then accept with `/trade confirm`",
+                    ce,
+                )
+            })
+        })
+        .await?;
+    } else {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::error(
+                    "One of you is already in a trade. Finish it or cancel it with `/trade cancel`",
+                    ce,
+                )
+            })
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Add something to your side of the trade
+#[poise::command(slash_command)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_waifu_name"]
+    #[description = "A waifu to offer"]
+    waifu: Option<i64>,
+    #[description = "How much currency to offer"]
+    #[min = 1]
+    #[max = 1000000]
+    currency: Option<i32>,
+    #[description = "How many packs to offer"]
+    #[min = 1]
+    #[max = 1000]
+    packs: Option<i16>,
+    #[description = "How many gold packs to offer"]
+    #[min = 1]
+    #[max = 1000]
+    gold_packs: Option<i16>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(trade) = ctx.data().trade_book.get(ctx.author().id).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+            .await?;
+        return Ok(());
+    };
+    if waifu.is_none() && currency.is_none() && packs.is_none() && gold_packs.is_none() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Choose something to add to the trade.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let mut new_offer = trade.offer(ctx.author().id).clone();
+    if let Some(instance_id) = waifu {
+        if new_offer.waifus.contains(&instance_id) {
+            ctx.send(|cr| cr.embed(|ce| fmt::error("You already offered this waifu.", ce)))
+                .await?;
+            return Ok(());
+        }
+        new_offer.waifus.push(instance_id);
+    }
+    let added = new_offer.add(
+        currency.unwrap_or(0),
+        packs.unwrap_or(0),
+        gold_packs.unwrap_or(0),
+    );
+    let Some(new_offer) = added else {
+        let message = format!(
+            "An offer can hold at most {} :coin: and {} of each pack.",
+            MAX_TRADE_CURRENCY, MAX_TRADE_PACKS
+        );
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+            .await?;
+        return Ok(());
+    };
+
+    // ownership is checked again when the trade is executed, this just catches mistakes early
+    let account = ctx.data().accounts.get_account(ctx.author().id).await?;
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    if !new_offer.is_covered_by(&account, &owned_waifus) {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("You don't have enough to offer this.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let updated = ctx
+        .data()
+        .trade_book
+        .update_offer(ctx.author().id, |offer| *offer = new_offer)
+        .await;
+    match updated {
+        Some(trade) => send_trade(ctx, &trade).await?,
+        None => {
+            ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Take something back out of your side of the trade
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_waifu_name"]
+    #[description = "An offered waifu to take back"]
+    waifu: Option<i64>,
+    #[description = "How much offered currency to take back"]
+    #[min = 1]
+    #[max = 1000000]
+    currency: Option<i32>,
+    #[description = "How many offered packs to take back"]
+    #[min = 1]
+    #[max = 1000]
+    packs: Option<i16>,
+    #[description = "How many offered gold packs to take back"]
+    #[min = 1]
+    #[max = 1000]
+    gold_packs: Option<i16>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(trade) = ctx.data().trade_book.get(ctx.author().id).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+            .await?;
+        return Ok(());
+    };
+    if waifu.is_none() && currency.is_none() && packs.is_none() && gold_packs.is_none() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Choose something to take out of the trade.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let removed = trade.offer(ctx.author().id).remove(
+        waifu,
+        currency.unwrap_or(0),
+        packs.unwrap_or(0),
+        gold_packs.unwrap_or(0),
+    );
+    let Some(new_offer) = removed else {
+        ctx.send(|cr| {
+            cr.embed(|ce| fmt::error("Your offer doesn't hold this waifu or this much.", ce))
+        })
+        .await?;
+        return Ok(());
+    };
+
+    let updated = ctx
+        .data()
+        .trade_book
+        .update_offer(ctx.author().id, |offer| *offer = new_offer)
+        .await;
+    match updated {
+        Some(trade) => send_trade(ctx, &trade).await?,
+        None => {
+            ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// View the trade you're in
+#[poise::command(slash_command)]
+pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    if let Some(trade) = ctx.data().trade_book.get(ctx.author().id).await {
+        send_trade(ctx, &trade).await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Accept the trade as it currently stands
+#[poise::command(slash_command)]
+pub async fn confirm(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Some(trade) = ctx.data().trade_book.get(ctx.author().id).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+            .await?;
+        return Ok(());
+    };
+    if trade.initiator_offer.is_empty() && trade.partner_offer.is_empty() {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Nothing has been offered yet.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    send_trade(ctx, &trade).await?;
+    let confirmed = ConfirmMenu::start(
+        ctx,
+        ctx.author().id,
+        "Are you sure you want to accept this trade? Once both sides confirm, it **cannot** be undone.",
+    )
+    .await?;
+    if !confirmed {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("Trade not confirmed.", ce)))
+            .await?;
+        return Ok(());
+    }
+
+    match ctx
+        .data()
+        .trade_book
+        .confirm(ctx.author().id, trade.revision)
+        .await
+    {
+        TradeConfirmation::Pending => {
+            let message = format!(
+                "You accepted the trade. Waiting for <@{}> to confirm.",
+                trade.other(ctx.author().id).0
+            );
+            ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+                .await?;
+        }
+        TradeConfirmation::Outdated => {
+            ctx.send(|cr| cr.embed(|ce| fmt::error("The trade changed while you were confirming it. Check it again with `/trade view`", ce)))
+                .await?;
+        }
+        TradeConfirmation::Ready(trade) => {
+            let action = trade.to_ledger_action(&ctx.command().qualified_name);
+            let applied = match action {
+                Some(action) => {
+                    let result = ctx.data().accounts.apply_ledger(&action).await;
+                    // the partner's inventory changed too, not only the author's
+                    ctx.data()
+                        .check_cache
+                        .invalidate_owned_waifus(&action.inventory_owners())
+                        .await;
+                    match result {
+                        Ok(()) => true,
+                        // someone spent or traded away what they offered in the meantime
+                        Err(e) if e.is::<LedgerError>() => false,
+                        Err(e) => return Err(e),
+                    }
+                }
+                None => false,
+            };
+            if applied {
+                ctx.send(|cr| {
+                    cr.embed(|ce| {
+                        fmt::success(
+                            "Trade completed! Check your new waifus with `/account waifus`",
+                            ce,
+                        )
+                    })
+                })
+                .await?;
+                for user_id in [trade.initiator, trade.partner] {
+                    achievements::award(ctx, user_id, Achievement::FirstTrade).await?;
+                }
+            } else {
+                ctx.send(|cr| cr.embed(|ce| fmt::error("The trade failed because someone no longer has what they offered. Nothing was exchanged.", ce)))
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancel the trade you're in
+#[poise::command(slash_command)]
+pub async fn cancel(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    if ctx
+        .data()
+        .trade_book
+        .remove(ctx.author().id)
+        .await
+        .is_some()
+    {
+        ctx.send(|cr| cr.embed(|ce| fmt::success("Trade cancelled.", ce)))
+            .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error(NOT_TRADING_MESSAGE, ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn describe_offer(offer: &TradeOffer, waifus: &[InventoryWaifu]) -> String {
+    let mut lines = vec![];
+    for instance_id in offer.waifus.iter() {
+        let name = waifus
+            .iter()
+            .find(|w| w.owned.instance_id == *instance_id)
+            .map(|w| w.waifu.name.as_str())
+            .unwrap_or("Unknown waifu");
+        lines.push(format!("**`{name}`**"));
+    }
+    if offer.currency > 0 {
+        lines.push(format!(":coin: {}", offer.currency));
+    }
+    if offer.packs > 0 {
+        lines.push(format!("{} Packs", offer.packs));
+    }
+    if offer.premium_one_packs > 0 {
+        lines.push(format!("{} Gold Packs", offer.premium_one_packs));
+    }
+    if lines.is_empty() {
+        lines.push(String::from("Nothing yet"));
+    }
+    if offer.confirmed {
+        lines.push(String::from(":white_check_mark: Confirmed"));
+    }
+
+    lines.join("\n")
+}
+
+async fn send_trade(ctx: Context<'_>, trade: &Trade) -> Result<(), Error> {
+    let mut offered_waifus = vec![];
+    for user_id in [trade.initiator, trade.partner] {
+        let owned_waifus = ctx.data().accounts.get_waifus(user_id).await?;
+        offered_waifus.extend(owned_waifus.into_iter().filter(|w| {
+            trade.initiator_offer.waifus.contains(&w.instance_id)
+                || trade.partner_offer.waifus.contains(&w.instance_id)
+        }));
+    }
+    let waifu_ids = offered_waifus.iter().map(|w| w.waifu_id as i32).collect();
+    let catalog_waifus = ctx.data().catalog.get_waifus(waifu_ids).await?;
+    let waifus = InventoryWaifu::join(offered_waifus, &catalog_waifus);
+    let initiator = trade.initiator.to_user(ctx).await?;
+    let partner = trade.partner.to_user(ctx).await?;
+    let description = format!(
+        "Both sides must accept with `/trade confirm`. Changing an offer withdraws every confirmation.\nThe trade is cancelled <t:{}:R> unless someone changes or confirms it.",
+        trade.expires_at.timestamp()
+    );
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title("Trade")
+                .description(description)
+                .field(
+                    format!("{}'s offer", initiator.name),
+                    describe_offer(&trade.initiator_offer, &waifus),
+                    true,
+                )
+                .field(
+                    format!("{}'s offer", partner.name),
+                    describe_offer(&trade.partner_offer, &waifus),
+                    true,
+                )
+                .colour(serenity::Colour::FABLED_PINK)
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [trade()]
+}
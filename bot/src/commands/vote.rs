@@ -0,0 +1,93 @@
+use crate::{
+    models::vote::{Vote, VoteTotals},
+    utils::fmt,
+    Context, Error,
+};
+
+use super::autocomplete::autocomplete_catalog_waifu;
+
+/// Tell everyone what you think of a waifu
+#[poise::command(
+    slash_command,
+    subcommands("like", "trash"),
+    check = "crate::checks::has_account"
+)]
+pub async fn vote(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Like a waifu
+#[poise::command(slash_command)]
+pub async fn like(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_catalog_waifu"]
+    #[description = "The waifu to like"]
+    waifu: i32,
+) -> Result<(), Error> {
+    cast_vote(ctx, waifu, Vote::Like).await
+}
+
+/// Trash a waifu
+#[poise::command(slash_command)]
+pub async fn trash(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_catalog_waifu"]
+    #[description = "The waifu to trash"]
+    waifu: i32,
+) -> Result<(), Error> {
+    cast_vote(ctx, waifu, Vote::Trash).await
+}
+
+async fn cast_vote(ctx: Context<'_>, waifu_id: i32, vote: Vote) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Ok(waifu) = ctx.data().catalog.get_waifu(waifu_id).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu doesn't exist.", ce)))
+            .await?;
+        return Ok(());
+    };
+
+    let previous = ctx
+        .data()
+        .votes
+        .cast_vote(ctx.author().id, i16::try_from(waifu._id)?, vote)
+        .await?;
+    if previous == Some(vote) {
+        let message = format!("You already {} **`{}`**.", vote.verb(), waifu.name);
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let totals = ctx
+        .data()
+        .catalog
+        .add_votes(waifu_id, VoteTotals::change(vote, previous))
+        .await?;
+
+    let message = match previous {
+        Some(previous) => format!(
+            "Changed your vote on **`{}`** from {} to {}. It now has {} likes and {} trash.",
+            waifu.name,
+            previous.as_str(),
+            vote.as_str(),
+            totals.likes,
+            totals.trash
+        ),
+        None => format!(
+            "You {} **`{}`**. It now has {} likes and {} trash.",
+            vote.verb(),
+            waifu.name,
+            totals.likes,
+            totals.trash
+        ),
+    };
+    ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+        .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [vote()]
+}
@@ -0,0 +1,65 @@
+use super::{autocomplete::autocomplete_catalog_waifu, interactions::current_price};
+use crate::{
+    models::price::price_trend,
+    utils::{fmt, ToEmbed},
+    Context, Error,
+};
+
+/// Look up waifus in the catalog
+#[poise::command(slash_command, subcommands("info"))]
+pub async fn waifu(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Everything there is to know about a waifu
+#[poise::command(slash_command)]
+pub async fn info(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_catalog_waifu"]
+    #[description = "The waifu to look up"]
+    waifu: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Ok(waifu) = ctx.data().catalog.get_waifu(waifu).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu doesn't exist.", ce)))
+            .await?;
+        return Ok(());
+    };
+    let waifu_id = waifu._id as i16;
+
+    let price = current_price(ctx, &waifu).await?;
+    let history = ctx.data().prices.get_price_history(waifu_id, 2).await?;
+    let trend = match price_trend(&history) {
+        Some(change) if change > 0 => format!("📈 +{} :coin:", change),
+        Some(change) if change < 0 => format!("📉 {} :coin:", change),
+        Some(_) => String::from("Steady"),
+        None => String::from("Not enough history yet"),
+    };
+    let owners = ctx.data().accounts.count_owners(waifu_id).await?;
+    let owned_waifus = ctx.data().accounts.get_waifus(ctx.author().id).await?;
+    let owned = owned_waifus.iter().any(|w| w.waifu_id == waifu_id);
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            waifu
+                .to_embed(ce)
+                .field("Sell Price", format!("{} :coin:", price), true)
+                .field("Trend", &trend, true)
+                .field(
+                    "Votes",
+                    format!("👍 {} / 🗑️ {}", waifu.likes, waifu.trash),
+                    true,
+                )
+                .field("Owners", format!("{} players", owners), true)
+                .field("You", if owned { "Owned" } else { "Not owned" }, true)
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [waifu()]
+}
@@ -0,0 +1,176 @@
+use poise::serenity_prelude as serenity;
+
+use super::autocomplete::autocomplete_catalog_waifu;
+use crate::{models::catalog_index::match_rank, utils::fmt, Context, Error};
+
+/// How many waifus fit on a wishlist
+const WISHLIST_SIZE: usize = 25;
+
+/// Keep track of the waifus you're chasing
+#[poise::command(
+    slash_command,
+    subcommands("add", "remove", "view"),
+    check = "crate::checks::has_account"
+)]
+pub async fn wishlist(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn autocomplete_wishlisted<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = poise::AutocompleteChoice<i32>> {
+    let wishlist = ctx
+        .data()
+        .wishlists
+        .get_wishlist(ctx.author().id)
+        .await
+        .unwrap_or(vec![]);
+    let mut waifus = ctx.data().catalog_index.names(&wishlist).await;
+    waifus.retain(|(_, name)| match_rank(name, partial).is_some());
+    waifus.sort_by_key(|(_, name)| match_rank(name, partial));
+
+    waifus
+        .into_iter()
+        .map(|(waifu_id, name)| poise::AutocompleteChoice {
+            name,
+            value: waifu_id as i32,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Add a waifu to your wishlist
+#[poise::command(slash_command)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_catalog_waifu"]
+    #[description = "The waifu you want"]
+    waifu: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let Ok(waifu) = ctx.data().catalog.get_waifu(waifu).await else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu doesn't exist.", ce)))
+            .await?;
+        return Ok(());
+    };
+    let wishlist = ctx.data().wishlists.get_wishlist(ctx.author().id).await?;
+    if wishlist.len() >= WISHLIST_SIZE {
+        let message = format!(
+            "Your wishlist is full, it can hold {} waifus. Make some room with `/wishlist remove`",
+            WISHLIST_SIZE
+        );
+        ctx.send(|cr| cr.embed(|ce| fmt::error(&message, ce)))
+            .await?;
+        return Ok(());
+    }
+
+    let added = ctx
+        .data()
+        .wishlists
+        .add_to_wishlist(ctx.author().id, waifu._id as i16)
+        .await?;
+    if added {
+        let message = format!(
+            "Added **`{}`** to your wishlist. It will show up a little more often in your packs!",
+            waifu.name
+        );
+        ctx.send(|cr| cr.embed(|ce| fmt::success(&message, ce)))
+            .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu is already on your wishlist.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Remove a waifu from your wishlist
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_wishlisted"]
+    #[description = "The waifu to remove"]
+    waifu: i32,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let removed = ctx
+        .data()
+        .wishlists
+        .remove_from_wishlist(ctx.author().id, waifu as i16)
+        .await?;
+    if removed {
+        ctx.send(|cr| cr.embed(|ce| fmt::success("Removed from your wishlist.", ce)))
+            .await?;
+    } else {
+        ctx.send(|cr| cr.embed(|ce| fmt::error("This waifu isn't on your wishlist.", ce)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// View your wishlist, or someone else's
+#[poise::command(slash_command)]
+pub async fn view(
+    ctx: Context<'_>,
+    #[description = "Whose wishlist to view"] member: Option<serenity::User>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let user = member.as_ref().unwrap_or(ctx.author());
+    let wishlist = ctx.data().wishlists.get_wishlist(user.id).await?;
+    if wishlist.is_empty() {
+        ctx.send(|cr| {
+            cr.embed(|ce| {
+                fmt::error(
+                    "This wishlist is empty. Add waifus with `/wishlist add`",
+                    ce,
+                )
+            })
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let waifus = ctx
+        .data()
+        .catalog
+        .get_waifus(wishlist.iter().map(|id| *id as i32).collect())
+        .await?;
+    let counts = ctx.data().wishlists.get_wishlist_counts(&wishlist).await?;
+    let lines: Vec<String> = wishlist
+        .iter()
+        .filter_map(|id| waifus.iter().find(|w| w._id as i16 == *id))
+        .map(|w| {
+            let count = counts
+                .iter()
+                .find(|(waifu_id, _)| *waifu_id == w._id as i16)
+                .map_or(0, |(_, count)| *count);
+            format!(
+                "{} **`{}`** - wishlisted by {} players",
+                w.rarity.icon(),
+                w.name,
+                count
+            )
+        })
+        .collect();
+
+    ctx.send(|cr| {
+        cr.embed(|ce| {
+            ce.title(format!("{}'s Wishlist", user.name))
+                .description(lines.join("\n"))
+                .footer(|cf| cf.text(format!("{}/{} waifus", wishlist.len(), WISHLIST_SIZE)))
+                .colour(serenity::Colour::GOLD)
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub fn commands() -> [crate::Command; 1] {
+    [wishlist()]
+}
@@ -2,17 +2,37 @@ use std::fs;
 
 use serde::Deserialize;
 
+use crate::models::{
+    pity::Pity,
+    waifu::{DropTable, Rarity},
+};
+
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub discord: Discord,
     pub postgres: Postgres,
     pub mongo: Mongo,
     pub stripe: Stripe,
+    #[serde(default)]
+    pub packs: Packs,
+    #[serde(default)]
+    pub pity: PityConfig,
+    #[serde(default)]
+    pub market: Market,
+    #[serde(default)]
+    pub pricing: Pricing,
 }
 impl Config {
     pub fn read() -> Self {
         let contents = fs::read_to_string("config.toml").expect("Cannot read configuration file");
-        toml::from_str(&contents).expect("Cannot parse configuration file")
+        let config: Self = toml::from_str(&contents).expect("Cannot parse configuration file");
+        // a zero period would panic the recompute timer at startup instead
+        assert!(
+            config.pricing.recompute_minutes > 0,
+            "pricing.recompute_minutes must be at least 1"
+        );
+
+        config
     }
 }
 
@@ -44,3 +64,84 @@ impl Stripe {
         format!("{}{path}", self.cloudflare_hook_base)
     }
 }
+
+/// Drop tables for each pack type, configured under `[packs.standard]` and `[packs.gold]`
+#[derive(Clone, Deserialize)]
+pub struct Packs {
+    pub standard: DropTable,
+    pub gold: DropTable,
+}
+impl Default for Packs {
+    fn default() -> Self {
+        Self {
+            standard: DropTable {
+                common: 60,
+                uncommon: 25,
+                rare: 10,
+                epic: 4,
+                legendary: 1,
+            },
+            gold: DropTable {
+                common: 35,
+                uncommon: 30,
+                rare: 20,
+                epic: 10,
+                legendary: 5,
+            },
+        }
+    }
+}
+
+/// Pity for each pack type, configured under `[pity.standard]` and `[pity.gold]`
+#[derive(Clone, Deserialize)]
+pub struct PityConfig {
+    pub standard: Pity,
+    pub gold: Pity,
+}
+impl Default for PityConfig {
+    fn default() -> Self {
+        Self {
+            standard: Pity {
+                threshold: 20,
+                rarities: vec![Rarity::Epic, Rarity::Legendary],
+            },
+            gold: Pity {
+                threshold: 10,
+                rarities: vec![Rarity::Legendary],
+            },
+        }
+    }
+}
+
+/// Marketplace settings, configured under `[market]`
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Market {
+    /// Percentage of the price charged when listing a waifu, `0` disables the fee
+    pub listing_fee_percent: i32,
+    /// How long a listing stays up before the waifu goes back to its seller
+    pub listing_hours: i64,
+}
+impl Default for Market {
+    fn default() -> Self {
+        Self {
+            listing_fee_percent: 5,
+            listing_hours: 72,
+        }
+    }
+}
+
+/// Pricing engine settings, configured under `[pricing]`
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Pricing {
+    /// How often waifu prices are recomputed from votes and copies owned, in minutes and at least 1
+    pub recompute_minutes: u64,
+}
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            recompute_minutes: 30,
+        }
+    }
+}
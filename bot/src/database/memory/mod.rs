@@ -0,0 +1,1932 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use rand::{seq::SliceRandom, thread_rng};
+use sqlx::types::chrono::{DateTime, Utc};
+use tokio::sync::Mutex as TokioMutex;
+
+use crate::{
+    database::{
+        AccountStore, AchievementStore, AllianceStore, BannerStore, BattleStore, LeaderboardStore,
+        MarketStore, PityStore, PriceStore, ProductStore, RewardStore, TreasuryStore, VoteStore,
+        WaifuCatalog, WishlistStore,
+    },
+    models::{
+        account::{Account, PremiumProduct},
+        achievement::{CollectionSet, UnlockedAchievement},
+        alliance::{Alliance, AllianceMember, AllianceRole, AllianceUpgrade, TreasuryMovement},
+        banner::Banner,
+        battle::{AllianceBattle, BattleOutcome},
+        leaderboard::{LeaderboardCategory, LeaderboardEntry},
+        ledger::{LedgerAction, LedgerChange, LedgerEntry, LedgerError, LedgerKind},
+        market::{ListingFilter, ListingUnavailable, MarketListing},
+        price::WaifuPrice,
+        reward::{AlreadyClaimed, RewardClaim, RewardKind},
+        vote::{Vote, VoteTotals},
+        waifu::{rank_search_results, roll_wishlisted, DropTable, OwnedWaifu, Rarity, Waifu},
+    },
+};
+
+/// Returned by the in-memory backend where Postgres or Mongo would fail to find a row
+#[derive(Debug)]
+pub struct NotFound(pub &'static str);
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} not found", self.0)
+    }
+}
+impl std::error::Error for NotFound {}
+
+/// Returned by the in-memory backend where Postgres would violate a unique constraint
+#[derive(Debug)]
+pub struct AlreadyExists(pub &'static str);
+impl fmt::Display for AlreadyExists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} already exists", self.0)
+    }
+}
+impl std::error::Error for AlreadyExists {}
+
+/// A row of `alliances`, memberships are kept separately like in `alliance_members`
+#[derive(Clone)]
+struct MemoryAlliance {
+    alliance_id: i32,
+    name: String,
+    treasury: i32,
+    experience: i32,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    accounts: HashMap<u64, Account>,
+    owned_waifus: Vec<OwnedWaifu>,
+    next_instance_id: i64,
+    alliances: Vec<MemoryAlliance>,
+    next_alliance_id: i32,
+    alliance_members: Vec<AllianceMember>,
+    /// Every treasury movement of every alliance, oldest first
+    treasury_movements: Vec<TreasuryMovement>,
+    /// Keyed by alliance id and upgrade
+    alliance_upgrades: HashMap<(i32, AllianceUpgrade), i16>,
+    alliance_battles: Vec<AllianceBattle>,
+    next_battle_id: i64,
+    products: HashMap<String, PremiumProduct>,
+    reward_claims: HashMap<(u64, RewardKind), RewardClaim>,
+    /// (guild id, user id) pairs
+    guild_members: HashSet<(u64, u64)>,
+    market_listings: Vec<MarketListing>,
+    next_listing_id: i64,
+    /// Keyed by user id and pack type
+    pity_counters: HashMap<(u64, String), i32>,
+    banners: Vec<Banner>,
+    /// (user id, waifu id) pairs, oldest first
+    wishlists: Vec<(u64, i16)>,
+    /// Keyed by user id and waifu id
+    votes: HashMap<(u64, i16), Vote>,
+    /// Every recorded price, oldest first
+    waifu_prices: Vec<WaifuPrice>,
+    /// Unlocked achievements of every user, oldest first
+    achievements: Vec<UnlockedAchievement>,
+    collection_sets: Vec<CollectionSet>,
+    /// Every entry written by `apply_ledger`, oldest first, like the append-only `ledger_entries`
+    ledger_entries: Vec<LedgerEntry>,
+    next_entry_id: i64,
+    next_transaction_id: i64,
+}
+
+impl MemoryState {
+    /// Gathers the alliance's owner and members the way `ALLIANCE_SELECT` does in Postgres
+    fn alliance(&self, alliance_id: i32) -> Option<Alliance> {
+        let alliance = self
+            .alliances
+            .iter()
+            .find(|a| a.alliance_id == alliance_id)?;
+        let mut members: Vec<&AllianceMember> = self
+            .alliance_members
+            .iter()
+            .filter(|m| m.alliance_id == alliance_id)
+            .collect();
+        members.sort_by_key(|m| (m.joined_at, m.user_id));
+        let owner = members
+            .iter()
+            .find(|m| m.role() == AllianceRole::Owner)?
+            .user_id;
+
+        Some(Alliance {
+            alliance_id,
+            name: alliance.name.clone(),
+            owner,
+            members: members
+                .iter()
+                .filter(|m| m.role() != AllianceRole::Owner)
+                .map(|m| m.user_id)
+                .collect(),
+            treasury: alliance.treasury,
+            experience: alliance.experience,
+        })
+    }
+    fn membership(&self, user_id: serenity::UserId) -> Option<&AllianceMember> {
+        self.alliance_members
+            .iter()
+            .find(|m| m.user_id == user_id.0 as i64)
+    }
+    /// Deletes the alliance and everything belonging to it, like the `ON DELETE CASCADE` keys in Postgres
+    fn remove_alliance(&mut self, alliance_id: i32) {
+        self.alliances.retain(|a| a.alliance_id != alliance_id);
+        self.alliance_members
+            .retain(|m| m.alliance_id != alliance_id);
+        self.treasury_movements
+            .retain(|m| m.alliance_id != alliance_id);
+        self.alliance_upgrades
+            .retain(|(upgrade_alliance_id, _), _| *upgrade_alliance_id != alliance_id);
+        self.alliance_battles
+            .retain(|b| b.challenger_id != alliance_id && b.defender_id != alliance_id);
+    }
+    /// Takes the user out of their alliance whatever their role, like `leave_alliance_for_good` in Postgres
+    fn leave_alliance_for_good(&mut self, user_id: serenity::UserId) {
+        let Some(membership) = self.membership(user_id).cloned() else {
+            return;
+        };
+        self.alliance_members
+            .retain(|m| m.user_id != membership.user_id);
+        if membership.role() != AllianceRole::Owner {
+            return;
+        }
+        let successor = self
+            .alliance_members
+            .iter_mut()
+            .filter(|m| m.alliance_id == membership.alliance_id)
+            .min_by_key(|m| (m.role() != AllianceRole::Officer, m.joined_at, m.user_id));
+        match successor {
+            Some(successor) => successor.role = AllianceRole::Owner.as_str().into(),
+            None => self.remove_alliance(membership.alliance_id),
+        }
+    }
+    /// Whether an active market listing holds the waifu
+    fn in_escrow(&self, instance_id: i64) -> bool {
+        let now = Utc::now();
+        self.market_listings
+            .iter()
+            .any(|l| l.instance_id == instance_id && l.expires_at > now)
+    }
+    /// Changes are applied to copies, which only replace the real state once
+    /// every change succeeded
+    fn apply_ledger(&mut self, action: &LedgerAction) -> Result<(), crate::Error> {
+        let mut accounts = self.accounts.clone();
+        let mut owned_waifus = self.owned_waifus.clone();
+        let mut next_instance_id = self.next_instance_id;
+        let mut alliances = self.alliances.clone();
+        let mut treasury_movements = self.treasury_movements.clone();
+        let mut entries = vec![];
+        for change in action.changes.iter() {
+            match change {
+                LedgerChange::Balance {
+                    user_id,
+                    kind,
+                    delta,
+                } => {
+                    let applied = match accounts.get_mut(&user_id.0) {
+                        Some(account) => match kind {
+                            LedgerKind::Currency => add_checked(&mut account.currency, *delta),
+                            LedgerKind::PremiumCurrency => {
+                                add_checked(&mut account.premium_currency, *delta)
+                            }
+                            LedgerKind::Packs => add_checked_i16(&mut account.packs, *delta),
+                            LedgerKind::PremiumOnePacks => {
+                                add_checked_i16(&mut account.premium_one_packs, *delta)
+                            }
+                            LedgerKind::Experience => {
+                                account.experience += *delta;
+                                true
+                            }
+                            LedgerKind::Waifu
+                            | LedgerKind::WaifuExperience
+                            | LedgerKind::Treasury
+                            | LedgerKind::AllianceExperience => false,
+                        },
+                        None => false,
+                    };
+                    if !applied {
+                        return Err(LedgerError {
+                            user_id: *user_id,
+                            kind: *kind,
+                        }
+                        .into());
+                    }
+                    entries.push(ledger_entry(action, *user_id, *kind, *delta, None, None));
+                }
+                LedgerChange::AddWaifu { user_id, waifu_id } => {
+                    if !accounts.contains_key(&user_id.0) {
+                        return Err(LedgerError {
+                            user_id: *user_id,
+                            kind: LedgerKind::Waifu,
+                        }
+                        .into());
+                    }
+                    next_instance_id += 1;
+                    owned_waifus.push(OwnedWaifu {
+                        instance_id: next_instance_id,
+                        waifu_id: *waifu_id as i16,
+                        owner_id: user_id.0 as i64,
+                        acquired_at: Utc::now(),
+                        source: action.reason.as_str().into(),
+                        experience: 0,
+                    });
+                    entries.push(ledger_entry(
+                        action,
+                        *user_id,
+                        LedgerKind::Waifu,
+                        1,
+                        Some(*waifu_id as i16),
+                        Some(next_instance_id),
+                    ));
+                }
+                LedgerChange::RemoveWaifu {
+                    user_id,
+                    instance_id,
+                } => {
+                    let position = owned_waifus.iter().position(|w| {
+                        w.instance_id == *instance_id
+                            && w.owner_id == user_id.0 as i64
+                            && !self.in_escrow(w.instance_id)
+                    });
+                    match position {
+                        Some(position) => {
+                            let removed = owned_waifus.remove(position);
+                            entries.push(ledger_entry(
+                                action,
+                                *user_id,
+                                LedgerKind::Waifu,
+                                -1,
+                                Some(removed.waifu_id),
+                                Some(*instance_id),
+                            ));
+                        }
+                        None => {
+                            return Err(LedgerError {
+                                user_id: *user_id,
+                                kind: LedgerKind::Waifu,
+                            }
+                            .into())
+                        }
+                    }
+                }
+                LedgerChange::WaifuExperience {
+                    user_id,
+                    instance_id,
+                    amount,
+                } => {
+                    let owned = owned_waifus.iter_mut().find(|w| {
+                        w.instance_id == *instance_id
+                            && w.owner_id == user_id.0 as i64
+                            && !self.in_escrow(w.instance_id)
+                    });
+                    match owned {
+                        Some(owned) => {
+                            owned.experience += *amount;
+                            entries.push(ledger_entry(
+                                action,
+                                *user_id,
+                                LedgerKind::WaifuExperience,
+                                *amount,
+                                Some(owned.waifu_id),
+                                Some(*instance_id),
+                            ));
+                        }
+                        None => {
+                            return Err(LedgerError {
+                                user_id: *user_id,
+                                kind: LedgerKind::WaifuExperience,
+                            }
+                            .into())
+                        }
+                    }
+                }
+                LedgerChange::TransferWaifu {
+                    from,
+                    to,
+                    instance_id,
+                } => {
+                    let owned = owned_waifus.iter_mut().find(|w| {
+                        w.instance_id == *instance_id
+                            && w.owner_id == from.0 as i64
+                            && !self.in_escrow(w.instance_id)
+                    });
+                    match owned {
+                        Some(owned) if accounts.contains_key(&to.0) => {
+                            owned.owner_id = to.0 as i64;
+                            owned.acquired_at = Utc::now();
+                            owned.source = action.reason.as_str().into();
+                            for (user_id, delta) in [(*from, -1), (*to, 1)] {
+                                entries.push(ledger_entry(
+                                    action,
+                                    user_id,
+                                    LedgerKind::Waifu,
+                                    delta,
+                                    Some(owned.waifu_id),
+                                    Some(*instance_id),
+                                ));
+                            }
+                        }
+                        _ => {
+                            return Err(LedgerError {
+                                user_id: *from,
+                                kind: LedgerKind::Waifu,
+                            }
+                            .into())
+                        }
+                    }
+                }
+                LedgerChange::Treasury {
+                    user_id,
+                    alliance_id,
+                    delta,
+                } => {
+                    let applied = match alliances.iter_mut().find(|a| a.alliance_id == *alliance_id)
+                    {
+                        Some(alliance) => add_checked(&mut alliance.treasury, *delta),
+                        None => false,
+                    };
+                    if !applied {
+                        return Err(LedgerError {
+                            user_id: *user_id,
+                            kind: LedgerKind::Treasury,
+                        }
+                        .into());
+                    }
+                    treasury_movements.push(TreasuryMovement {
+                        alliance_id: *alliance_id,
+                        user_id: user_id.0 as i64,
+                        delta: *delta,
+                        reason: action.reason.as_str().into(),
+                        created_at: Utc::now(),
+                    });
+                    entries.push(ledger_entry(
+                        action,
+                        *user_id,
+                        LedgerKind::Treasury,
+                        *delta,
+                        None,
+                        None,
+                    ));
+                }
+                LedgerChange::AllianceExperience { user_id, amount } => {
+                    let alliance_id = self.membership(*user_id).map(|m| m.alliance_id);
+                    if let Some(alliance) = alliances
+                        .iter_mut()
+                        .find(|a| Some(a.alliance_id) == alliance_id)
+                    {
+                        alliance.experience += *amount;
+                        entries.push(ledger_entry(
+                            action,
+                            *user_id,
+                            LedgerKind::AllianceExperience,
+                            *amount,
+                            None,
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+
+        self.accounts = accounts;
+        self.owned_waifus = owned_waifus;
+        self.next_instance_id = next_instance_id;
+        self.alliances = alliances;
+        self.treasury_movements = treasury_movements;
+        self.next_transaction_id += 1;
+        for mut entry in entries {
+            self.next_entry_id += 1;
+            entry.entry_id = self.next_entry_id;
+            entry.transaction_id = self.next_transaction_id;
+            self.ledger_entries.push(entry);
+        }
+
+        Ok(())
+    }
+}
+
+/// The `ledger_entries` row for one change of the action, numbered once the whole action applied
+fn ledger_entry(
+    action: &LedgerAction,
+    user_id: serenity::UserId,
+    kind: LedgerKind,
+    delta: i32,
+    waifu_id: Option<i16>,
+    instance_id: Option<i64>,
+) -> LedgerEntry {
+    LedgerEntry {
+        entry_id: 0,
+        transaction_id: 0,
+        user_id: user_id.0 as i64,
+        kind: kind.as_str().into(),
+        delta,
+        waifu_id,
+        instance_id,
+        reason: action.reason.as_str().into(),
+        command: action.command.clone(),
+        created_at: Utc::now(),
+    }
+}
+
+/// Keeps accounts, alliances, products and reward claims in memory, mirroring the Postgres backend.
+/// Nothing is persisted, so this is only meant for tests and local development.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: TokioMutex<MemoryState>,
+}
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Test fixtures. No store method adds products, banners or collection sets,
+/// Postgres gets them straight from their tables.
+#[cfg(test)]
+impl MemoryStore {
+    pub fn with_products(mut self, products: Vec<PremiumProduct>) -> Self {
+        let state = self.state.get_mut();
+        for product in products {
+            state.products.insert(product.product_id.clone(), product);
+        }
+        self
+    }
+    pub fn with_banners(mut self, banners: Vec<Banner>) -> Self {
+        self.state.get_mut().banners.extend(banners);
+        self
+    }
+    pub fn with_collection_sets(mut self, collection_sets: Vec<CollectionSet>) -> Self {
+        self.state.get_mut().collection_sets.extend(collection_sets);
+        self
+    }
+    /// Every ledger entry written for the user, oldest first
+    pub async fn ledger_entries(&self, user_id: serenity::UserId) -> Vec<LedgerEntry> {
+        let guard = self.state.lock().await;
+        guard
+            .ledger_entries
+            .iter()
+            .filter(|e| e.user_id == user_id.0 as i64)
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl AccountStore for MemoryStore {
+    async fn register_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        if guard.accounts.contains_key(&user_id.0) {
+            return Err(AlreadyExists("account").into());
+        }
+        // same defaults as the accounts table
+        let account = Account {
+            user_id: user_id.0 as i64,
+            currency: 500,
+            premium_currency: 0,
+            packs: 3,
+            premium_one_packs: 0,
+            experience: 0,
+        };
+        guard.accounts.insert(user_id.0, account);
+
+        Ok(())
+    }
+    async fn get_account(&self, user_id: serenity::UserId) -> Result<Account, crate::Error> {
+        let guard = self.state.lock().await;
+        let account = guard.accounts.get(&user_id.0).ok_or(NotFound("account"))?;
+
+        Ok(account.clone())
+    }
+    async fn delete_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        guard.leave_alliance_for_good(user_id);
+        guard.accounts.remove(&user_id.0);
+        guard
+            .owned_waifus
+            .retain(|w| w.owner_id != user_id.0 as i64);
+        guard
+            .reward_claims
+            .retain(|(claim_user_id, _), _| *claim_user_id != user_id.0);
+        guard
+            .market_listings
+            .retain(|l| l.seller_id != user_id.0 as i64);
+        guard
+            .pity_counters
+            .retain(|(pity_user_id, _), _| *pity_user_id != user_id.0);
+        guard
+            .wishlists
+            .retain(|(wishlist_user_id, _)| *wishlist_user_id != user_id.0);
+        guard
+            .votes
+            .retain(|(vote_user_id, _), _| *vote_user_id != user_id.0);
+        guard.achievements.retain(|a| a.user_id != user_id.0 as i64);
+
+        Ok(())
+    }
+    async fn apply_ledger(&self, action: &LedgerAction) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        guard.apply_ledger(action)
+    }
+    async fn get_waifus(&self, user_id: serenity::UserId) -> Result<Vec<OwnedWaifu>, crate::Error> {
+        let guard = self.state.lock().await;
+        let waifus = guard
+            .owned_waifus
+            .iter()
+            .filter(|w| w.owner_id == user_id.0 as i64 && !guard.in_escrow(w.instance_id))
+            .cloned()
+            .collect();
+
+        Ok(waifus)
+    }
+    async fn get_accounts(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<Account>, crate::Error> {
+        let guard = self.state.lock().await;
+        let accounts = user_ids
+            .iter()
+            .filter_map(|u| guard.accounts.get(&u.0))
+            .cloned()
+            .collect();
+
+        Ok(accounts)
+    }
+    async fn get_waifus_of(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<OwnedWaifu>, crate::Error> {
+        let guard = self.state.lock().await;
+        let waifus = guard
+            .owned_waifus
+            .iter()
+            .filter(|w| {
+                user_ids.iter().any(|u| w.owner_id == u.0 as i64) && !guard.in_escrow(w.instance_id)
+            })
+            .cloned()
+            .collect();
+
+        Ok(waifus)
+    }
+    async fn count_owners(&self, waifu_id: i16) -> Result<i64, crate::Error> {
+        let guard = self.state.lock().await;
+        let owners: HashSet<i64> = guard
+            .owned_waifus
+            .iter()
+            .filter(|w| w.waifu_id == waifu_id)
+            .map(|w| w.owner_id)
+            .collect();
+
+        Ok(owners.len() as i64)
+    }
+}
+
+fn add_checked(value: &mut i32, delta: i32) -> bool {
+    match value.checked_add(delta) {
+        Some(new_value) if new_value >= 0 => {
+            *value = new_value;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn add_checked_i16(value: &mut i16, delta: i32) -> bool {
+    let mut widened = i32::from(*value);
+    if !add_checked(&mut widened, delta) {
+        return false;
+    }
+    match i16::try_from(widened) {
+        Ok(new_value) => {
+            *value = new_value;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[async_trait]
+impl AchievementStore for MemoryStore {
+    async fn get_achievements(
+        &self,
+        user_id: serenity::UserId,
+    ) -> Result<Vec<UnlockedAchievement>, crate::Error> {
+        let guard = self.state.lock().await;
+        let achievements = guard
+            .achievements
+            .iter()
+            .filter(|a| a.user_id == user_id.0 as i64)
+            .cloned()
+            .collect();
+
+        Ok(achievements)
+    }
+    async fn unlock_achievement(
+        &self,
+        user_id: serenity::UserId,
+        achievement: &str,
+        reward: &LedgerAction,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let unlocked = guard
+            .achievements
+            .iter()
+            .any(|a| a.user_id == user_id.0 as i64 && a.achievement == achievement);
+        if unlocked {
+            return Ok(false);
+        }
+
+        guard.apply_ledger(reward)?;
+        guard.achievements.push(UnlockedAchievement {
+            user_id: user_id.0 as i64,
+            achievement: achievement.into(),
+            unlocked_at: Utc::now(),
+        });
+
+        Ok(true)
+    }
+    async fn get_collection_sets(&self) -> Result<Vec<CollectionSet>, crate::Error> {
+        let guard = self.state.lock().await;
+
+        Ok(guard.collection_sets.clone())
+    }
+}
+
+#[async_trait]
+impl AllianceStore for MemoryStore {
+    async fn get_alliance(&self, user_id: serenity::UserId) -> Result<Alliance, crate::Error> {
+        let guard = self.state.lock().await;
+        let alliance = guard
+            .membership(user_id)
+            .and_then(|m| guard.alliance(m.alliance_id))
+            .ok_or(NotFound("alliance"))?;
+
+        Ok(alliance)
+    }
+    async fn create_alliance(
+        &self,
+        user_id: serenity::UserId,
+        name: &str,
+    ) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        if guard.membership(user_id).is_some() {
+            return Err(AlreadyExists("alliance member").into());
+        }
+        guard.next_alliance_id += 1;
+        let alliance_id = guard.next_alliance_id;
+        guard.alliances.push(MemoryAlliance {
+            alliance_id,
+            name: name.into(),
+            treasury: 0,
+            experience: 0,
+        });
+        guard.alliance_members.push(AllianceMember {
+            user_id: user_id.0 as i64,
+            alliance_id,
+            role: AllianceRole::Owner.as_str().into(),
+            joined_at: Utc::now(),
+        });
+
+        Ok(())
+    }
+    async fn delete_alliance(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        let Some(alliance_id) = guard
+            .membership(user_id)
+            .filter(|m| m.role() == AllianceRole::Owner)
+            .map(|m| m.alliance_id)
+        else {
+            return Ok(());
+        };
+        guard.remove_alliance(alliance_id);
+
+        Ok(())
+    }
+    async fn join_alliance(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+        member_cap: i32,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        if guard.membership(user_id).is_some() {
+            return Err(AlreadyExists("alliance member").into());
+        }
+        if !guard.alliances.iter().any(|a| a.alliance_id == alliance_id) {
+            return Err(NotFound("alliance").into());
+        }
+        let size = guard
+            .alliance_members
+            .iter()
+            .filter(|m| m.alliance_id == alliance_id)
+            .count();
+        if size >= member_cap as usize {
+            return Ok(false);
+        }
+        guard.alliance_members.push(AllianceMember {
+            user_id: user_id.0 as i64,
+            alliance_id,
+            role: AllianceRole::Member.as_str().into(),
+            joined_at: Utc::now(),
+        });
+
+        Ok(true)
+    }
+    async fn leave_alliance(&self, user_id: serenity::UserId) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let before = guard.alliance_members.len();
+        guard
+            .alliance_members
+            .retain(|m| m.user_id != user_id.0 as i64 || m.role() == AllianceRole::Owner);
+
+        Ok(guard.alliance_members.len() < before)
+    }
+    async fn kick_member(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let before = guard.alliance_members.len();
+        guard.alliance_members.retain(|m| {
+            m.alliance_id != alliance_id
+                || m.user_id != user_id.0 as i64
+                || m.role() == AllianceRole::Owner
+        });
+
+        Ok(guard.alliance_members.len() < before)
+    }
+    async fn transfer_alliance(
+        &self,
+        owner: serenity::UserId,
+        new_owner: serenity::UserId,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let Some(alliance_id) = guard
+            .membership(owner)
+            .filter(|m| m.role() == AllianceRole::Owner)
+            .map(|m| m.alliance_id)
+        else {
+            return Ok(false);
+        };
+        let is_member = guard
+            .membership(new_owner)
+            .is_some_and(|m| m.alliance_id == alliance_id);
+        if !is_member {
+            return Ok(false);
+        }
+        for member in guard.alliance_members.iter_mut() {
+            if member.user_id == owner.0 as i64 {
+                member.role = AllianceRole::Officer.as_str().into();
+            } else if member.user_id == new_owner.0 as i64 {
+                member.role = AllianceRole::Owner.as_str().into();
+            }
+        }
+
+        Ok(true)
+    }
+    async fn get_membership(
+        &self,
+        user_id: serenity::UserId,
+    ) -> Result<Option<AllianceMember>, crate::Error> {
+        let guard = self.state.lock().await;
+
+        Ok(guard.membership(user_id).cloned())
+    }
+    async fn get_alliance_members(
+        &self,
+        alliance_id: i32,
+    ) -> Result<Vec<AllianceMember>, crate::Error> {
+        let guard = self.state.lock().await;
+        let mut members: Vec<AllianceMember> = guard
+            .alliance_members
+            .iter()
+            .filter(|m| m.alliance_id == alliance_id)
+            .cloned()
+            .collect();
+        members.sort_by_key(|m| (m.role() != AllianceRole::Owner, m.joined_at, m.user_id));
+
+        Ok(members)
+    }
+    async fn set_member_role(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+        role: AllianceRole,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let Some(member) = guard.alliance_members.iter_mut().find(|m| {
+            m.alliance_id == alliance_id
+                && m.user_id == user_id.0 as i64
+                && m.role() != AllianceRole::Owner
+        }) else {
+            return Ok(false);
+        };
+        member.role = role.as_str().into();
+
+        Ok(true)
+    }
+    async fn rename_alliance(&self, alliance_id: i32, name: &str) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        if let Some(alliance) = guard
+            .alliances
+            .iter_mut()
+            .find(|a| a.alliance_id == alliance_id)
+        {
+            alliance.name = name.into();
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BattleStore for MemoryStore {
+    async fn record_battle(
+        &self,
+        challenger_id: i32,
+        defender_id: i32,
+        seed: u64,
+        wager: i32,
+        outcome: &BattleOutcome,
+        wager_action: &LedgerAction,
+    ) -> Result<AllianceBattle, crate::Error> {
+        let mut guard = self.state.lock().await;
+        guard.apply_ledger(wager_action)?;
+        guard.next_battle_id += 1;
+        let battle = AllianceBattle {
+            battle_id: guard.next_battle_id,
+            challenger_id,
+            defender_id,
+            winner_id: outcome.winner.pick(challenger_id, defender_id),
+            seed: seed as i64,
+            wager,
+            rounds: outcome.rounds,
+            log: outcome.log.clone(),
+            challenger_roster: outcome.challenger_roster.clone(),
+            defender_roster: outcome.defender_roster.clone(),
+            fought_at: Utc::now(),
+        };
+        guard.alliance_battles.push(battle.clone());
+
+        Ok(battle)
+    }
+}
+
+#[async_trait]
+impl TreasuryStore for MemoryStore {
+    async fn get_treasury_movements(
+        &self,
+        alliance_id: i32,
+        limit: i64,
+    ) -> Result<Vec<TreasuryMovement>, crate::Error> {
+        let guard = self.state.lock().await;
+        let movements = guard
+            .treasury_movements
+            .iter()
+            .rev()
+            .filter(|m| m.alliance_id == alliance_id)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+
+        Ok(movements)
+    }
+    async fn get_alliance_upgrades(
+        &self,
+        alliance_id: i32,
+    ) -> Result<HashMap<AllianceUpgrade, i16>, crate::Error> {
+        let guard = self.state.lock().await;
+        let upgrades = guard
+            .alliance_upgrades
+            .iter()
+            .filter(|((upgrade_alliance_id, _), _)| *upgrade_alliance_id == alliance_id)
+            .map(|((_, upgrade), level)| (*upgrade, *level))
+            .collect();
+
+        Ok(upgrades)
+    }
+    async fn buy_alliance_upgrade(
+        &self,
+        alliance_id: i32,
+        upgrade: AllianceUpgrade,
+        level: i16,
+        payment: &LedgerAction,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let current = guard
+            .alliance_upgrades
+            .get(&(alliance_id, upgrade))
+            .copied()
+            .unwrap_or(0);
+        if current != level {
+            return Ok(false);
+        }
+
+        guard.apply_ledger(payment)?;
+        guard
+            .alliance_upgrades
+            .insert((alliance_id, upgrade), level + 1);
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl RewardStore for MemoryStore {
+    async fn get_reward_claim(
+        &self,
+        user_id: serenity::UserId,
+        kind: RewardKind,
+    ) -> Result<Option<RewardClaim>, crate::Error> {
+        let guard = self.state.lock().await;
+
+        Ok(guard.reward_claims.get(&(user_id.0, kind)).cloned())
+    }
+    async fn claim_reward(
+        &self,
+        user_id: serenity::UserId,
+        kind: RewardKind,
+        previous_claimed_at: Option<DateTime<Utc>>,
+        claimed_at: DateTime<Utc>,
+        streak: i32,
+        action: &LedgerAction,
+    ) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        let current = guard
+            .reward_claims
+            .get(&(user_id.0, kind))
+            .map(|c| c.last_claimed_at);
+        if current != previous_claimed_at {
+            return Err(AlreadyClaimed.into());
+        }
+
+        guard.apply_ledger(action)?;
+        guard.reward_claims.insert(
+            (user_id.0, kind),
+            RewardClaim {
+                user_id: user_id.0 as i64,
+                kind: kind.as_str().into(),
+                last_claimed_at: claimed_at,
+                streak,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BannerStore for MemoryStore {
+    async fn get_banners(&self) -> Result<Vec<Banner>, crate::Error> {
+        let guard = self.state.lock().await;
+        let now = Utc::now();
+        let mut banners: Vec<Banner> = guard
+            .banners
+            .iter()
+            .filter(|b| b.ends_at > now)
+            .cloned()
+            .collect();
+        banners.sort_by_key(|b| (b.starts_at, b.banner_id));
+
+        Ok(banners)
+    }
+    async fn get_banner(&self, banner_id: i32) -> Result<Option<Banner>, crate::Error> {
+        let guard = self.state.lock().await;
+
+        Ok(guard
+            .banners
+            .iter()
+            .find(|b| b.banner_id == banner_id)
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl LeaderboardStore for MemoryStore {
+    async fn record_guild_member(
+        &self,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) -> Result<(), crate::Error> {
+        let mut guard = self.state.lock().await;
+        guard.guild_members.insert((guild_id.0, user_id.0));
+
+        Ok(())
+    }
+    async fn get_leaderboard(
+        &self,
+        category: LeaderboardCategory,
+        guild_id: Option<serenity::GuildId>,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>, crate::Error> {
+        let guard = self.state.lock().await;
+        let in_scope = |user_id: i64| match guild_id {
+            Some(guild_id) => guard.guild_members.contains(&(guild_id.0, user_id as u64)),
+            None => true,
+        };
+        let entry = |user_id: i64, value: i64| LeaderboardEntry {
+            user_id,
+            name: None,
+            value,
+        };
+
+        let mut entries: Vec<LeaderboardEntry> = match category {
+            LeaderboardCategory::Level => guard
+                .accounts
+                .values()
+                .map(|a| entry(a.user_id, a.experience.into()))
+                .collect(),
+            LeaderboardCategory::Currency => guard
+                .accounts
+                .values()
+                .map(|a| entry(a.user_id, a.currency.into()))
+                .collect(),
+            LeaderboardCategory::WaifuCount => {
+                let mut counts: HashMap<i64, i64> = HashMap::new();
+                for owned in guard.owned_waifus.iter() {
+                    *counts.entry(owned.owner_id).or_default() += 1;
+                }
+                counts
+                    .into_iter()
+                    .map(|(user_id, count)| entry(user_id, count))
+                    .collect()
+            }
+            LeaderboardCategory::AllianceSize => guard
+                .alliances
+                .iter()
+                .filter_map(|a| guard.alliance(a.alliance_id))
+                .map(|a| LeaderboardEntry {
+                    user_id: a.owner,
+                    name: Some(a.name),
+                    value: a.members.len() as i64 + 1,
+                })
+                .collect(),
+        };
+        entries.retain(|e| in_scope(e.user_id));
+        entries.sort_by(|a, b| b.value.cmp(&a.value).then(a.user_id.cmp(&b.user_id)));
+        entries.truncate(limit as usize);
+
+        Ok(entries)
+    }
+}
+
+#[async_trait]
+impl MarketStore for MemoryStore {
+    async fn create_listing(
+        &self,
+        seller: serenity::UserId,
+        instance_id: i64,
+        price: i32,
+        expires_at: DateTime<Utc>,
+        fee_action: &LedgerAction,
+    ) -> Result<MarketListing, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let owned = guard
+            .owned_waifus
+            .iter()
+            .find(|w| w.instance_id == instance_id && w.owner_id == seller.0 as i64);
+        let Some(waifu_id) = owned.map(|w| w.waifu_id) else {
+            return Err(LedgerError {
+                user_id: seller,
+                kind: LedgerKind::Waifu,
+            }
+            .into());
+        };
+        if guard.in_escrow(instance_id) {
+            return Err(LedgerError {
+                user_id: seller,
+                kind: LedgerKind::Waifu,
+            }
+            .into());
+        }
+
+        guard.apply_ledger(fee_action)?;
+        guard
+            .market_listings
+            .retain(|l| l.instance_id != instance_id);
+        guard.next_listing_id += 1;
+        let listing = MarketListing {
+            listing_id: guard.next_listing_id,
+            instance_id,
+            seller_id: seller.0 as i64,
+            waifu_id,
+            price,
+            listed_at: Utc::now(),
+            expires_at,
+        };
+        guard.market_listings.push(listing.clone());
+
+        Ok(listing)
+    }
+    async fn get_listings(
+        &self,
+        filter: &ListingFilter,
+        limit: i64,
+    ) -> Result<Vec<MarketListing>, crate::Error> {
+        let guard = self.state.lock().await;
+        let now = Utc::now();
+        // listings are pushed in order, so newest first is the reverse
+        let listings = guard
+            .market_listings
+            .iter()
+            .rev()
+            .filter(|l| l.expires_at > now && filter.matches(l))
+            .take(limit as usize)
+            .cloned()
+            .collect();
+
+        Ok(listings)
+    }
+    async fn get_listing(&self, listing_id: i64) -> Result<Option<MarketListing>, crate::Error> {
+        let guard = self.state.lock().await;
+        let now = Utc::now();
+        let listing = guard
+            .market_listings
+            .iter()
+            .find(|l| l.listing_id == listing_id && l.expires_at > now)
+            .cloned();
+
+        Ok(listing)
+    }
+    async fn buy_listing(
+        &self,
+        listing_id: i64,
+        buyer: serenity::UserId,
+        command: &str,
+    ) -> Result<MarketListing, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let now = Utc::now();
+        let position = guard.market_listings.iter().position(|l| {
+            l.listing_id == listing_id && l.expires_at > now && l.seller_id != buyer.0 as i64
+        });
+        let Some(position) = position else {
+            return Err(ListingUnavailable.into());
+        };
+
+        let listing = guard.market_listings.remove(position);
+        if let Err(e) = guard.apply_ledger(&listing.to_sale_action(buyer, command)) {
+            // put the listing back, like a rolled back transaction would
+            guard.market_listings.insert(position, listing);
+            return Err(e);
+        }
+
+        Ok(listing)
+    }
+    async fn cancel_listing(
+        &self,
+        listing_id: i64,
+        seller: serenity::UserId,
+    ) -> Result<Option<MarketListing>, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let position = guard
+            .market_listings
+            .iter()
+            .position(|l| l.listing_id == listing_id && l.seller_id == seller.0 as i64);
+
+        Ok(position.map(|position| guard.market_listings.remove(position)))
+    }
+    async fn expire_listings(&self) -> Result<u64, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let now = Utc::now();
+        let before = guard.market_listings.len();
+        guard.market_listings.retain(|l| l.expires_at > now);
+
+        Ok((before - guard.market_listings.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl PityStore for MemoryStore {
+    async fn get_pity(&self, user_id: serenity::UserId, pack: &str) -> Result<i32, crate::Error> {
+        let guard = self.state.lock().await;
+        let pulls = guard
+            .pity_counters
+            .get(&(user_id.0, pack.to_string()))
+            .copied();
+
+        Ok(pulls.unwrap_or(0))
+    }
+    async fn record_summon(
+        &self,
+        user_id: serenity::UserId,
+        pack: &str,
+        hit_premium: bool,
+        action: &LedgerAction,
+    ) -> Result<i32, crate::Error> {
+        let mut guard = self.state.lock().await;
+        guard.apply_ledger(action)?;
+        let pulls = guard
+            .pity_counters
+            .entry((user_id.0, pack.to_string()))
+            .or_insert(0);
+        *pulls = if hit_premium { 0 } else { *pulls + 1 };
+
+        Ok(*pulls)
+    }
+}
+
+#[async_trait]
+impl PriceStore for MemoryStore {
+    async fn get_copy_counts(&self) -> Result<Vec<(i16, i64)>, crate::Error> {
+        let guard = self.state.lock().await;
+        let mut counts: HashMap<i16, i64> = HashMap::new();
+        for owned in guard.owned_waifus.iter() {
+            *counts.entry(owned.waifu_id).or_default() += 1;
+        }
+
+        Ok(counts.into_iter().collect())
+    }
+    async fn record_prices(&self, prices: &[(i16, i32)]) -> Result<u64, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let now = Utc::now();
+        let mut changed = 0;
+        for (waifu_id, price) in prices.iter() {
+            let current = guard
+                .waifu_prices
+                .iter()
+                .rev()
+                .find(|p| p.waifu_id == *waifu_id);
+            if current.is_some_and(|p| p.price == *price) {
+                continue;
+            }
+            guard.waifu_prices.push(WaifuPrice {
+                waifu_id: *waifu_id,
+                price: *price,
+                computed_at: now,
+            });
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+    async fn get_price(&self, waifu_id: i16) -> Result<Option<WaifuPrice>, crate::Error> {
+        let guard = self.state.lock().await;
+        let price = guard
+            .waifu_prices
+            .iter()
+            .rev()
+            .find(|p| p.waifu_id == waifu_id)
+            .cloned();
+
+        Ok(price)
+    }
+    async fn get_prices(&self, waifu_ids: &[i16]) -> Result<Vec<WaifuPrice>, crate::Error> {
+        let guard = self.state.lock().await;
+        let mut prices: HashMap<i16, WaifuPrice> = HashMap::new();
+        for price in guard.waifu_prices.iter() {
+            if waifu_ids.contains(&price.waifu_id) {
+                prices.insert(price.waifu_id, price.clone());
+            }
+        }
+
+        Ok(prices.into_values().collect())
+    }
+    async fn get_price_history(
+        &self,
+        waifu_id: i16,
+        limit: i64,
+    ) -> Result<Vec<WaifuPrice>, crate::Error> {
+        let guard = self.state.lock().await;
+        let history = guard
+            .waifu_prices
+            .iter()
+            .rev()
+            .filter(|p| p.waifu_id == waifu_id)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+
+        Ok(history)
+    }
+}
+
+#[async_trait]
+impl VoteStore for MemoryStore {
+    async fn cast_vote(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+        vote: Vote,
+    ) -> Result<Option<Vote>, crate::Error> {
+        let mut guard = self.state.lock().await;
+        Ok(guard.votes.insert((user_id.0, waifu_id), vote))
+    }
+    async fn get_vote_totals(&self, waifu_id: i16) -> Result<VoteTotals, crate::Error> {
+        let guard = self.state.lock().await;
+        let mut totals = VoteTotals::default();
+        for ((_, vote_waifu_id), vote) in guard.votes.iter() {
+            if *vote_waifu_id != waifu_id {
+                continue;
+            }
+            match vote {
+                Vote::Like => totals.likes += 1,
+                Vote::Trash => totals.trash += 1,
+            }
+        }
+
+        Ok(totals)
+    }
+}
+
+#[async_trait]
+impl WishlistStore for MemoryStore {
+    async fn add_to_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        if guard.wishlists.contains(&(user_id.0, waifu_id)) {
+            return Ok(false);
+        }
+        guard.wishlists.push((user_id.0, waifu_id));
+
+        Ok(true)
+    }
+    async fn remove_from_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error> {
+        let mut guard = self.state.lock().await;
+        let before = guard.wishlists.len();
+        guard
+            .wishlists
+            .retain(|entry| *entry != (user_id.0, waifu_id));
+
+        Ok(guard.wishlists.len() < before)
+    }
+    async fn get_wishlist(&self, user_id: serenity::UserId) -> Result<Vec<i16>, crate::Error> {
+        let guard = self.state.lock().await;
+        let wishlist = guard
+            .wishlists
+            .iter()
+            .filter(|(wishlist_user_id, _)| *wishlist_user_id == user_id.0)
+            .map(|(_, waifu_id)| *waifu_id)
+            .collect();
+
+        Ok(wishlist)
+    }
+    async fn get_wishlist_counts(
+        &self,
+        waifu_ids: &[i16],
+    ) -> Result<Vec<(i16, i64)>, crate::Error> {
+        let guard = self.state.lock().await;
+        let mut counts: HashMap<i16, i64> = HashMap::new();
+        for (_, waifu_id) in guard.wishlists.iter() {
+            if waifu_ids.contains(waifu_id) {
+                *counts.entry(*waifu_id).or_default() += 1;
+            }
+        }
+
+        Ok(counts.into_iter().collect())
+    }
+}
+
+#[async_trait]
+impl ProductStore for MemoryStore {
+    async fn get_premium_product(&self, price_id: &str) -> Result<PremiumProduct, crate::Error> {
+        let guard = self.state.lock().await;
+        let product = guard
+            .products
+            .get(price_id)
+            .ok_or(NotFound("premium product"))?;
+
+        Ok(product.clone())
+    }
+}
+
+/// A waifu catalog held in memory, the counterpart of the Mongo `waifus` collection
+pub struct MemoryCatalog {
+    waifus: TokioMutex<Vec<Waifu>>,
+}
+impl MemoryCatalog {
+    pub fn new(waifus: Vec<Waifu>) -> Self {
+        Self {
+            waifus: TokioMutex::new(waifus),
+        }
+    }
+    /// Reads a JSON array of waifus, falling back to an empty catalog if the file doesn't exist
+    pub fn from_file(path: &str) -> Self {
+        let waifus = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).expect("Cannot parse waifu catalog"),
+            Err(_) => vec![],
+        };
+
+        Self::new(waifus)
+    }
+    fn sample_waifus(
+        catalog: &[Waifu],
+        size: u32,
+        current_waifus: &[i16],
+        drawn: &[Waifu],
+        rarity: Option<Rarity>,
+        only: Option<&[i16]>,
+    ) -> Vec<Waifu> {
+        let candidates: Vec<&Waifu> = catalog
+            .iter()
+            .filter(|w| !current_waifus.contains(&(w._id as i16)))
+            .filter(|w| !drawn.iter().any(|d| d._id == w._id))
+            .filter(|w| rarity.map_or(true, |r| w.rarity == r))
+            .filter(|w| only.map_or(true, |only| only.contains(&(w._id as i16))))
+            .collect();
+
+        candidates
+            .choose_multiple(&mut thread_rng(), size as usize)
+            .map(|w| (*w).clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WaifuCatalog for MemoryCatalog {
+    async fn get_random_waifus(
+        &self,
+        count: u32,
+        current_waifus: &[i16],
+        table: &DropTable,
+        wishlist: &[i16],
+    ) -> Result<Vec<Waifu>, crate::Error> {
+        let catalog = self.waifus.lock().await;
+        let mut waifus: Vec<Waifu> = vec![];
+        for (rarity, amount) in table.roll(count) {
+            let wishlisted = if wishlist.is_empty() {
+                0
+            } else {
+                roll_wishlisted(amount)
+            };
+            let mut drawn = Self::sample_waifus(
+                &catalog,
+                wishlisted,
+                current_waifus,
+                &waifus,
+                Some(rarity),
+                Some(wishlist),
+            );
+            // wishlist slots without a wishlisted waifu left are drawn from the whole rarity
+            let remaining = amount - drawn.len() as u32;
+            waifus.append(&mut drawn);
+            let mut drawn = Self::sample_waifus(
+                &catalog,
+                remaining,
+                current_waifus,
+                &waifus,
+                Some(rarity),
+                None,
+            );
+            waifus.append(&mut drawn);
+        }
+
+        // rarities without enough waifus left are topped up from the whole catalog
+        let missing = count.saturating_sub(waifus.len() as u32);
+        if missing > 0 {
+            let mut drawn =
+                Self::sample_waifus(&catalog, missing, current_waifus, &waifus, None, None);
+            waifus.append(&mut drawn);
+        }
+
+        Ok(waifus)
+    }
+    async fn get_waifus(&self, waifu_ids: Vec<i32>) -> Result<Vec<Waifu>, crate::Error> {
+        let catalog = self.waifus.lock().await;
+        let waifus = catalog
+            .iter()
+            .filter(|w| waifu_ids.contains(&(w._id as i32)))
+            .cloned()
+            .collect();
+
+        Ok(waifus)
+    }
+    async fn get_waifu(&self, waifu_id: i32) -> Result<Waifu, crate::Error> {
+        let catalog = self.waifus.lock().await;
+        let waifu = catalog
+            .iter()
+            .find(|w| w._id as i32 == waifu_id)
+            .ok_or(NotFound("waifu"))?;
+
+        Ok(waifu.clone())
+    }
+    async fn get_all_waifus(&self) -> Result<Vec<Waifu>, crate::Error> {
+        let catalog = self.waifus.lock().await;
+
+        Ok(catalog.clone())
+    }
+    async fn search_waifus(
+        &self,
+        query: &str,
+        rarity: Option<Rarity>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Waifu>, crate::Error> {
+        let catalog = self.waifus.lock().await;
+        let waifus = catalog
+            .iter()
+            .filter(|w| rarity.map_or(true, |rarity| w.rarity == rarity))
+            .cloned()
+            .collect();
+
+        Ok(rank_search_results(waifus, query, offset + limit)
+            .into_iter()
+            .skip(offset)
+            .collect())
+    }
+    async fn add_votes(
+        &self,
+        waifu_id: i32,
+        change: VoteTotals,
+    ) -> Result<VoteTotals, crate::Error> {
+        let mut catalog = self.waifus.lock().await;
+        let waifu = catalog
+            .iter_mut()
+            .find(|w| w._id as i32 == waifu_id)
+            .ok_or(NotFound("waifu"))?;
+        waifu.likes = (waifu.likes as i64 + change.likes).max(0) as u32;
+        waifu.trash = (waifu.trash as i64 + change.trash).max(0) as u32;
+
+        Ok(VoteTotals {
+            likes: waifu.likes.into(),
+            trash: waifu.trash.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::chrono::Duration;
+
+    use super::*;
+    use crate::models::{ledger::LedgerReason, trade::Trade};
+
+    const ALICE: serenity::UserId = serenity::UserId(1);
+    const BOB: serenity::UserId = serenity::UserId(2);
+
+    /// Both players start with the default 500 currency and 3 packs
+    async fn store_with_accounts() -> MemoryStore {
+        let store = MemoryStore::new();
+        for user_id in [ALICE, BOB] {
+            store.register_account(user_id).await.unwrap();
+        }
+        store
+    }
+
+    /// Gives the user a copy of the waifu, returning its instance id
+    async fn give_waifu(store: &MemoryStore, user_id: serenity::UserId, waifu_id: u16) -> i64 {
+        let action = LedgerAction::new(LedgerReason::Summon, "test").add_waifu(user_id, waifu_id);
+        store.apply_ledger(&action).await.unwrap();
+        let owned_waifus = store.get_waifus(user_id).await.unwrap();
+        owned_waifus.last().unwrap().instance_id
+    }
+
+    async fn currency(store: &MemoryStore, user_id: serenity::UserId) -> i32 {
+        store.get_account(user_id).await.unwrap().currency
+    }
+
+    fn tomorrow() -> DateTime<Utc> {
+        Utc::now() + Duration::days(1)
+    }
+
+    #[tokio::test]
+    async fn apply_ledger_rolls_back_a_failed_action() {
+        let store = store_with_accounts().await;
+        // only the last change fails, the ones before it must not stick either
+        let action = LedgerAction::new(LedgerReason::WaifuPurchase, "test")
+            .currencies(ALICE, -100, 0)
+            .add_waifu(ALICE, 7)
+            .packs(ALICE, -10, 0);
+
+        let error = store.apply_ledger(&action).await.unwrap_err();
+        assert!(error.is::<LedgerError>());
+        let account = store.get_account(ALICE).await.unwrap();
+        assert_eq!((account.currency, account.packs), (500, 3));
+        assert!(store.get_waifus(ALICE).await.unwrap().is_empty());
+        assert!(store.ledger_entries(ALICE).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn apply_ledger_records_an_entry_per_change() {
+        let store = store_with_accounts().await;
+        let action = LedgerAction::new(LedgerReason::WaifuPurchase, "test")
+            .currencies(ALICE, -100, 0)
+            .add_waifu(ALICE, 7);
+        store.apply_ledger(&action).await.unwrap();
+
+        let entries = store.ledger_entries(ALICE).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            (entries[0].kind.as_str(), entries[0].delta),
+            ("currency", -100)
+        );
+        assert_eq!(
+            (entries[1].kind.as_str(), entries[1].waifu_id),
+            ("waifu", Some(7))
+        );
+        assert_eq!(entries[0].transaction_id, entries[1].transaction_id);
+        assert_eq!(entries[0].reason, "waifu_purchase");
+    }
+
+    #[tokio::test]
+    async fn trade_swaps_both_offers() {
+        let store = store_with_accounts().await;
+        let instance_id = give_waifu(&store, ALICE, 7).await;
+        let mut trade = Trade::new(ALICE, BOB);
+        trade.initiator_offer.waifus.push(instance_id);
+        trade.partner_offer.currency = 300;
+
+        let action = trade.to_ledger_action("test").unwrap();
+        store.apply_ledger(&action).await.unwrap();
+
+        assert!(store.get_waifus(ALICE).await.unwrap().is_empty());
+        let bob_waifus = store.get_waifus(BOB).await.unwrap();
+        assert_eq!(bob_waifus.len(), 1);
+        assert_eq!(bob_waifus[0].instance_id, instance_id);
+        assert_eq!(currency(&store, ALICE).await, 800);
+        assert_eq!(currency(&store, BOB).await, 200);
+    }
+
+    #[tokio::test]
+    async fn trade_is_all_or_nothing() {
+        let store = store_with_accounts().await;
+        let instance_id = give_waifu(&store, ALICE, 7).await;
+        let mut trade = Trade::new(ALICE, BOB);
+        trade.initiator_offer.waifus.push(instance_id);
+        // more than Bob has, so Alice must keep her waifu
+        trade.partner_offer.currency = 600;
+
+        let action = trade.to_ledger_action("test").unwrap();
+        assert!(store.apply_ledger(&action).await.is_err());
+
+        assert_eq!(store.get_waifus(ALICE).await.unwrap().len(), 1);
+        assert!(store.get_waifus(BOB).await.unwrap().is_empty());
+        assert_eq!(currency(&store, ALICE).await, 500);
+        assert_eq!(currency(&store, BOB).await, 500);
+    }
+
+    #[tokio::test]
+    async fn listed_waifus_stay_in_escrow_until_sold() {
+        let store = store_with_accounts().await;
+        let instance_id = give_waifu(&store, ALICE, 7).await;
+        let fee = LedgerAction::new(LedgerReason::MarketListing, "test").currencies(ALICE, -10, 0);
+        let listing = store
+            .create_listing(ALICE, instance_id, 200, tomorrow(), &fee)
+            .await
+            .unwrap();
+
+        // escrowed waifus can't be traded away or listed twice
+        assert!(store.get_waifus(ALICE).await.unwrap().is_empty());
+        let transfer =
+            LedgerAction::new(LedgerReason::Trade, "test").transfer_waifu(ALICE, BOB, instance_id);
+        assert!(store.apply_ledger(&transfer).await.is_err());
+        let relisted = store
+            .create_listing(ALICE, instance_id, 100, tomorrow(), &fee)
+            .await;
+        assert!(relisted.is_err());
+
+        store
+            .buy_listing(listing.listing_id, BOB, "test")
+            .await
+            .unwrap();
+        let bob_waifus = store.get_waifus(BOB).await.unwrap();
+        assert_eq!(bob_waifus[0].instance_id, instance_id);
+        assert_eq!(currency(&store, ALICE).await, 500 - 10 + 200);
+        assert_eq!(currency(&store, BOB).await, 300);
+        let sold = store.get_listing(listing.listing_id).await.unwrap();
+        assert!(sold.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_purchase_keeps_the_listing() {
+        let store = store_with_accounts().await;
+        let instance_id = give_waifu(&store, ALICE, 7).await;
+        let fee = LedgerAction::new(LedgerReason::MarketListing, "test");
+        let listing = store
+            .create_listing(ALICE, instance_id, 600, tomorrow(), &fee)
+            .await
+            .unwrap();
+
+        let error = store
+            .buy_listing(listing.listing_id, BOB, "test")
+            .await
+            .unwrap_err();
+        assert!(error.is::<LedgerError>());
+        let still_listed = store.get_listing(listing.listing_id).await.unwrap();
+        assert!(still_listed.is_some());
+        assert!(store.get_waifus(BOB).await.unwrap().is_empty());
+        assert_eq!(currency(&store, BOB).await, 500);
+    }
+
+    #[tokio::test]
+    async fn cancelled_listing_returns_the_waifu() {
+        let store = store_with_accounts().await;
+        let instance_id = give_waifu(&store, ALICE, 7).await;
+        let fee = LedgerAction::new(LedgerReason::MarketListing, "test").currencies(ALICE, -10, 0);
+        let listing = store
+            .create_listing(ALICE, instance_id, 200, tomorrow(), &fee)
+            .await
+            .unwrap();
+
+        // only the seller can take it down
+        let cancelled = store.cancel_listing(listing.listing_id, BOB).await.unwrap();
+        assert!(cancelled.is_none());
+        let cancelled = store
+            .cancel_listing(listing.listing_id, ALICE)
+            .await
+            .unwrap();
+        assert!(cancelled.is_some());
+
+        assert_eq!(store.get_waifus(ALICE).await.unwrap().len(), 1);
+        // the fee is never refunded
+        assert_eq!(currency(&store, ALICE).await, 490);
+    }
+
+    #[tokio::test]
+    async fn reward_claims_cant_be_raced() {
+        let store = store_with_accounts().await;
+        let now = Utc::now();
+        let action = LedgerAction::new(LedgerReason::Reward, "test").currencies(ALICE, 150, 0);
+        store
+            .claim_reward(ALICE, RewardKind::Daily, None, now, 1, &action)
+            .await
+            .unwrap();
+
+        // a second claim that still expects no earlier claim lost the race
+        let error = store
+            .claim_reward(ALICE, RewardKind::Daily, None, now, 1, &action)
+            .await
+            .unwrap_err();
+        assert!(error.is::<AlreadyClaimed>());
+        assert_eq!(currency(&store, ALICE).await, 650);
+
+        let later = now + Duration::days(1);
+        store
+            .claim_reward(ALICE, RewardKind::Daily, Some(now), later, 2, &action)
+            .await
+            .unwrap();
+        let claim = store
+            .get_reward_claim(ALICE, RewardKind::Daily)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((claim.last_claimed_at, claim.streak), (later, 2));
+        assert_eq!(currency(&store, ALICE).await, 800);
+    }
+
+    #[tokio::test]
+    async fn failed_reward_records_no_claim() {
+        let store = store_with_accounts().await;
+        let action = LedgerAction::new(LedgerReason::Reward, "test")
+            .currencies(ALICE, 150, 0)
+            .packs(ALICE, -10, 0);
+
+        let claimed = store
+            .claim_reward(ALICE, RewardKind::Daily, None, Utc::now(), 1, &action)
+            .await;
+        assert!(claimed.is_err());
+        let claim = store
+            .get_reward_claim(ALICE, RewardKind::Daily)
+            .await
+            .unwrap();
+        assert!(claim.is_none());
+        assert_eq!(currency(&store, ALICE).await, 500);
+    }
+
+    #[tokio::test]
+    async fn only_running_banners_can_be_summoned_on() {
+        let now = Utc::now();
+        let banner = |banner_id: i32, starts_at: DateTime<Utc>, ends_at: DateTime<Utc>| Banner {
+            banner_id,
+            name: format!("Banner {banner_id}"),
+            description: String::new(),
+            featured_waifus: vec![7],
+            rate_up: 50,
+            starts_at,
+            ends_at,
+            pack_name: None,
+            pack_price: None,
+            pack_size: None,
+        };
+        let store = MemoryStore::new().with_banners(vec![
+            banner(1, now - Duration::days(7), now - Duration::days(1)),
+            banner(2, tomorrow(), now + Duration::days(7)),
+            banner(3, now - Duration::days(1), tomorrow()),
+        ]);
+
+        // `/banners` lists running banners first, then upcoming ones
+        let banners = store.get_banners().await.unwrap();
+        let listed: Vec<i32> = banners.iter().map(|b| b.banner_id).collect();
+        assert_eq!(listed, vec![3, 2]);
+        assert!(banners[0].is_active(now));
+        assert!(!banners[1].is_active(now));
+
+        // an ended banner is still found by id, `/summon` refuses it as not running
+        let ended = store.get_banner(1).await.unwrap().unwrap();
+        assert!(!ended.is_active(now));
+    }
+
+    #[tokio::test]
+    async fn completed_collection_sets_pay_out_once() {
+        let set = CollectionSet {
+            set_id: 1,
+            name: String::from("Twins"),
+            description: String::new(),
+            waifu_ids: vec![7, 8],
+            reward_currency: 500,
+            reward_packs: 1,
+            reward_premium_one_packs: 0,
+        };
+        let store = store_with_accounts().await.with_collection_sets(vec![set]);
+        let sets = store.get_collection_sets().await.unwrap();
+        let set = &sets[0];
+
+        give_waifu(&store, ALICE, 7).await;
+        give_waifu(&store, ALICE, 8).await;
+        let owned_waifus = store.get_waifus(ALICE).await.unwrap();
+        let owned: Vec<i16> = owned_waifus.iter().map(|w| w.waifu_id).collect();
+        assert!(set.is_complete(&owned));
+        assert!(!set.is_complete(&owned[..1]));
+
+        // the same action the achievement check applies
+        let reward = set.reward();
+        let action = LedgerAction::new(LedgerReason::Achievement, "test")
+            .currencies(ALICE, reward.currency, 0)
+            .packs(ALICE, reward.packs, reward.premium_one_packs);
+        assert!(store
+            .unlock_achievement(ALICE, &set.key(), &action)
+            .await
+            .unwrap());
+        assert!(!store
+            .unlock_achievement(ALICE, &set.key(), &action)
+            .await
+            .unwrap());
+        let account = store.get_account(ALICE).await.unwrap();
+        assert_eq!((account.currency, account.packs), (1000, 4));
+    }
+
+    #[tokio::test]
+    async fn redeeming_a_product_credits_the_account() {
+        let product = PremiumProduct {
+            product_id: String::from("price_123"),
+            currency: 1000,
+            premium_currency: 50,
+            packs: 5,
+            premium_one_packs: 1,
+        };
+        let store = store_with_accounts().await.with_products(vec![product]);
+        assert!(store.get_premium_product("price_456").await.is_err());
+
+        // the same action `/shop redeem` applies
+        let product = store.get_premium_product("price_123").await.unwrap();
+        let action = LedgerAction::new(LedgerReason::PremiumRedeem, "test")
+            .packs(ALICE, product.packs, product.premium_one_packs)
+            .currencies(ALICE, product.currency, product.premium_currency);
+        store.apply_ledger(&action).await.unwrap();
+
+        let account = store.get_account(ALICE).await.unwrap();
+        assert_eq!(
+            (
+                account.currency,
+                account.premium_currency,
+                account.packs,
+                account.premium_one_packs
+            ),
+            (1500, 50, 8, 1)
+        );
+        let entries = store.ledger_entries(ALICE).await;
+        assert!(entries.iter().all(|e| e.reason == "premium_redeem"));
+        assert_eq!(currency(&store, BOB).await, 500);
+    }
+
+    #[tokio::test]
+    async fn deleted_owners_hand_their_alliance_on() {
+        let store = store_with_accounts().await;
+        store.create_alliance(ALICE, "Waifu Club").await.unwrap();
+        let alliance = store.get_alliance(ALICE).await.unwrap();
+        assert!(store
+            .join_alliance(alliance.alliance_id, BOB, 10)
+            .await
+            .unwrap());
+
+        store.delete_account(ALICE).await.unwrap();
+
+        let alliance = store.get_alliance(BOB).await.unwrap();
+        assert_eq!(alliance.owner, BOB.0 as i64);
+        assert!(alliance.members.is_empty());
+        assert!(store.get_membership(ALICE).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn the_last_member_deleting_their_account_deletes_the_alliance() {
+        let store = store_with_accounts().await;
+        store.create_alliance(ALICE, "Waifu Club").await.unwrap();
+
+        store.delete_account(ALICE).await.unwrap();
+
+        assert!(store.get_membership(ALICE).await.unwrap().is_none());
+        assert!(store.state.lock().await.alliances.is_empty());
+    }
+}
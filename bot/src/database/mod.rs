@@ -1,2 +1,345 @@
+pub mod memory;
 pub mod mongo;
 pub mod postgres;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::models::{
+    account::{Account, PremiumProduct},
+    achievement::{CollectionSet, UnlockedAchievement},
+    alliance::{Alliance, AllianceMember, AllianceRole, AllianceUpgrade, TreasuryMovement},
+    banner::Banner,
+    battle::{AllianceBattle, BattleOutcome},
+    leaderboard::{LeaderboardCategory, LeaderboardEntry},
+    ledger::LedgerAction,
+    market::{ListingFilter, MarketListing},
+    price::WaifuPrice,
+    reward::{RewardClaim, RewardKind},
+    vote::{Vote, VoteTotals},
+    waifu::{DropTable, OwnedWaifu, Rarity, Waifu},
+};
+
+#[async_trait]
+pub trait AccountStore: Send + Sync {
+    async fn register_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error>;
+    async fn get_account(&self, user_id: serenity::UserId) -> Result<Account, crate::Error>;
+    async fn delete_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error>;
+    /// Applies every change of the action atomically, see [`LedgerAction`]
+    async fn apply_ledger(&self, action: &LedgerAction) -> Result<(), crate::Error>;
+    /// Every waifu instance the user owns, oldest first
+    async fn get_waifus(&self, user_id: serenity::UserId) -> Result<Vec<OwnedWaifu>, crate::Error>;
+    /// The accounts of the users, in one query. Users without an account are left out.
+    async fn get_accounts(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<Account>, crate::Error>;
+    /// [`Self::get_waifus`] for several users in one query, oldest first
+    async fn get_waifus_of(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<OwnedWaifu>, crate::Error>;
+    /// How many players own at least one copy of the waifu
+    async fn count_owners(&self, waifu_id: i16) -> Result<i64, crate::Error>;
+}
+
+#[async_trait]
+pub trait AchievementStore: Send + Sync {
+    /// Everything the user unlocked, oldest first
+    async fn get_achievements(
+        &self,
+        user_id: serenity::UserId,
+    ) -> Result<Vec<UnlockedAchievement>, crate::Error>;
+    /// Unlocks the achievement and applies its reward in one transaction.
+    /// Returns `false` without rewarding anything if it was already unlocked.
+    async fn unlock_achievement(
+        &self,
+        user_id: serenity::UserId,
+        achievement: &str,
+        reward: &LedgerAction,
+    ) -> Result<bool, crate::Error>;
+    async fn get_collection_sets(&self) -> Result<Vec<CollectionSet>, crate::Error>;
+}
+
+#[async_trait]
+pub trait AllianceStore: Send + Sync {
+    async fn get_alliance(&self, user_id: serenity::UserId) -> Result<Alliance, crate::Error>;
+    /// Creates an alliance owned by the user
+    async fn create_alliance(
+        &self,
+        user_id: serenity::UserId,
+        name: &str,
+    ) -> Result<(), crate::Error>;
+    /// Deletes the alliance the user owns, along with every membership
+    async fn delete_alliance(&self, user_id: serenity::UserId) -> Result<(), crate::Error>;
+    /// Adds the user to the alliance as a member.
+    /// Returns `false` if the alliance already has `member_cap` players, owner included.
+    async fn join_alliance(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+        member_cap: i32,
+    ) -> Result<bool, crate::Error>;
+    /// Removes a member from whichever alliance they're in. Owners can't leave, returns `false` for them.
+    async fn leave_alliance(&self, user_id: serenity::UserId) -> Result<bool, crate::Error>;
+    /// Returns `false` if the user isn't a member or officer of the alliance
+    async fn kick_member(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+    ) -> Result<bool, crate::Error>;
+    /// Makes a member the new owner, the old owner stays on as an officer.
+    /// Returns `false` if the user isn't a member of the owner's alliance.
+    async fn transfer_alliance(
+        &self,
+        owner: serenity::UserId,
+        new_owner: serenity::UserId,
+    ) -> Result<bool, crate::Error>;
+    /// The user's membership, `None` if they aren't in an alliance
+    async fn get_membership(
+        &self,
+        user_id: serenity::UserId,
+    ) -> Result<Option<AllianceMember>, crate::Error>;
+    /// Every membership of the alliance, owner first, then in the order they joined
+    async fn get_alliance_members(
+        &self,
+        alliance_id: i32,
+    ) -> Result<Vec<AllianceMember>, crate::Error>;
+    /// Moves someone between member and officer.
+    /// Returns `false` if the user isn't in the alliance or owns it.
+    async fn set_member_role(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+        role: AllianceRole,
+    ) -> Result<bool, crate::Error>;
+    async fn rename_alliance(&self, alliance_id: i32, name: &str) -> Result<(), crate::Error>;
+}
+
+#[async_trait]
+pub trait BattleStore: Send + Sync {
+    /// Stores a fought battle and pays out its wager with `wager_action` in one transaction.
+    /// Fails with a [`crate::models::ledger::LedgerError`] if the loser's treasury can't cover it.
+    async fn record_battle(
+        &self,
+        challenger_id: i32,
+        defender_id: i32,
+        seed: u64,
+        wager: i32,
+        outcome: &BattleOutcome,
+        wager_action: &LedgerAction,
+    ) -> Result<AllianceBattle, crate::Error>;
+}
+
+/// Deposits and withdrawals go through [`AccountStore::apply_ledger`] with a treasury change
+#[async_trait]
+pub trait TreasuryStore: Send + Sync {
+    /// The latest movements of the alliance's treasury, newest first
+    async fn get_treasury_movements(
+        &self,
+        alliance_id: i32,
+        limit: i64,
+    ) -> Result<Vec<TreasuryMovement>, crate::Error>;
+    /// Every upgrade the alliance bought, with its level
+    async fn get_alliance_upgrades(
+        &self,
+        alliance_id: i32,
+    ) -> Result<HashMap<AllianceUpgrade, i16>, crate::Error>;
+    /// Raises the upgrade from `level` to the next one and pays for it in one transaction.
+    /// Returns `false` without paying if the upgrade is no longer at `level`.
+    async fn buy_alliance_upgrade(
+        &self,
+        alliance_id: i32,
+        upgrade: AllianceUpgrade,
+        level: i16,
+        payment: &LedgerAction,
+    ) -> Result<bool, crate::Error>;
+}
+
+#[async_trait]
+pub trait BannerStore: Send + Sync {
+    /// Banners that haven't ended yet, running or upcoming, in the order they start
+    async fn get_banners(&self) -> Result<Vec<Banner>, crate::Error>;
+    async fn get_banner(&self, banner_id: i32) -> Result<Option<Banner>, crate::Error>;
+}
+
+#[async_trait]
+pub trait LeaderboardStore: Send + Sync {
+    /// Remembers that the user plays in the guild, for server leaderboards
+    async fn record_guild_member(
+        &self,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+    ) -> Result<(), crate::Error>;
+    /// The top `limit` entries of the category, across every account or only the guild's players
+    async fn get_leaderboard(
+        &self,
+        category: LeaderboardCategory,
+        guild_id: Option<serenity::GuildId>,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>, crate::Error>;
+}
+
+#[async_trait]
+pub trait MarketStore: Send + Sync {
+    /// Puts an owned waifu up for sale and charges the listing fee in `fee_action`.
+    /// Fails with a [`crate::models::ledger::LedgerError`] if the seller doesn't own the waifu,
+    /// it is already listed, or they can't pay the fee.
+    async fn create_listing(
+        &self,
+        seller: serenity::UserId,
+        instance_id: i64,
+        price: i32,
+        expires_at: DateTime<Utc>,
+        fee_action: &LedgerAction,
+    ) -> Result<MarketListing, crate::Error>;
+    /// Active listings matching the filter, newest first
+    async fn get_listings(
+        &self,
+        filter: &ListingFilter,
+        limit: i64,
+    ) -> Result<Vec<MarketListing>, crate::Error>;
+    async fn get_listing(&self, listing_id: i64) -> Result<Option<MarketListing>, crate::Error>;
+    /// Ends the listing and executes the sale in one transaction. Fails with
+    /// [`crate::models::market::ListingUnavailable`] if the listing isn't active anymore.
+    async fn buy_listing(
+        &self,
+        listing_id: i64,
+        buyer: serenity::UserId,
+        command: &str,
+    ) -> Result<MarketListing, crate::Error>;
+    /// Takes the seller's listing down, the waifu leaves escrow. `None` if they had no such listing.
+    async fn cancel_listing(
+        &self,
+        listing_id: i64,
+        seller: serenity::UserId,
+    ) -> Result<Option<MarketListing>, crate::Error>;
+    /// Deletes expired listings, returning how many there were
+    async fn expire_listings(&self) -> Result<u64, crate::Error>;
+}
+
+#[async_trait]
+pub trait PityStore: Send + Sync {
+    /// How many packs of this type the user opened in a row without a premium waifu
+    async fn get_pity(&self, user_id: serenity::UserId, pack: &str) -> Result<i32, crate::Error>;
+    /// Applies the summon's ledger action and, in the same transaction, resets the pity counter
+    /// if the pack had a premium waifu or increments it otherwise. Returns the new counter.
+    async fn record_summon(
+        &self,
+        user_id: serenity::UserId,
+        pack: &str,
+        hit_premium: bool,
+        action: &LedgerAction,
+    ) -> Result<i32, crate::Error>;
+}
+
+#[async_trait]
+pub trait PriceStore: Send + Sync {
+    /// How many copies of each waifu players own, waifus nobody owns are left out
+    async fn get_copy_counts(&self) -> Result<Vec<(i16, i64)>, crate::Error>;
+    /// Adds a history row for every waifu whose price differs from its current one.
+    /// Returns how many prices changed.
+    async fn record_prices(&self, prices: &[(i16, i32)]) -> Result<u64, crate::Error>;
+    async fn get_price(&self, waifu_id: i16) -> Result<Option<WaifuPrice>, crate::Error>;
+    /// Current prices of the waifus, waifus without a price yet are left out
+    async fn get_prices(&self, waifu_ids: &[i16]) -> Result<Vec<WaifuPrice>, crate::Error>;
+    /// Past prices of the waifu, newest first
+    async fn get_price_history(
+        &self,
+        waifu_id: i16,
+        limit: i64,
+    ) -> Result<Vec<WaifuPrice>, crate::Error>;
+}
+
+#[async_trait]
+pub trait ProductStore: Send + Sync {
+    async fn get_premium_product(&self, price_id: &str) -> Result<PremiumProduct, crate::Error>;
+}
+
+#[async_trait]
+pub trait RewardStore: Send + Sync {
+    async fn get_reward_claim(
+        &self,
+        user_id: serenity::UserId,
+        kind: RewardKind,
+    ) -> Result<Option<RewardClaim>, crate::Error>;
+    /// Records the claim and applies the reward's ledger action in one transaction.
+    /// Fails with [`crate::models::reward::AlreadyClaimed`] if the last claim is no longer
+    /// `previous_claimed_at`, so a reward can't be claimed twice by racing commands.
+    async fn claim_reward(
+        &self,
+        user_id: serenity::UserId,
+        kind: RewardKind,
+        previous_claimed_at: Option<DateTime<Utc>>,
+        claimed_at: DateTime<Utc>,
+        streak: i32,
+        action: &LedgerAction,
+    ) -> Result<(), crate::Error>;
+}
+
+#[async_trait]
+pub trait VoteStore: Send + Sync {
+    /// Records the user's vote on the waifu, replacing any earlier one. Returns the earlier vote.
+    async fn cast_vote(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+        vote: Vote,
+    ) -> Result<Option<Vote>, crate::Error>;
+    async fn get_vote_totals(&self, waifu_id: i16) -> Result<VoteTotals, crate::Error>;
+}
+
+#[async_trait]
+pub trait WishlistStore: Send + Sync {
+    /// Returns `false` if the waifu already was on the wishlist
+    async fn add_to_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error>;
+    /// Returns `false` if the waifu wasn't on the wishlist
+    async fn remove_from_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error>;
+    /// Wishlisted waifu ids, oldest first
+    async fn get_wishlist(&self, user_id: serenity::UserId) -> Result<Vec<i16>, crate::Error>;
+    /// How many players wishlist each of the waifus, waifus nobody wishlists are left out
+    async fn get_wishlist_counts(&self, waifu_ids: &[i16])
+        -> Result<Vec<(i16, i64)>, crate::Error>;
+}
+
+#[async_trait]
+pub trait WaifuCatalog: Send + Sync {
+    /// Draws `count` waifus the player doesn't own, rolling each one's rarity on the table.
+    /// Waifus on the player's `wishlist` are slightly more likely, see [`crate::models::waifu::WISHLIST_BOOST_PERCENT`].
+    async fn get_random_waifus(
+        &self,
+        count: u32,
+        current_waifus: &[i16],
+        table: &DropTable,
+        wishlist: &[i16],
+    ) -> Result<Vec<Waifu>, crate::Error>;
+    async fn get_waifus(&self, waifu_ids: Vec<i32>) -> Result<Vec<Waifu>, crate::Error>;
+    async fn get_waifu(&self, waifu_id: i32) -> Result<Waifu, crate::Error>;
+    async fn get_all_waifus(&self) -> Result<Vec<Waifu>, crate::Error>;
+    /// Waifus whose name contains the query, best matches first, see [`crate::models::waifu::search_rank`].
+    /// `offset` skips that many of the best matches, so results can be paged through.
+    async fn search_waifus(
+        &self,
+        query: &str,
+        rarity: Option<Rarity>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Waifu>, crate::Error>;
+    /// Adds the change to the waifu's likes and trash in one atomic update, returning the new totals
+    async fn add_votes(
+        &self,
+        waifu_id: i32,
+        change: VoteTotals,
+    ) -> Result<VoteTotals, crate::Error>;
+}
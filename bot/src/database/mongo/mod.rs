@@ -1,40 +1,114 @@
+use async_trait::async_trait;
 use futures::TryStreamExt;
 use mongodb::{
     bson::{doc, Bson},
-    options::{ClientOptions, ResolverConfig},
-    Client, Collection,
+    options::{
+        ClientOptions, Collation, CollationStrength, FindOneAndUpdateOptions, FindOptions,
+        IndexOptions, ResolverConfig, ReturnDocument,
+    },
+    Client, Collection, IndexModel,
 };
+use serde::Deserialize;
 
-use crate::{config::Mongo, models::waifu::Waifu};
+use crate::{
+    config::Mongo,
+    database::WaifuCatalog,
+    models::{
+        vote::VoteTotals,
+        waifu::{rank_by_name, roll_wishlisted, DropTable, Rarity, Waifu},
+    },
+};
+
+/// Name of the index prefix searches go through, see [`name_collation`]
+const NAME_INDEX: &str = "name_case_insensitive";
+
+/// Compares names ignoring case, the name index is built with it so case-insensitive range queries can use it
+fn name_collation() -> Collation {
+    Collation::builder()
+        .locale("en")
+        .strength(CollationStrength::Secondary)
+        .build()
+}
+
+/// Only what's needed to rank a search match, full waifus are fetched for the best ones
+#[derive(Deserialize)]
+struct SearchCandidate {
+    _id: u16,
+    name: String,
+}
 
 pub struct MongoConnection {
     waifu_collection: Collection<Waifu>,
 }
 impl MongoConnection {
-    pub async fn connect(config: &Mongo) -> Self {
+    pub async fn connect(config: &Mongo) -> Result<Self, crate::Error> {
         let client_options = ClientOptions::parse_with_resolver_config(
             &config.connection_uri,
             ResolverConfig::cloudflare(),
         )
-        .await
-        .expect("Cannot parse mongodb connection uri");
-
-        let client = Client::with_options(client_options)
-            .expect("Failed to build client with client options");
+        .await?;
+        let client = Client::with_options(client_options)?;
 
         let core_db = client.database("core");
         let waifu_collection = core_db.collection::<Waifu>("waifus");
+        let name_index = IndexModel::builder()
+            .keys(doc! { "name": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name(NAME_INDEX.to_string())
+                    .collation(name_collation())
+                    .build(),
+            )
+            .build();
+        waifu_collection.create_index(name_index, None).await?;
 
-        Self { waifu_collection }
+        Ok(Self { waifu_collection })
     }
-    pub async fn get_random_waifus(
+    /// Runs a search stage, only fetching the id and name of the matching waifus
+    async fn find_candidates(
         &self,
-        count: u32,
-        current_waifus: &Vec<i16>,
+        filter: mongodb::bson::Document,
+        collation: Option<Collation>,
+    ) -> Result<Vec<SearchCandidate>, crate::Error> {
+        let options = FindOptions::builder()
+            .projection(doc! { "_id": 1, "name": 1 })
+            .collation(collation)
+            .build();
+        let mut cursor = self
+            .waifu_collection
+            .clone_with_type::<SearchCandidate>()
+            .find(filter, options)
+            .await?;
+        let mut documents = vec![];
+        while let Some(doc) = cursor.try_next().await? {
+            documents.push(doc);
+        }
+
+        Ok(documents)
+    }
+    async fn sample_waifus(
+        &self,
+        size: u32,
+        excluded: &[i32],
+        rarity: Option<Rarity>,
+        only: Option<&[i32]>,
     ) -> Result<Vec<Waifu>, crate::Error> {
-        let current_waifus: Vec<i32> = current_waifus.iter().map(|el| el.clone() as i32).collect();
-        let query = doc! { "$sample": { "size": count } };
-        let query2 = doc! { "$match": { "_id": { "$nin": current_waifus } } };
+        let mut filter = match only {
+            Some(only) => doc! { "_id": { "$nin": excluded, "$in": only } },
+            None => doc! { "_id": { "$nin": excluded } },
+        };
+        match rarity {
+            // documents without a rarity count as common
+            Some(Rarity::Common) => {
+                filter.insert("rarity", doc! { "$in": [Rarity::Common.as_str(), null] });
+            }
+            Some(rarity) => {
+                filter.insert("rarity", rarity.as_str());
+            }
+            None => {}
+        }
+        let query = doc! { "$sample": { "size": size } };
+        let query2 = doc! { "$match": filter };
         let mut cursor = self
             .waifu_collection
             .aggregate([query2, query], None)
@@ -47,7 +121,55 @@ impl MongoConnection {
 
         Ok(documents)
     }
-    pub async fn get_waifus(&self, waifu_ids: Vec<i32>) -> Result<Vec<Waifu>, crate::Error> {
+}
+
+#[async_trait]
+impl WaifuCatalog for MongoConnection {
+    async fn get_random_waifus(
+        &self,
+        count: u32,
+        current_waifus: &[i16],
+        table: &DropTable,
+        wishlist: &[i16],
+    ) -> Result<Vec<Waifu>, crate::Error> {
+        let mut excluded: Vec<i32> = current_waifus.iter().map(|el| el.clone() as i32).collect();
+        let wishlist: Vec<i32> = wishlist.iter().map(|id| *id as i32).collect();
+        let mut documents = vec![];
+        for (rarity, amount) in table.roll(count) {
+            let wishlisted = if wishlist.is_empty() {
+                0
+            } else {
+                roll_wishlisted(amount)
+            };
+            let mut drawn = vec![];
+            if wishlisted > 0 {
+                drawn = self
+                    .sample_waifus(wishlisted, &excluded, Some(rarity), Some(&wishlist))
+                    .await?;
+                excluded.extend(drawn.iter().map(|w| w._id as i32));
+            }
+            // wishlist slots without a wishlisted waifu left are drawn from the whole rarity
+            let remaining = amount - drawn.len() as u32;
+            if remaining > 0 {
+                let mut rest = self
+                    .sample_waifus(remaining, &excluded, Some(rarity), None)
+                    .await?;
+                excluded.extend(rest.iter().map(|w| w._id as i32));
+                drawn.append(&mut rest);
+            }
+            documents.append(&mut drawn);
+        }
+
+        // rarities without enough waifus left are topped up from the whole catalog
+        let missing = count.saturating_sub(documents.len() as u32);
+        if missing > 0 {
+            let mut drawn = self.sample_waifus(missing, &excluded, None, None).await?;
+            documents.append(&mut drawn);
+        }
+
+        Ok(documents)
+    }
+    async fn get_waifus(&self, waifu_ids: Vec<i32>) -> Result<Vec<Waifu>, crate::Error> {
         let query = doc! { "_id": { "$in": waifu_ids } };
         let mut cursor = self.waifu_collection.find(query, None).await?;
         let mut documents = vec![];
@@ -57,15 +179,14 @@ impl MongoConnection {
 
         Ok(documents)
     }
-    pub async fn get_waifu(&self, waifu_id: i32) -> Result<Waifu, crate::Error> {
+    async fn get_waifu(&self, waifu_id: i32) -> Result<Waifu, crate::Error> {
         let query = doc! { "_id": waifu_id };
         let waifu = self.waifu_collection.find_one(query, None).await?;
 
-        Ok(waifu.unwrap())
+        Ok(waifu.ok_or("waifu not found")?)
     }
-    pub async fn search_waifus(&self, query: &str) -> Result<Vec<Waifu>, crate::Error> {
-        let mongo_query = doc! { "name": { "$regex": query, "$options": "i" } };
-        let mut cursor = self.waifu_collection.find(mongo_query, None).await?;
+    async fn get_all_waifus(&self) -> Result<Vec<Waifu>, crate::Error> {
+        let mut cursor = self.waifu_collection.find(None, None).await?;
         let mut documents = vec![];
         while let Some(doc) = cursor.try_next().await? {
             documents.push(doc);
@@ -73,4 +194,99 @@ impl MongoConnection {
 
         Ok(documents)
     }
+    async fn search_waifus(
+        &self,
+        query: &str,
+        rarity: Option<Rarity>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Waifu>, crate::Error> {
+        if limit == 0 {
+            return Ok(vec![]);
+        }
+        let query = query.trim();
+        let wanted = offset + limit;
+        let mut filter = doc! {};
+        match rarity {
+            Some(Rarity::Common) => {
+                filter.insert("rarity", doc! { "$in": [Rarity::Common.as_str(), null] });
+            }
+            Some(rarity) => {
+                filter.insert("rarity", rarity.as_str());
+            }
+            None => {}
+        }
+        // names starting with the query rank best. Under the name index's collation they're a range,
+        // and U+FFFF sorts after every character so it closes the range without scanning the collection
+        let mut prefix_filter = filter.clone();
+        prefix_filter.insert(
+            "name",
+            doc! { "$gte": query, "$lt": format!("{query}\u{FFFF}") },
+        );
+        let mut candidates = self
+            .find_candidates(prefix_filter, Some(name_collation()))
+            .await?;
+
+        // any prefix match outranks the other matches, so the whole catalog is only scanned when they fall short
+        if candidates.len() < wanted {
+            let found: Vec<i32> = candidates.iter().map(|c| c._id as i32).collect();
+            filter.insert("_id", doc! { "$nin": found });
+            filter.insert(
+                "name",
+                doc! { "$regex": escape_regex(query), "$options": "i" },
+            );
+            let mut rest = self.find_candidates(filter, None).await?;
+            candidates.append(&mut rest);
+        }
+
+        // every candidate is ranked before any is dropped, then only the best are fetched in full
+        let best: Vec<SearchCandidate> =
+            rank_by_name(candidates, |c| c.name.as_str(), query, wanted)
+                .into_iter()
+                .skip(offset)
+                .collect();
+        let mut waifus = self
+            .get_waifus(best.iter().map(|c| c._id as i32).collect())
+            .await?;
+        waifus.sort_by_key(|w| best.iter().position(|c| c._id == w._id));
+
+        Ok(waifus)
+    }
+    async fn add_votes(
+        &self,
+        waifu_id: i32,
+        change: VoteTotals,
+    ) -> Result<VoteTotals, crate::Error> {
+        // `$inc` applies concurrent votes one after the other, so none of them is lost
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let waifu = self
+            .waifu_collection
+            .find_one_and_update(
+                doc! { "_id": waifu_id },
+                doc! { "$inc": { "likes": change.likes as i32, "trash": change.trash as i32 } },
+                options,
+            )
+            .await?
+            .ok_or("waifu not found")?;
+
+        Ok(VoteTotals {
+            likes: waifu.likes.into(),
+            trash: waifu.trash.into(),
+        })
+    }
+}
+
+/// Escapes everything the regex engine would interpret, so user input only ever matches literally
+fn escape_regex(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
 }
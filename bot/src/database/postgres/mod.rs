@@ -1,12 +1,34 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
 use poise::serenity_prelude as serenity;
 use sqlx::{
-    postgres::{PgPoolOptions, Postgres},
+    postgres::{PgConnection, PgPoolOptions, Postgres},
+    types::chrono::{DateTime, Utc},
     Pool,
 };
 
 use crate::{
     config::Postgres as PostgresConfig,
-    models::account::{Account, Alliance, PremiumProduct},
+    database::{
+        AccountStore, AchievementStore, AllianceStore, BannerStore, BattleStore, LeaderboardStore,
+        MarketStore, PityStore, PriceStore, ProductStore, RewardStore, TreasuryStore, VoteStore,
+        WishlistStore,
+    },
+    models::{
+        account::{Account, PremiumProduct},
+        achievement::{CollectionSet, UnlockedAchievement},
+        alliance::{Alliance, AllianceMember, AllianceRole, AllianceUpgrade, TreasuryMovement},
+        banner::Banner,
+        battle::{AllianceBattle, BattleOutcome},
+        leaderboard::{LeaderboardCategory, LeaderboardEntry},
+        ledger::{LedgerAction, LedgerChange, LedgerError, LedgerKind},
+        market::{ListingFilter, ListingUnavailable, MarketListing},
+        price::WaifuPrice,
+        reward::{AlreadyClaimed, RewardClaim, RewardKind},
+        vote::{Vote, VoteTotals},
+        waifu::OwnedWaifu,
+    },
 };
 
 pub struct PostgresConnection {
@@ -23,15 +45,25 @@ impl PostgresConnection {
 
         Self { pool }
     }
-    pub async fn register_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
-        sqlx::query("INSERT INTO accounts VALUES($1)")
+    /// Applies the migrations embedded from `bot/migrations` that haven't run yet
+    pub async fn migrate(&self) -> Result<(), crate::Error> {
+        sqlx::migrate!().run(&self.pool).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountStore for PostgresConnection {
+    async fn register_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+        sqlx::query("INSERT INTO accounts (user_id) VALUES($1)")
             .bind(user_id.0 as i64)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
-    pub async fn get_account(&self, user_id: serenity::UserId) -> Result<Account, crate::Error> {
+    async fn get_account(&self, user_id: serenity::UserId) -> Result<Account, crate::Error> {
         let account = sqlx::query_as("SELECT * FROM accounts WHERE user_id = $1")
             .bind(user_id.0 as i64)
             .fetch_one(&self.pool)
@@ -39,138 +71,1202 @@ impl PostgresConnection {
 
         Ok(account)
     }
-    pub async fn delete_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+    async fn delete_account(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        // the membership would cascade away with the account, but the alliance has to be told
+        leave_alliance_for_good(&mut *transaction, user_id).await?;
         sqlx::query("DELETE FROM accounts WHERE user_id = $1")
             .bind(user_id.0 as i64)
-            .execute(&self.pool)
+            .execute(&mut *transaction)
             .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+    async fn apply_ledger(&self, action: &LedgerAction) -> Result<(), crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        apply_ledger_changes(&mut *transaction, action).await?;
+        transaction.commit().await?;
 
         Ok(())
     }
-    pub async fn update_currencies(
+    async fn get_waifus(&self, user_id: serenity::UserId) -> Result<Vec<OwnedWaifu>, crate::Error> {
+        let waifus = sqlx::query_as(&format!(
+            "SELECT * FROM owned_waifus WHERE owner_id = $1 AND {NOT_IN_ESCROW} ORDER BY acquired_at, instance_id"
+        ))
+        .bind(user_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(waifus)
+    }
+    async fn get_accounts(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<Account>, crate::Error> {
+        let user_ids: Vec<i64> = user_ids.iter().map(|u| u.0 as i64).collect();
+        let accounts = sqlx::query_as("SELECT * FROM accounts WHERE user_id = ANY($1)")
+            .bind(user_ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(accounts)
+    }
+    async fn get_waifus_of(
+        &self,
+        user_ids: &[serenity::UserId],
+    ) -> Result<Vec<OwnedWaifu>, crate::Error> {
+        let user_ids: Vec<i64> = user_ids.iter().map(|u| u.0 as i64).collect();
+        let waifus = sqlx::query_as(&format!(
+            "SELECT * FROM owned_waifus WHERE owner_id = ANY($1) AND {NOT_IN_ESCROW} ORDER BY acquired_at, instance_id"
+        ))
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(waifus)
+    }
+    async fn count_owners(&self, waifu_id: i16) -> Result<i64, crate::Error> {
+        let (owners,): (i64,) =
+            sqlx::query_as("SELECT COUNT(DISTINCT owner_id) FROM owned_waifus WHERE waifu_id = $1")
+                .bind(waifu_id)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(owners)
+    }
+}
+
+#[async_trait]
+impl AchievementStore for PostgresConnection {
+    async fn get_achievements(
         &self,
         user_id: serenity::UserId,
-        currency: i32,
-        premium_currency: i32,
-    ) -> Result<(), crate::Error> {
-        sqlx::query(
-            "UPDATE accounts SET currency = currency + $1, premium_currency = premium_currency + $1 WHERE user_id = $3"
-        ).bind(currency).bind(premium_currency).bind(user_id.0 as i64).execute(&self.pool).await?;
+    ) -> Result<Vec<UnlockedAchievement>, crate::Error> {
+        let achievements = sqlx::query_as(
+            "SELECT * FROM achievements WHERE user_id = $1 ORDER BY unlocked_at, achievement",
+        )
+        .bind(user_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
 
-        Ok(())
+        Ok(achievements)
     }
-    pub async fn update_experience(
+    async fn unlock_achievement(
         &self,
         user_id: serenity::UserId,
-        amount: i32,
-    ) -> Result<(), crate::Error> {
-        sqlx::query("UPDATE accounts SET experience = experience + $1 WHERE user_id = $2")
-            .bind(amount)
-            .bind(user_id.0 as i64)
-            .execute(&self.pool)
+        achievement: &str,
+        reward: &LedgerAction,
+    ) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query(
+            "INSERT INTO achievements (user_id, achievement) VALUES($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id.0 as i64)
+        .bind(achievement)
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        apply_ledger_changes(&mut *transaction, reward).await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+    async fn get_collection_sets(&self) -> Result<Vec<CollectionSet>, crate::Error> {
+        let sets = sqlx::query_as("SELECT * FROM collection_sets ORDER BY set_id")
+            .fetch_all(&self.pool)
             .await?;
 
-        Ok(())
+        Ok(sets)
     }
-    pub async fn update_packs(
+}
+
+#[async_trait]
+impl AllianceStore for PostgresConnection {
+    async fn get_alliance(&self, user_id: serenity::UserId) -> Result<Alliance, crate::Error> {
+        let alliance = sqlx::query_as(&format!(
+            "{ALLIANCE_SELECT} WHERE a.alliance_id = (SELECT alliance_id FROM alliance_members WHERE user_id = $1)"
+        ))
+        .bind(user_id.0 as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(alliance)
+    }
+    async fn create_alliance(
         &self,
         user_id: serenity::UserId,
-        packs: i16,
-        premium_one_packs: i16,
+        name: &str,
     ) -> Result<(), crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let (alliance_id,): (i32,) = sqlx::query_as(
+            "INSERT INTO alliances (name, member_count) VALUES($1, 1) RETURNING alliance_id",
+        )
+        .bind(name)
+        .fetch_one(&mut *transaction)
+        .await?;
+        sqlx::query(
+            "INSERT INTO alliance_members (user_id, alliance_id, role) VALUES($1, $2, 'owner')",
+        )
+        .bind(user_id.0 as i64)
+        .bind(alliance_id)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+    async fn delete_alliance(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
         sqlx::query(
-            "UPDATE accounts SET packs = packs + $1, premium_one_packs = premium_one_packs + $2 WHERE user_id = $3"
-        ).bind(packs).bind(premium_one_packs).bind(user_id.0 as i64).execute(&self.pool).await?;
+            "DELETE FROM alliances WHERE alliance_id = \
+            (SELECT alliance_id FROM alliance_members WHERE user_id = $1 AND role = 'owner')",
+        )
+        .bind(user_id.0 as i64)
+        .execute(&self.pool)
+        .await?;
 
         Ok(())
     }
-    pub async fn add_waifu(
+    async fn join_alliance(
         &self,
+        alliance_id: i32,
         user_id: serenity::UserId,
-        waifu_id: u16,
-    ) -> Result<(), crate::Error> {
-        sqlx::query("UPDATE accounts SET waifus = array_append(waifus, $1) WHERE user_id = $2")
-            .bind(waifu_id as i16)
+        member_cap: i32,
+    ) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        // claiming the seat first locks the alliance row, so concurrent invites can't overfill it
+        let claimed = sqlx::query(
+            "UPDATE alliances SET member_count = member_count + 1 WHERE alliance_id = $1 AND member_count < $2",
+        )
+        .bind(alliance_id)
+        .bind(member_cap)
+        .execute(&mut *transaction)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            "INSERT INTO alliance_members (user_id, alliance_id, role) VALUES($1, $2, 'member')",
+        )
+        .bind(user_id.0 as i64)
+        .bind(alliance_id)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+    async fn leave_alliance(&self, user_id: serenity::UserId) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let left: Option<(i32,)> = sqlx::query_as(
+            "DELETE FROM alliance_members WHERE user_id = $1 AND role <> 'owner' RETURNING alliance_id",
+        )
+        .bind(user_id.0 as i64)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some((alliance_id,)) = left else {
+            return Ok(false);
+        };
+        decrement_member_count(&mut *transaction, alliance_id).await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+    async fn kick_member(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+    ) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query(
+            "DELETE FROM alliance_members WHERE alliance_id = $1 AND user_id = $2 AND role <> 'owner'",
+        )
+        .bind(alliance_id)
+        .bind(user_id.0 as i64)
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        decrement_member_count(&mut *transaction, alliance_id).await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+    async fn transfer_alliance(
+        &self,
+        owner: serenity::UserId,
+        new_owner: serenity::UserId,
+    ) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        // the old owner steps down first, an alliance can only have one owner at a time
+        let demoted: Option<(i32,)> = sqlx::query_as(
+            "UPDATE alliance_members SET role = 'officer' WHERE user_id = $1 AND role = 'owner' \
+            RETURNING alliance_id",
+        )
+        .bind(owner.0 as i64)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some((alliance_id,)) = demoted else {
+            return Ok(false);
+        };
+        let promoted = sqlx::query(
+            "UPDATE alliance_members SET role = 'owner' WHERE alliance_id = $1 AND user_id = $2",
+        )
+        .bind(alliance_id)
+        .bind(new_owner.0 as i64)
+        .execute(&mut *transaction)
+        .await?;
+        if promoted.rows_affected() == 0 {
+            return Ok(false);
+        }
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+    async fn get_membership(
+        &self,
+        user_id: serenity::UserId,
+    ) -> Result<Option<AllianceMember>, crate::Error> {
+        let membership = sqlx::query_as("SELECT * FROM alliance_members WHERE user_id = $1")
             .bind(user_id.0 as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(membership)
+    }
+    async fn get_alliance_members(
+        &self,
+        alliance_id: i32,
+    ) -> Result<Vec<AllianceMember>, crate::Error> {
+        let members = sqlx::query_as(
+            "SELECT * FROM alliance_members WHERE alliance_id = $1 \
+            ORDER BY role = 'owner' DESC, joined_at, user_id",
+        )
+        .bind(alliance_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+    async fn set_member_role(
+        &self,
+        alliance_id: i32,
+        user_id: serenity::UserId,
+        role: AllianceRole,
+    ) -> Result<bool, crate::Error> {
+        let result = sqlx::query(
+            "UPDATE alliance_members SET role = $3 WHERE alliance_id = $1 AND user_id = $2 AND role <> 'owner'",
+        )
+        .bind(alliance_id)
+        .bind(user_id.0 as i64)
+        .bind(role.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+    async fn rename_alliance(&self, alliance_id: i32, name: &str) -> Result<(), crate::Error> {
+        sqlx::query("UPDATE alliances SET name = $2 WHERE alliance_id = $1")
+            .bind(alliance_id)
+            .bind(name)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
-    pub async fn remove_waifu(
+}
+
+#[async_trait]
+impl BattleStore for PostgresConnection {
+    async fn record_battle(
+        &self,
+        challenger_id: i32,
+        defender_id: i32,
+        seed: u64,
+        wager: i32,
+        outcome: &BattleOutcome,
+        wager_action: &LedgerAction,
+    ) -> Result<AllianceBattle, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let battle = sqlx::query_as(
+            "INSERT INTO alliance_battles (challenger_id, defender_id, winner_id, seed, wager, rounds, log, \
+            challenger_roster, defender_roster) VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *",
+        )
+        .bind(challenger_id)
+        .bind(defender_id)
+        .bind(outcome.winner.pick(challenger_id, defender_id))
+        .bind(seed as i64)
+        .bind(wager)
+        .bind(outcome.rounds)
+        .bind(&outcome.log)
+        .bind(&outcome.challenger_roster)
+        .bind(&outcome.defender_roster)
+        .fetch_one(&mut *transaction)
+        .await?;
+        apply_ledger_changes(&mut *transaction, wager_action).await?;
+        transaction.commit().await?;
+
+        Ok(battle)
+    }
+}
+
+#[async_trait]
+impl TreasuryStore for PostgresConnection {
+    async fn get_treasury_movements(
+        &self,
+        alliance_id: i32,
+        limit: i64,
+    ) -> Result<Vec<TreasuryMovement>, crate::Error> {
+        let movements = sqlx::query_as(
+            "SELECT * FROM alliance_treasury_movements WHERE alliance_id = $1 \
+            ORDER BY created_at DESC, movement_id DESC LIMIT $2",
+        )
+        .bind(alliance_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(movements)
+    }
+    async fn get_alliance_upgrades(
+        &self,
+        alliance_id: i32,
+    ) -> Result<HashMap<AllianceUpgrade, i16>, crate::Error> {
+        let rows: Vec<(String, i16)> =
+            sqlx::query_as("SELECT upgrade, level FROM alliance_upgrades WHERE alliance_id = $1")
+                .bind(alliance_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(upgrade, level)| Some((AllianceUpgrade::parse(&upgrade)?, level)))
+            .collect())
+    }
+    async fn buy_alliance_upgrade(
+        &self,
+        alliance_id: i32,
+        upgrade: AllianceUpgrade,
+        level: i16,
+        payment: &LedgerAction,
+    ) -> Result<bool, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        // only moves up from the level the buyer saw, so two officers can't pay for the same level
+        let result = sqlx::query(
+            "INSERT INTO alliance_upgrades (alliance_id, upgrade, level) VALUES($1, $2, $3 + 1) \
+            ON CONFLICT (alliance_id, upgrade) DO UPDATE SET level = alliance_upgrades.level + 1 \
+            WHERE alliance_upgrades.level = $3",
+        )
+        .bind(alliance_id)
+        .bind(upgrade.as_str())
+        .bind(level)
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        apply_ledger_changes(&mut *transaction, payment).await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl BannerStore for PostgresConnection {
+    async fn get_banners(&self) -> Result<Vec<Banner>, crate::Error> {
+        let banners = sqlx::query_as(
+            "SELECT * FROM banners WHERE ends_at > now() ORDER BY starts_at, banner_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(banners)
+    }
+    async fn get_banner(&self, banner_id: i32) -> Result<Option<Banner>, crate::Error> {
+        let banner = sqlx::query_as("SELECT * FROM banners WHERE banner_id = $1")
+            .bind(banner_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(banner)
+    }
+}
+
+#[async_trait]
+impl LeaderboardStore for PostgresConnection {
+    async fn record_guild_member(
         &self,
+        guild_id: serenity::GuildId,
         user_id: serenity::UserId,
-        waifu_id: u16,
     ) -> Result<(), crate::Error> {
-        sqlx::query("UPDATE accounts SET waifus = array_remove(waifus, $1) WHERE user_id = $2")
-            .bind(waifu_id as i16)
-            .bind(user_id.0 as i64)
-            .execute(&self.pool)
-            .await?;
+        sqlx::query(
+            "INSERT INTO guild_members (guild_id, user_id) VALUES($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(user_id.0 as i64)
+        .execute(&self.pool)
+        .await?;
 
         Ok(())
     }
-    pub async fn get_waifus(&self, user_id: serenity::UserId) -> Result<Vec<i16>, crate::Error> {
-        let (waifus,) = sqlx::query_as("SELECT waifus FROM accounts WHERE user_id = $1")
-            .bind(user_id.0 as i64)
-            .fetch_one(&self.pool)
+    async fn get_leaderboard(
+        &self,
+        category: LeaderboardCategory,
+        guild_id: Option<serenity::GuildId>,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>, crate::Error> {
+        // $1 is the guild to restrict the ranking to, or NULL for the global leaderboard
+        let query = match category {
+            LeaderboardCategory::Level => {
+                "SELECT user_id, NULL::TEXT AS name, experience::BIGINT AS value FROM accounts a \
+                WHERE $1::BIGINT IS NULL OR EXISTS (SELECT 1 FROM guild_members g WHERE g.guild_id = $1 AND g.user_id = a.user_id) \
+                ORDER BY experience DESC, user_id LIMIT $2"
+            }
+            LeaderboardCategory::Currency => {
+                "SELECT user_id, NULL::TEXT AS name, currency::BIGINT AS value FROM accounts a \
+                WHERE $1::BIGINT IS NULL OR EXISTS (SELECT 1 FROM guild_members g WHERE g.guild_id = $1 AND g.user_id = a.user_id) \
+                ORDER BY currency DESC, user_id LIMIT $2"
+            }
+            LeaderboardCategory::WaifuCount => {
+                "SELECT user_id, NULL::TEXT AS name, waifu_count::BIGINT AS value FROM accounts a \
+                WHERE waifu_count > 0 AND ($1::BIGINT IS NULL OR EXISTS (SELECT 1 FROM guild_members g WHERE g.guild_id = $1 AND g.user_id = a.user_id)) \
+                ORDER BY waifu_count DESC, user_id LIMIT $2"
+            }
+            LeaderboardCategory::AllianceSize => {
+                "SELECT o.user_id, a.name, a.member_count::BIGINT AS value FROM alliances a \
+                JOIN alliance_members o ON o.alliance_id = a.alliance_id AND o.role = 'owner' \
+                WHERE $1::BIGINT IS NULL OR EXISTS (SELECT 1 FROM guild_members g WHERE g.guild_id = $1 AND g.user_id = o.user_id) \
+                ORDER BY a.member_count DESC, a.alliance_id LIMIT $2"
+            }
+        };
+        let entries = sqlx::query_as(query)
+            .bind(guild_id.map(|g| g.0 as i64))
+            .bind(limit)
+            .fetch_all(&self.pool)
             .await?;
 
-        Ok(waifus)
+        Ok(entries)
+    }
+}
+
+#[async_trait]
+impl MarketStore for PostgresConnection {
+    async fn create_listing(
+        &self,
+        seller: serenity::UserId,
+        instance_id: i64,
+        price: i32,
+        expires_at: DateTime<Utc>,
+        fee_action: &LedgerAction,
+    ) -> Result<MarketListing, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        // an expired listing that wasn't cleaned up yet would block the new one
+        sqlx::query("DELETE FROM market_listings WHERE instance_id = $1 AND expires_at <= now()")
+            .bind(instance_id)
+            .execute(&mut *transaction)
+            .await?;
+        let listing: Option<MarketListing> = sqlx::query_as(
+            "INSERT INTO market_listings (instance_id, seller_id, waifu_id, price, expires_at) \
+            SELECT instance_id, owner_id, waifu_id, $3, $4 FROM owned_waifus WHERE instance_id = $1 AND owner_id = $2 \
+            ON CONFLICT (instance_id) DO NOTHING RETURNING *",
+        )
+        .bind(instance_id)
+        .bind(seller.0 as i64)
+        .bind(price)
+        .bind(expires_at)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(listing) = listing else {
+            return Err(LedgerError {
+                user_id: seller,
+                kind: LedgerKind::Waifu,
+            }
+            .into());
+        };
+
+        apply_ledger_changes(&mut *transaction, fee_action).await?;
+        transaction.commit().await?;
+
+        Ok(listing)
     }
-    pub async fn get_alliance(&self, user_id: serenity::UserId) -> Result<Alliance, crate::Error> {
-        let alliance =
-            sqlx::query_as("SELECT * FROM alliances WHERE owner = $1 OR $1 = ANY(members)")
+    async fn get_listings(
+        &self,
+        filter: &ListingFilter,
+        limit: i64,
+    ) -> Result<Vec<MarketListing>, crate::Error> {
+        let listings = sqlx::query_as(
+            "SELECT * FROM market_listings WHERE expires_at > now() \
+            AND ($1::SMALLINT[] IS NULL OR waifu_id = ANY($1)) \
+            AND ($2::BIGINT IS NULL OR seller_id = $2) \
+            AND ($3::INTEGER IS NULL OR price >= $3) \
+            AND ($4::INTEGER IS NULL OR price <= $4) \
+            ORDER BY listed_at DESC, listing_id DESC LIMIT $5",
+        )
+        .bind(filter.waifu_ids.clone())
+        .bind(filter.seller_id.map(|s| s.0 as i64))
+        .bind(filter.min_price)
+        .bind(filter.max_price)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(listings)
+    }
+    async fn get_listing(&self, listing_id: i64) -> Result<Option<MarketListing>, crate::Error> {
+        let listing = sqlx::query_as(
+            "SELECT * FROM market_listings WHERE listing_id = $1 AND expires_at > now()",
+        )
+        .bind(listing_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(listing)
+    }
+    async fn buy_listing(
+        &self,
+        listing_id: i64,
+        buyer: serenity::UserId,
+        command: &str,
+    ) -> Result<MarketListing, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let listing: Option<MarketListing> = sqlx::query_as(
+            "DELETE FROM market_listings WHERE listing_id = $1 AND expires_at > now() AND seller_id <> $2 RETURNING *",
+        )
+        .bind(listing_id)
+        .bind(buyer.0 as i64)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(listing) = listing else {
+            return Err(ListingUnavailable.into());
+        };
+
+        // the listing is gone, so the waifu left escrow and can be transferred
+        apply_ledger_changes(&mut *transaction, &listing.to_sale_action(buyer, command)).await?;
+        transaction.commit().await?;
+
+        Ok(listing)
+    }
+    async fn cancel_listing(
+        &self,
+        listing_id: i64,
+        seller: serenity::UserId,
+    ) -> Result<Option<MarketListing>, crate::Error> {
+        let listing = sqlx::query_as(
+            "DELETE FROM market_listings WHERE listing_id = $1 AND seller_id = $2 RETURNING *",
+        )
+        .bind(listing_id)
+        .bind(seller.0 as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(listing)
+    }
+    async fn expire_listings(&self) -> Result<u64, crate::Error> {
+        let result = sqlx::query("DELETE FROM market_listings WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl PityStore for PostgresConnection {
+    async fn get_pity(&self, user_id: serenity::UserId, pack: &str) -> Result<i32, crate::Error> {
+        let pulls: Option<(i32,)> =
+            sqlx::query_as("SELECT pulls FROM pity_counters WHERE user_id = $1 AND pack = $2")
                 .bind(user_id.0 as i64)
+                .bind(pack)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(pulls.map_or(0, |(pulls,)| pulls))
+    }
+    async fn record_summon(
+        &self,
+        user_id: serenity::UserId,
+        pack: &str,
+        hit_premium: bool,
+        action: &LedgerAction,
+    ) -> Result<i32, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        apply_ledger_changes(&mut *transaction, action).await?;
+        let (pulls,): (i32,) = sqlx::query_as(
+            "INSERT INTO pity_counters (user_id, pack, pulls) VALUES($1, $2, CASE WHEN $3 THEN 0 ELSE 1 END) \
+            ON CONFLICT (user_id, pack) DO UPDATE SET pulls = CASE WHEN $3 THEN 0 ELSE pity_counters.pulls + 1 END \
+            RETURNING pulls",
+        )
+        .bind(user_id.0 as i64)
+        .bind(pack)
+        .bind(hit_premium)
+        .fetch_one(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(pulls)
+    }
+}
+
+#[async_trait]
+impl PriceStore for PostgresConnection {
+    async fn get_copy_counts(&self) -> Result<Vec<(i16, i64)>, crate::Error> {
+        let counts =
+            sqlx::query_as("SELECT waifu_id, COUNT(*) FROM owned_waifus GROUP BY waifu_id")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(counts)
+    }
+    async fn record_prices(&self, prices: &[(i16, i32)]) -> Result<u64, crate::Error> {
+        let (waifu_ids, prices): (Vec<i16>, Vec<i32>) = prices.iter().copied().unzip();
+        let result = sqlx::query(
+            "INSERT INTO waifu_prices (waifu_id, price) \
+            SELECT p.waifu_id, p.price FROM UNNEST($1::SMALLINT[], $2::INTEGER[]) AS p(waifu_id, price) \
+            WHERE p.price IS DISTINCT FROM \
+            (SELECT h.price FROM waifu_prices h WHERE h.waifu_id = p.waifu_id ORDER BY h.computed_at DESC LIMIT 1)",
+        )
+        .bind(waifu_ids)
+        .bind(prices)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+    async fn get_price(&self, waifu_id: i16) -> Result<Option<WaifuPrice>, crate::Error> {
+        let price = sqlx::query_as(
+            "SELECT * FROM waifu_prices WHERE waifu_id = $1 ORDER BY computed_at DESC LIMIT 1",
+        )
+        .bind(waifu_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(price)
+    }
+    async fn get_prices(&self, waifu_ids: &[i16]) -> Result<Vec<WaifuPrice>, crate::Error> {
+        let prices = sqlx::query_as(
+            "SELECT DISTINCT ON (waifu_id) * FROM waifu_prices WHERE waifu_id = ANY($1) \
+            ORDER BY waifu_id, computed_at DESC",
+        )
+        .bind(waifu_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prices)
+    }
+    async fn get_price_history(
+        &self,
+        waifu_id: i16,
+        limit: i64,
+    ) -> Result<Vec<WaifuPrice>, crate::Error> {
+        let history = sqlx::query_as(
+            "SELECT * FROM waifu_prices WHERE waifu_id = $1 ORDER BY computed_at DESC LIMIT $2",
+        )
+        .bind(waifu_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(history)
+    }
+}
+
+#[async_trait]
+impl ProductStore for PostgresConnection {
+    async fn get_premium_product(&self, price_id: &str) -> Result<PremiumProduct, crate::Error> {
+        let product: PremiumProduct =
+            sqlx::query_as("SELECT * FROM premium_products WHERE product_id = $1")
+                .bind(price_id)
                 .fetch_one(&self.pool)
                 .await?;
 
-        Ok(alliance)
+        Ok(product)
     }
-    pub async fn create_alliance(
+}
+
+#[async_trait]
+impl RewardStore for PostgresConnection {
+    async fn get_reward_claim(
         &self,
         user_id: serenity::UserId,
-        name: &str,
-    ) -> Result<(), crate::Error> {
-        sqlx::query("INSERT INTO alliances VALUES($1, $2)")
+        kind: RewardKind,
+    ) -> Result<Option<RewardClaim>, crate::Error> {
+        let claim = sqlx::query_as("SELECT * FROM reward_claims WHERE user_id = $1 AND kind = $2")
             .bind(user_id.0 as i64)
-            .bind(name)
-            .execute(&self.pool)
+            .bind(kind.as_str())
+            .fetch_optional(&self.pool)
             .await?;
 
-        Ok(())
+        Ok(claim)
     }
-    pub async fn delete_alliance(&self, user_id: serenity::UserId) -> Result<(), crate::Error> {
-        sqlx::query("DELETE FROM alliances WHERE owner = $1")
-            .bind(user_id.0 as i64)
-            .execute(&self.pool)
-            .await?;
+    async fn claim_reward(
+        &self,
+        user_id: serenity::UserId,
+        kind: RewardKind,
+        previous_claimed_at: Option<DateTime<Utc>>,
+        claimed_at: DateTime<Utc>,
+        streak: i32,
+        action: &LedgerAction,
+    ) -> Result<(), crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query(
+            "INSERT INTO reward_claims (user_id, kind, last_claimed_at, streak) VALUES($1, $2, $3, $4) \
+            ON CONFLICT (user_id, kind) DO UPDATE SET last_claimed_at = $3, streak = $4 \
+            WHERE reward_claims.last_claimed_at IS NOT DISTINCT FROM $5",
+        )
+        .bind(user_id.0 as i64)
+        .bind(kind.as_str())
+        .bind(claimed_at)
+        .bind(streak)
+        .bind(previous_claimed_at)
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AlreadyClaimed.into());
+        }
+
+        apply_ledger_changes(&mut *transaction, action).await?;
+        transaction.commit().await?;
 
         Ok(())
     }
-    pub async fn join_alliance(
+}
+
+#[async_trait]
+impl VoteStore for PostgresConnection {
+    async fn cast_vote(
         &self,
-        owner: serenity::UserId,
         user_id: serenity::UserId,
-    ) -> Result<(), crate::Error> {
-        sqlx::query("UPDATE alliances SET members = array_append(members, $1) WHERE owner = $2")
+        waifu_id: i16,
+        vote: Vote,
+    ) -> Result<Option<Vote>, crate::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let previous: Option<(String,)> = sqlx::query_as(
+            "SELECT vote FROM waifu_votes WHERE user_id = $1 AND waifu_id = $2 FOR UPDATE",
+        )
+        .bind(user_id.0 as i64)
+        .bind(waifu_id)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        sqlx::query(
+            "INSERT INTO waifu_votes (user_id, waifu_id, vote) VALUES($1, $2, $3) \
+            ON CONFLICT (user_id, waifu_id) DO UPDATE SET vote = EXCLUDED.vote, voted_at = now()",
+        )
+        .bind(user_id.0 as i64)
+        .bind(waifu_id)
+        .bind(vote.as_str())
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(previous.and_then(|(previous,)| Vote::parse(&previous)))
+    }
+    async fn get_vote_totals(&self, waifu_id: i16) -> Result<VoteTotals, crate::Error> {
+        let (likes, trash): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*) FILTER (WHERE vote = 'like'), COUNT(*) FILTER (WHERE vote = 'trash') \
+            FROM waifu_votes WHERE waifu_id = $1",
+        )
+        .bind(waifu_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(VoteTotals { likes, trash })
+    }
+}
+
+#[async_trait]
+impl WishlistStore for PostgresConnection {
+    async fn add_to_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error> {
+        let result = sqlx::query(
+            "INSERT INTO wishlists (user_id, waifu_id) VALUES($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id.0 as i64)
+        .bind(waifu_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+    async fn remove_from_wishlist(
+        &self,
+        user_id: serenity::UserId,
+        waifu_id: i16,
+    ) -> Result<bool, crate::Error> {
+        let result = sqlx::query("DELETE FROM wishlists WHERE user_id = $1 AND waifu_id = $2")
             .bind(user_id.0 as i64)
-            .bind(owner.0 as i64)
+            .bind(waifu_id)
             .execute(&self.pool)
             .await?;
 
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
-    pub async fn get_premium_product(
+    async fn get_wishlist(&self, user_id: serenity::UserId) -> Result<Vec<i16>, crate::Error> {
+        let wishlist: Vec<(i16,)> = sqlx::query_as(
+            "SELECT waifu_id FROM wishlists WHERE user_id = $1 ORDER BY added_at, waifu_id",
+        )
+        .bind(user_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(wishlist.into_iter().map(|(waifu_id,)| waifu_id).collect())
+    }
+    async fn get_wishlist_counts(
         &self,
-        price_id: &str,
-    ) -> Result<PremiumProduct, crate::Error> {
-        let product: PremiumProduct =
-            sqlx::query_as("SELECT * FROM premium_products WHERE product_id = $1")
-                .bind(price_id)
-                .fetch_one(&self.pool)
+        waifu_ids: &[i16],
+    ) -> Result<Vec<(i16, i64)>, crate::Error> {
+        let counts = sqlx::query_as(
+            "SELECT waifu_id, COUNT(*) FROM wishlists WHERE waifu_id = ANY($1) GROUP BY waifu_id",
+        )
+        .bind(waifu_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+}
+
+/// Selects `Alliance` rows from `alliances a`, with the owner and members gathered from `alliance_members`
+const ALLIANCE_SELECT: &str = "SELECT a.alliance_id, a.name, a.treasury, a.experience, \
+    (SELECT o.user_id FROM alliance_members o WHERE o.alliance_id = a.alliance_id AND o.role = 'owner') AS owner, \
+    ARRAY(SELECT m.user_id FROM alliance_members m WHERE m.alliance_id = a.alliance_id AND m.role <> 'owner' \
+    ORDER BY m.joined_at, m.user_id) AS members \
+    FROM alliances a";
+
+/// Keeps `accounts.waifu_count` in step with a waifu that was just added, removed or transferred
+async fn add_waifu_count(
+    conn: &mut PgConnection,
+    user_id: serenity::UserId,
+    delta: i32,
+) -> Result<(), crate::Error> {
+    sqlx::query("UPDATE accounts SET waifu_count = waifu_count + $1 WHERE user_id = $2")
+        .bind(delta)
+        .bind(user_id.0 as i64)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Keeps `alliances.member_count` in step with a membership that was just deleted
+async fn decrement_member_count(
+    conn: &mut PgConnection,
+    alliance_id: i32,
+) -> Result<(), crate::Error> {
+    sqlx::query("UPDATE alliances SET member_count = member_count - 1 WHERE alliance_id = $1")
+        .bind(alliance_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Takes the user out of their alliance whatever their role, for when their account is deleted.
+/// An owner hands the alliance to its oldest officer, or its oldest member, and the last one out deletes it.
+async fn leave_alliance_for_good(
+    conn: &mut PgConnection,
+    user_id: serenity::UserId,
+) -> Result<(), crate::Error> {
+    let left: Option<(i32, String)> = sqlx::query_as(
+        "DELETE FROM alliance_members WHERE user_id = $1 RETURNING alliance_id, role",
+    )
+    .bind(user_id.0 as i64)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some((alliance_id, role)) = left else {
+        return Ok(());
+    };
+    if role == AllianceRole::Owner.as_str() {
+        let successor: Option<(i64,)> = sqlx::query_as(
+            "UPDATE alliance_members SET role = 'owner' WHERE user_id = \
+            (SELECT user_id FROM alliance_members WHERE alliance_id = $1 \
+            ORDER BY role = 'officer' DESC, joined_at, user_id LIMIT 1) \
+            RETURNING user_id",
+        )
+        .bind(alliance_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if successor.is_none() {
+            sqlx::query("DELETE FROM alliances WHERE alliance_id = $1")
+                .bind(alliance_id)
+                .execute(&mut *conn)
                 .await?;
+            return Ok(());
+        }
+    }
 
-        Ok(product)
+    decrement_member_count(conn, alliance_id).await?;
+
+    Ok(())
+}
+
+/// Condition on `owned_waifus` rows that excludes waifus held in escrow by an active market listing
+const NOT_IN_ESCROW: &str = "NOT EXISTS (SELECT 1 FROM market_listings l \
+    WHERE l.instance_id = owned_waifus.instance_id AND l.expires_at > now())";
+
+/// Applies every change of a ledger action and writes its `ledger_entries` rows.
+/// Balances are never allowed to go negative, and waifus can only be removed from their owners.
+/// Callers are expected to run this inside a transaction so a failed change rolls back the rest.
+async fn apply_ledger_changes(
+    conn: &mut PgConnection,
+    action: &LedgerAction,
+) -> Result<(), crate::Error> {
+    for change in action.changes.iter() {
+        match change {
+            LedgerChange::Balance {
+                user_id,
+                kind,
+                delta,
+            } => {
+                let query = match kind {
+                    LedgerKind::Currency => "UPDATE accounts SET currency = currency + $1 WHERE user_id = $2 AND currency + $1 >= 0",
+                    LedgerKind::PremiumCurrency => "UPDATE accounts SET premium_currency = premium_currency + $1 WHERE user_id = $2 AND premium_currency + $1 >= 0",
+                    LedgerKind::Packs => "UPDATE accounts SET packs = packs + $1 WHERE user_id = $2 AND packs + $1 >= 0",
+                    LedgerKind::PremiumOnePacks => "UPDATE accounts SET premium_one_packs = premium_one_packs + $1 WHERE user_id = $2 AND premium_one_packs + $1 >= 0",
+                    LedgerKind::Experience => "UPDATE accounts SET experience = experience + $1 WHERE user_id = $2",
+                    LedgerKind::Waifu
+                    | LedgerKind::WaifuExperience
+                    | LedgerKind::Treasury
+                    | LedgerKind::AllianceExperience => {
+                        return Err(LedgerError {
+                            user_id: *user_id,
+                            kind: *kind,
+                        }
+                        .into())
+                    }
+                };
+                let result = sqlx::query(query)
+                    .bind(*delta)
+                    .bind(user_id.0 as i64)
+                    .execute(&mut *conn)
+                    .await?;
+                if result.rows_affected() == 0 {
+                    return Err(LedgerError {
+                        user_id: *user_id,
+                        kind: *kind,
+                    }
+                    .into());
+                }
+
+                insert_ledger_entry(conn, action, *user_id, *kind, *delta, None, None).await?;
+            }
+            LedgerChange::AddWaifu { user_id, waifu_id } => {
+                let (instance_id,): (i64,) = sqlx::query_as(
+                    "INSERT INTO owned_waifus (waifu_id, owner_id, source) VALUES($1, $2, $3) RETURNING instance_id",
+                )
+                .bind(*waifu_id as i16)
+                .bind(user_id.0 as i64)
+                .bind(action.reason.as_str())
+                .fetch_one(&mut *conn)
+                .await?;
+
+                insert_ledger_entry(
+                    conn,
+                    action,
+                    *user_id,
+                    LedgerKind::Waifu,
+                    1,
+                    Some(*waifu_id as i16),
+                    Some(instance_id),
+                )
+                .await?;
+                add_waifu_count(conn, *user_id, 1).await?;
+            }
+            LedgerChange::RemoveWaifu {
+                user_id,
+                instance_id,
+            } => {
+                let removed: Option<(i16,)> = sqlx::query_as(
+                    &format!("DELETE FROM owned_waifus WHERE instance_id = $1 AND owner_id = $2 AND {NOT_IN_ESCROW} RETURNING waifu_id"),
+                )
+                .bind(*instance_id)
+                .bind(user_id.0 as i64)
+                .fetch_optional(&mut *conn)
+                .await?;
+                let Some((waifu_id,)) = removed else {
+                    return Err(LedgerError {
+                        user_id: *user_id,
+                        kind: LedgerKind::Waifu,
+                    }
+                    .into());
+                };
+
+                insert_ledger_entry(
+                    conn,
+                    action,
+                    *user_id,
+                    LedgerKind::Waifu,
+                    -1,
+                    Some(waifu_id),
+                    Some(*instance_id),
+                )
+                .await?;
+                add_waifu_count(conn, *user_id, -1).await?;
+            }
+            LedgerChange::WaifuExperience {
+                user_id,
+                instance_id,
+                amount,
+            } => {
+                let updated: Option<(i16,)> = sqlx::query_as(
+                    &format!("UPDATE owned_waifus SET experience = experience + $1 WHERE instance_id = $2 AND owner_id = $3 AND {NOT_IN_ESCROW} RETURNING waifu_id"),
+                )
+                .bind(*amount)
+                .bind(*instance_id)
+                .bind(user_id.0 as i64)
+                .fetch_optional(&mut *conn)
+                .await?;
+                let Some((waifu_id,)) = updated else {
+                    return Err(LedgerError {
+                        user_id: *user_id,
+                        kind: LedgerKind::WaifuExperience,
+                    }
+                    .into());
+                };
+
+                insert_ledger_entry(
+                    conn,
+                    action,
+                    *user_id,
+                    LedgerKind::WaifuExperience,
+                    *amount,
+                    Some(waifu_id),
+                    Some(*instance_id),
+                )
+                .await?;
+            }
+            LedgerChange::TransferWaifu {
+                from,
+                to,
+                instance_id,
+            } => {
+                let transferred: Option<(i16,)> = sqlx::query_as(&format!(
+                    "UPDATE owned_waifus SET owner_id = $1, acquired_at = now(), source = $2 \
+                    WHERE instance_id = $3 AND owner_id = $4 AND {NOT_IN_ESCROW} RETURNING waifu_id"
+                ))
+                .bind(to.0 as i64)
+                .bind(action.reason.as_str())
+                .bind(*instance_id)
+                .bind(from.0 as i64)
+                .fetch_optional(&mut *conn)
+                .await?;
+                let Some((waifu_id,)) = transferred else {
+                    return Err(LedgerError {
+                        user_id: *from,
+                        kind: LedgerKind::Waifu,
+                    }
+                    .into());
+                };
+
+                for (user_id, delta) in [(*from, -1), (*to, 1)] {
+                    insert_ledger_entry(
+                        conn,
+                        action,
+                        user_id,
+                        LedgerKind::Waifu,
+                        delta,
+                        Some(waifu_id),
+                        Some(*instance_id),
+                    )
+                    .await?;
+                    add_waifu_count(conn, user_id, delta).await?;
+                }
+            }
+            LedgerChange::Treasury {
+                user_id,
+                alliance_id,
+                delta,
+            } => {
+                let result = sqlx::query(
+                    "UPDATE alliances SET treasury = treasury + $1 WHERE alliance_id = $2 AND treasury + $1 >= 0",
+                )
+                .bind(*delta)
+                .bind(*alliance_id)
+                .execute(&mut *conn)
+                .await?;
+                if result.rows_affected() == 0 {
+                    return Err(LedgerError {
+                        user_id: *user_id,
+                        kind: LedgerKind::Treasury,
+                    }
+                    .into());
+                }
+                sqlx::query(
+                    "INSERT INTO alliance_treasury_movements (alliance_id, user_id, delta, reason) VALUES($1, $2, $3, $4)",
+                )
+                .bind(*alliance_id)
+                .bind(user_id.0 as i64)
+                .bind(*delta)
+                .bind(action.reason.as_str())
+                .execute(&mut *conn)
+                .await?;
+
+                insert_ledger_entry(
+                    conn,
+                    action,
+                    *user_id,
+                    LedgerKind::Treasury,
+                    *delta,
+                    None,
+                    None,
+                )
+                .await?;
+            }
+            LedgerChange::AllianceExperience { user_id, amount } => {
+                let result = sqlx::query(
+                    "UPDATE alliances SET experience = experience + $1 \
+                    WHERE alliance_id = (SELECT alliance_id FROM alliance_members WHERE user_id = $2)",
+                )
+                .bind(*amount)
+                .bind(user_id.0 as i64)
+                .execute(&mut *conn)
+                .await?;
+                if result.rows_affected() > 0 {
+                    insert_ledger_entry(
+                        conn,
+                        action,
+                        *user_id,
+                        LedgerKind::AllianceExperience,
+                        *amount,
+                        None,
+                        None,
+                    )
+                    .await?;
+                }
+            }
+        }
     }
+
+    Ok(())
+}
+
+async fn insert_ledger_entry(
+    conn: &mut PgConnection,
+    action: &LedgerAction,
+    user_id: serenity::UserId,
+    kind: LedgerKind,
+    delta: i32,
+    waifu_id: Option<i16>,
+    instance_id: Option<i64>,
+) -> Result<(), crate::Error> {
+    sqlx::query(
+        "INSERT INTO ledger_entries (user_id, kind, delta, waifu_id, instance_id, reason, command) VALUES($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(user_id.0 as i64)
+    .bind(kind.as_str())
+    .bind(delta)
+    .bind(waifu_id)
+    .bind(instance_id)
+    .bind(action.reason.as_str())
+    .bind(&action.command)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
 }
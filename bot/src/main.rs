@@ -6,18 +6,90 @@ mod database;
 mod models;
 mod utils;
 
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
 use poise::serenity_prelude::{self as serenity, GuildId};
+use tokio::sync::RwLock;
 
 use checks::CheckCache;
-use database::{mongo::MongoConnection, postgres::PostgresConnection};
+use database::{
+    memory::{MemoryCatalog, MemoryStore},
+    mongo::MongoConnection,
+    postgres::PostgresConnection,
+    AccountStore, AchievementStore, AllianceStore, BannerStore, BattleStore, LeaderboardStore,
+    MarketStore, PityStore, PriceStore, ProductStore, RewardStore, TreasuryStore, VoteStore,
+    WaifuCatalog, WishlistStore,
+};
+use models::{achievement::CollectionSet, catalog_index::CatalogIndex, trade::TradeBook};
 
 pub struct Data {
-    postgres: PostgresConnection,
-    mongo: MongoConnection,
+    accounts: Arc<dyn AccountStore>,
+    alliances: Arc<dyn AllianceStore>,
+    treasury: Arc<dyn TreasuryStore>,
+    battles: Arc<dyn BattleStore>,
+    achievements: Arc<dyn AchievementStore>,
+    products: Arc<dyn ProductStore>,
+    rewards: Arc<dyn RewardStore>,
+    leaderboards: Arc<dyn LeaderboardStore>,
+    banners: Arc<dyn BannerStore>,
+    wishlists: Arc<dyn WishlistStore>,
+    votes: Arc<dyn VoteStore>,
+    market: Arc<dyn MarketStore>,
+    pity: Arc<dyn PityStore>,
+    prices: Arc<dyn PriceStore>,
+    catalog: Arc<dyn WaifuCatalog>,
+    catalog_index: Arc<CatalogIndex>,
+    collection_sets: Arc<RwLock<Vec<CollectionSet>>>,
     check_cache: CheckCache,
+    trade_book: TradeBook,
     http: reqwest::Client,
     conf: config::Config,
 } // User data, which is stored and accessible in all command invocations
+impl Data {
+    /// Every store is backed by the same backend, only the catalog lives elsewhere
+    fn new<S>(store: Arc<S>, catalog: Arc<dyn WaifuCatalog>, conf: config::Config) -> Self
+    where
+        S: AccountStore
+            + AchievementStore
+            + AllianceStore
+            + TreasuryStore
+            + BattleStore
+            + ProductStore
+            + RewardStore
+            + LeaderboardStore
+            + MarketStore
+            + PityStore
+            + BannerStore
+            + WishlistStore
+            + VoteStore
+            + PriceStore
+            + 'static,
+    {
+        Self {
+            accounts: store.clone(),
+            alliances: store.clone(),
+            treasury: store.clone(),
+            battles: store.clone(),
+            achievements: store.clone(),
+            products: store.clone(),
+            rewards: store.clone(),
+            leaderboards: store.clone(),
+            banners: store.clone(),
+            wishlists: store.clone(),
+            votes: store.clone(),
+            market: store.clone(),
+            pity: store.clone(),
+            prices: store,
+            catalog,
+            catalog_index: Arc::new(CatalogIndex::new()),
+            collection_sets: Arc::new(RwLock::new(vec![])),
+            check_cache: CheckCache::new(),
+            trade_book: TradeBook::new(),
+            http: reqwest::Client::new(),
+            conf,
+        }
+    }
+}
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 pub type Command = poise::Command<Data, Error>;
@@ -26,11 +98,25 @@ pub type Command = poise::Command<Data, Error>;
 async fn main() {
     let cli_args: Vec<String> = std::env::args().collect();
     let should_resync = cli_args.contains(&String::from("--resync"));
+    // runs without Postgres or Mongo, see `database::memory`
+    let use_memory = cli_args.contains(&String::from("--memory"));
 
     let conf = config::Config::read();
+    if cli_args.get(1).map(String::as_str) == Some("migrate") {
+        let postgres_connection = PostgresConnection::connect(&conf.postgres).await;
+        postgres_connection
+            .migrate()
+            .await
+            .expect("Failed to run POSTGRES migrations");
+        println!("Migrations applied");
+        return;
+    }
+
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: commands::commands(),
+            pre_command: |ctx| Box::pin(commands::track_guild_member(ctx)),
+            post_command: |ctx| Box::pin(commands::after_command(ctx)),
             ..Default::default()
         })
         .token(&conf.discord.token)
@@ -53,18 +139,115 @@ async fn main() {
                 let activity = serenity::Activity::playing("with 15,000 waifus");
                 ctx.set_activity(activity).await;
 
-                let postgres_connection = PostgresConnection::connect(&conf.postgres).await;
-                let mongo_connection = MongoConnection::connect(&conf.mongo).await;
-                Ok(Data {
-                    postgres: postgres_connection,
-                    mongo: mongo_connection,
-                    check_cache: CheckCache::new(),
-                    http: reqwest::Client::new(),
-                    conf: conf.clone(),
-                })
+                let data = if use_memory {
+                    println!("Using in-memory storage, nothing will be persisted!");
+                    let store = Arc::new(MemoryStore::new());
+                    let catalog = Arc::new(MemoryCatalog::from_file("waifus.json"));
+                    Data::new(store, catalog, conf.clone())
+                } else {
+                    let postgres_connection =
+                        Arc::new(PostgresConnection::connect(&conf.postgres).await);
+                    postgres_connection.migrate().await?;
+                    let mongo_connection = Arc::new(MongoConnection::connect(&conf.mongo).await?);
+                    Data::new(postgres_connection, mongo_connection, conf.clone())
+                };
+                spawn_market_expiry(data.market.clone());
+                spawn_catalog_index(data.catalog.clone(), data.catalog_index.clone());
+                spawn_collection_sets(data.achievements.clone(), data.collection_sets.clone());
+                spawn_price_recompute(
+                    data.catalog.clone(),
+                    data.prices.clone(),
+                    data.conf.pricing.recompute_minutes,
+                );
+
+                Ok(data)
             })
         });
 
     println!("Running bot");
     framework.run().await.expect("Framework cannot run the bot");
 }
+
+/// Regularly deletes expired market listings. Their waifus already left escrow when they expired,
+/// this only keeps the table small.
+fn spawn_market_expiry(market: Arc<dyn MarketStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+        loop {
+            interval.tick().await;
+            match market.expire_listings().await {
+                Ok(0) => {}
+                Ok(expired) => println!("Expired {expired} market listings"),
+                Err(e) => println!("Failed to expire market listings: {e}"),
+            }
+        }
+    });
+}
+
+/// Keeps the autocomplete index in step with the catalog, building it right away on startup
+fn spawn_catalog_index(catalog: Arc<dyn WaifuCatalog>, index: Arc<CatalogIndex>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+        loop {
+            interval.tick().await;
+            match catalog.get_all_waifus().await {
+                Ok(waifus) => index.rebuild(&waifus).await,
+                Err(e) => println!("Failed to rebuild the catalog index: {e}"),
+            }
+        }
+    });
+}
+
+/// Keeps the cached collection sets in step with the database, loading them right away on startup
+fn spawn_collection_sets(
+    achievements: Arc<dyn AchievementStore>,
+    collection_sets: Arc<RwLock<Vec<CollectionSet>>>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+        loop {
+            interval.tick().await;
+            match achievements.get_collection_sets().await {
+                Ok(sets) => *collection_sets.write().await = sets,
+                Err(e) => println!("Failed to refresh the collection sets: {e}"),
+            }
+        }
+    });
+}
+
+/// Regularly recomputes every waifu's price from its rarity, votes and the copies players own,
+/// starting right away so prices exist before anyone sells
+fn spawn_price_recompute(
+    catalog: Arc<dyn WaifuCatalog>,
+    prices: Arc<dyn PriceStore>,
+    recompute_minutes: u64,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * recompute_minutes));
+        loop {
+            interval.tick().await;
+            match recompute_prices(catalog.as_ref(), prices.as_ref()).await {
+                Ok(0) => {}
+                Ok(changed) => println!("Updated {changed} waifu prices"),
+                Err(e) => println!("Failed to recompute waifu prices: {e}"),
+            }
+        }
+    });
+}
+
+async fn recompute_prices(
+    catalog: &dyn WaifuCatalog,
+    prices: &dyn PriceStore,
+) -> Result<u64, Error> {
+    let waifus = catalog.get_all_waifus().await?;
+    let copies: HashMap<i16, i64> = prices.get_copy_counts().await?.into_iter().collect();
+    let computed: Vec<(i16, i32)> = waifus
+        .iter()
+        .map(|w| {
+            let copies = copies.get(&(w._id as i16)).copied().unwrap_or(0);
+            (w._id as i16, models::price::compute_price(w, copies))
+        })
+        .collect();
+
+    prices.record_prices(&computed).await
+}
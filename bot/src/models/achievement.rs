@@ -0,0 +1,170 @@
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::reward::Reward;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Achievement {
+    FirstSummon,
+    FirstTrade,
+    FirstAlliance,
+    Collector10,
+    Collector50,
+    Collector100,
+    Level10,
+}
+impl Achievement {
+    pub const ALL: [Achievement; 7] = [
+        Self::FirstSummon,
+        Self::FirstTrade,
+        Self::FirstAlliance,
+        Self::Collector10,
+        Self::Collector50,
+        Self::Collector100,
+        Self::Level10,
+    ];
+
+    /// The value stored in the `achievement` column of `achievements`
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::FirstSummon => "first_summon",
+            Self::FirstTrade => "first_trade",
+            Self::FirstAlliance => "first_alliance",
+            Self::Collector10 => "collector_10",
+            Self::Collector50 => "collector_50",
+            Self::Collector100 => "collector_100",
+            Self::Level10 => "level_10",
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FirstSummon => "First Summon",
+            Self::FirstTrade => "Fair Trade",
+            Self::FirstAlliance => "Better Together",
+            Self::Collector10 => "Collector",
+            Self::Collector50 => "Curator",
+            Self::Collector100 => "Archivist",
+            Self::Level10 => "Veteran",
+        }
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::FirstSummon => "Open your first pack",
+            Self::FirstTrade => "Complete a trade with another player",
+            Self::FirstAlliance => "Create or join an alliance",
+            Self::Collector10 => "Own 10 waifus",
+            Self::Collector50 => "Own 50 waifus",
+            Self::Collector100 => "Own 100 waifus",
+            Self::Level10 => "Reach level 10",
+        }
+    }
+    pub fn reward(&self) -> Reward {
+        let (currency, packs, premium_one_packs) = match self {
+            Self::FirstSummon => (250, 0, 0),
+            Self::FirstTrade => (250, 1, 0),
+            Self::FirstAlliance => (500, 0, 0),
+            Self::Collector10 => (500, 1, 0),
+            Self::Collector50 => (1500, 3, 0),
+            Self::Collector100 => (3000, 0, 1),
+            Self::Level10 => (1000, 0, 1),
+        };
+
+        Reward {
+            currency,
+            packs,
+            premium_one_packs,
+        }
+    }
+    /// Whether the player's current state earns this achievement.
+    /// Summons and trades aren't visible in it, those achievements are unlocked where they happen.
+    pub fn is_met(&self, progress: &Progress) -> bool {
+        match self {
+            Self::FirstSummon | Self::FirstTrade => false,
+            Self::FirstAlliance => progress.in_alliance,
+            Self::Collector10 => progress.waifu_count >= 10,
+            Self::Collector50 => progress.waifu_count >= 50,
+            Self::Collector100 => progress.waifu_count >= 100,
+            Self::Level10 => progress.level >= 10,
+        }
+    }
+}
+
+/// What achievements are checked against after every command
+pub struct Progress {
+    pub waifu_count: usize,
+    pub level: i32,
+    pub in_alliance: bool,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct UnlockedAchievement {
+    pub user_id: i64,
+    pub achievement: String,
+    pub unlocked_at: DateTime<Utc>,
+}
+
+/// A group of waifus that pays out once a player owns all of them
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct CollectionSet {
+    pub set_id: i32,
+    pub name: String,
+    pub description: String,
+    pub waifu_ids: Vec<i16>,
+    pub reward_currency: i32,
+    pub reward_packs: i16,
+    pub reward_premium_one_packs: i16,
+}
+impl CollectionSet {
+    /// The value stored in the `achievement` column of `achievements` once the set is complete
+    pub fn key(&self) -> String {
+        format!("set-{}", self.set_id)
+    }
+    pub fn reward(&self) -> Reward {
+        Reward {
+            currency: self.reward_currency,
+            packs: self.reward_packs,
+            premium_one_packs: self.reward_premium_one_packs,
+        }
+    }
+    /// How many of the set's waifus are among `owned`
+    pub fn progress(&self, owned: &[i16]) -> usize {
+        self.waifu_ids
+            .iter()
+            .filter(|id| owned.contains(id))
+            .count()
+    }
+    /// A set without waifus is never complete, so a half-configured set can't pay out for free
+    pub fn is_complete(&self, owned: &[i16]) -> bool {
+        !self.waifu_ids.is_empty() && self.progress(owned) == self.waifu_ids.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(waifu_ids: Vec<i16>) -> CollectionSet {
+        CollectionSet {
+            set_id: 1,
+            name: String::from("Twins"),
+            description: String::new(),
+            waifu_ids,
+            reward_currency: 500,
+            reward_packs: 1,
+            reward_premium_one_packs: 0,
+        }
+    }
+
+    #[test]
+    fn set_completes_once_every_waifu_is_owned() {
+        let twins = set(vec![7, 8]);
+        assert!(!twins.is_complete(&[7, 7, 9]));
+        assert_eq!(twins.progress(&[7, 7, 9]), 1);
+        assert!(twins.is_complete(&[9, 8, 7]));
+    }
+
+    #[test]
+    fn empty_set_is_never_complete() {
+        assert!(!set(vec![]).is_complete(&[]));
+        assert!(!set(vec![]).is_complete(&[7]));
+    }
+}
@@ -0,0 +1,214 @@
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// Experience an alliance needs for every level
+pub const ALLIANCE_LEVEL_EXPERIENCE: i32 = 1000;
+/// Alliance experience for every waifu a member summons
+pub const SUMMON_ALLIANCE_EXPERIENCE: i32 = 25;
+/// Alliance experience for every daily reward a member claims
+pub const DAILY_ALLIANCE_EXPERIENCE: i32 = 50;
+/// No level lets an alliance grow past this many players
+pub const MAX_MEMBER_CAP: i32 = 20;
+
+/// Alliances start at level 1 and gain one every [`ALLIANCE_LEVEL_EXPERIENCE`]
+pub fn alliance_level_for(experience: i32) -> i32 {
+    1 + experience / ALLIANCE_LEVEL_EXPERIENCE
+}
+
+/// How many players an alliance of this level holds, the owner included
+pub fn member_cap(level: i32) -> usize {
+    (4 + level).min(MAX_MEMBER_CAP) as usize
+}
+
+/// The percentage taken off `/shop packs`, 5% at level 5 and 10% from level 10
+pub fn pack_discount(level: i32) -> i32 {
+    (level / 5 * 5).min(10)
+}
+
+/// Takes `percent` off the price, rounding in the player's favour
+pub fn apply_discount(price: i32, percent: i32) -> i32 {
+    price * (100 - percent) / 100
+}
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct Alliance {
+    pub alliance_id: i32,
+    pub name: String,
+    pub owner: i64,
+    /// Everyone but the owner, in the order they joined
+    pub members: Vec<i64>,
+    /// Currency shared by the alliance
+    pub treasury: i32,
+    pub experience: i32,
+}
+impl Alliance {
+    pub fn level(&self) -> i32 {
+        alliance_level_for(self.experience)
+    }
+    /// Every player in the alliance, the owner included
+    pub fn size(&self) -> usize {
+        self.members.len() + 1
+    }
+    pub fn is_full(&self) -> bool {
+        self.size() >= member_cap(self.level())
+    }
+}
+
+/// Ordered from least to most trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AllianceRole {
+    Member,
+    Officer,
+    Owner,
+}
+impl AllianceRole {
+    /// The value stored in the `role` column of `alliance_members`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Member => "member",
+            Self::Officer => "officer",
+            Self::Owner => "owner",
+        }
+    }
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "member" => Some(Self::Member),
+            "officer" => Some(Self::Officer),
+            "owner" => Some(Self::Owner),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Member => "Member",
+            Self::Officer => "Officer",
+            Self::Owner => "Owner",
+        }
+    }
+    /// Which role may do what
+    pub fn can(&self, permission: AlliancePermission) -> bool {
+        match permission {
+            AlliancePermission::Invite | AlliancePermission::Kick | AlliancePermission::Spend => {
+                *self >= Self::Officer
+            }
+            AlliancePermission::Rename | AlliancePermission::ManageRoles => *self == Self::Owner,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlliancePermission {
+    Invite,
+    Kick,
+    Rename,
+    /// Spending anything the alliance owns together
+    Spend,
+    /// Promoting and demoting members
+    ManageRoles,
+}
+impl AlliancePermission {
+    /// Completes "You can't ..."
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Invite => "invite people",
+            Self::Kick => "kick people",
+            Self::Rename => "rename the alliance",
+            Self::Spend => "spend the alliance's resources",
+            Self::ManageRoles => "promote or demote people",
+        }
+    }
+}
+
+/// One player's membership of an alliance
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct AllianceMember {
+    pub user_id: i64,
+    pub alliance_id: i32,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+impl AllianceMember {
+    pub fn role(&self) -> AllianceRole {
+        AllianceRole::parse(&self.role).unwrap_or(AllianceRole::Member)
+    }
+}
+
+/// Something an alliance buys with its treasury, one level at a time
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AllianceUpgrade {
+    Stipend,
+    Pantry,
+}
+impl AllianceUpgrade {
+    pub const ALL: [Self; 2] = [Self::Stipend, Self::Pantry];
+    pub const MAX_LEVEL: i16 = 5;
+
+    /// The value stored in the `upgrade` column of `alliance_upgrades`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stipend => "stipend",
+            Self::Pantry => "pantry",
+        }
+    }
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "stipend" => Some(Self::Stipend),
+            "pantry" => Some(Self::Pantry),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stipend => "Stipend",
+            Self::Pantry => "Pantry",
+        }
+    }
+    pub fn describe(&self, level: i16) -> String {
+        match self {
+            Self::Stipend => format!(
+                "+{} :coin: on every member's daily reward",
+                self.bonus(level)
+            ),
+            Self::Pantry => format!(
+                "+{}% experience when members feed their waifus",
+                self.bonus(level)
+            ),
+        }
+    }
+    /// What the upgrade gives at `level`, coins for the stipend and a percentage for the pantry
+    pub fn bonus(&self, level: i16) -> i32 {
+        match self {
+            Self::Stipend => 25 * level as i32,
+            Self::Pantry => 10 * level as i32,
+        }
+    }
+    /// What it costs the treasury to reach `level`
+    pub fn cost(&self, level: i16) -> i32 {
+        match self {
+            Self::Stipend => 1000 * level as i32,
+            Self::Pantry => 1500 * level as i32,
+        }
+    }
+}
+
+/// A deposit, withdrawal or upgrade paid out of an alliance's treasury
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TreasuryMovement {
+    pub alliance_id: i32,
+    /// The member who moved the currency
+    pub user_id: i64,
+    pub delta: i32,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+impl TreasuryMovement {
+    pub fn describe(&self) -> &str {
+        match self.reason.as_str() {
+            "alliance_deposit" => "deposited",
+            "alliance_withdrawal" => "withdrew",
+            "alliance_upgrade" => "bought an upgrade for",
+            "alliance_battle" if self.delta > 0 => "won a battle worth",
+            "alliance_battle" => "lost a battle worth",
+            reason => reason,
+        }
+    }
+}
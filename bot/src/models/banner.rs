@@ -0,0 +1,96 @@
+use poise::serenity_prelude as serenity;
+use rand::{thread_rng, Rng};
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::{models::waifu::Waifu, utils::ToEmbed};
+
+/// A time-boxed summon event that rates up a featured set of waifus
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct Banner {
+    pub banner_id: i32,
+    pub name: String,
+    pub description: String,
+    pub featured_waifus: Vec<i16>,
+    /// Chance in percent that each waifu of a pack is drawn from the featured set
+    pub rate_up: i32,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub pack_name: Option<String>,
+    pub pack_price: Option<i32>,
+    pub pack_size: Option<i32>,
+}
+impl Banner {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+    /// The banner's own pack, if it has one
+    pub fn pack(&self) -> Option<BannerPack> {
+        Some(BannerPack {
+            name: self.pack_name.clone()?,
+            price: self.pack_price?,
+            size: self.pack_size?,
+        })
+    }
+    /// Rolls how many of the `count` waifus of a pack come from the featured set
+    pub fn roll_featured(&self, count: u32) -> u32 {
+        let mut rng = thread_rng();
+        (0..count)
+            .filter(|_| rng.gen_range(0..100) < self.rate_up)
+            .count() as u32
+    }
+}
+
+/// A pack only available on its banner, bought with currency when summoning
+pub struct BannerPack {
+    pub name: String,
+    pub price: i32,
+    pub size: i32,
+}
+
+/// A banner together with its featured waifus, for `/banners`
+pub struct BannerEntry {
+    pub banner: Banner,
+    pub featured: Vec<Waifu>,
+}
+impl ToEmbed for BannerEntry {
+    fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
+        let featured: Vec<String> = self
+            .featured
+            .iter()
+            .map(|w| format!("{} **`{}`**", w.rarity.icon(), w.name))
+            .collect();
+        let featured = if featured.is_empty() {
+            String::from("None")
+        } else {
+            featured.join("\n")
+        };
+        let schedule = if self.banner.is_active(Utc::now()) {
+            format!("Ends <t:{}:R>", self.banner.ends_at.timestamp())
+        } else {
+            format!(
+                "Starts <t:{}:R>, ends <t:{}:f>",
+                self.banner.starts_at.timestamp(),
+                self.banner.ends_at.timestamp()
+            )
+        };
+        let pack = match self.banner.pack() {
+            Some(pack) => format!(
+                "**{}** - {} waifus for :coin: {}",
+                pack.name, pack.size, pack.price
+            ),
+            None => String::from("Use any of your packs"),
+        };
+
+        if let Some(waifu) = self.featured.first() {
+            ce.thumbnail(waifu.download_url());
+        }
+        ce.title(&self.banner.name)
+            .description(&self.banner.description)
+            .field("Featured", featured, false)
+            .field("Rate-up", format!("{}%", self.banner.rate_up), true)
+            .field("Schedule", schedule, true)
+            .field("Pack", pack, false)
+            .footer(|cf| cf.text(format!("Banner #{}", self.banner.banner_id)))
+            .colour(serenity::Colour::FABLED_PINK)
+    }
+}
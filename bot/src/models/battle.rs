@@ -0,0 +1,269 @@
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::waifu::{level_for, Waifu};
+
+/// How many waifus each alliance fields in a battle
+pub const ROSTER_SIZE: usize = 5;
+/// Battles still standing after this many rounds go to the side with more health left
+pub const MAX_ROUNDS: i16 = 30;
+
+/// One waifu fighting for an alliance
+#[derive(Debug, Clone)]
+pub struct Fighter {
+    pub instance_id: i64,
+    pub name: String,
+    pub power: i32,
+    pub health: i32,
+}
+impl Fighter {
+    /// Net likes make a waifu hit harder, an experienced owner makes it last longer
+    pub fn new(instance_id: i64, waifu: &Waifu, owner_experience: i32) -> Self {
+        let votes = (waifu.likes as i64 - waifu.trash as i64).clamp(0, 500) as i32;
+        let owner_level = level_for(owner_experience).clamp(0, 25);
+        Self {
+            instance_id,
+            name: waifu.name.clone(),
+            power: 10 + votes / 10,
+            health: 100 + 10 * owner_level,
+        }
+    }
+    fn rating(&self) -> i32 {
+        self.power * self.health
+    }
+}
+
+/// The strongest [`ROSTER_SIZE`] fighters, oldest copies first when they're equally strong
+pub fn pick_roster(mut fighters: Vec<Fighter>) -> Vec<Fighter> {
+    fighters.sort_by_key(|f| (-f.rating(), f.instance_id));
+    fighters.truncate(ROSTER_SIZE);
+    fighters
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Challenger,
+    Defender,
+}
+impl Side {
+    pub fn pick<T>(&self, challenger: T, defender: T) -> T {
+        match self {
+            Self::Challenger => challenger,
+            Self::Defender => defender,
+        }
+    }
+}
+
+pub struct BattleOutcome {
+    pub winner: Side,
+    pub rounds: i16,
+    pub log: Vec<String>,
+    pub challenger_roster: Vec<i64>,
+    pub defender_roster: Vec<i64>,
+}
+
+/// SplitMix64, so a seed replays the same battle whatever version of `rand` is in use
+struct BattleRng(u64);
+impl BattleRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// A number in `0..bound`
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Fights the battle out, the same rosters and seed always give the same outcome.
+/// Each round the challengers attack first, every standing fighter hitting a random
+/// standing opponent. A hit deals 80% to 120% of the attacker's power, doubled on a
+/// critical hit, or nothing when it misses.
+pub fn simulate(challenger: &[Fighter], defender: &[Fighter], seed: u64) -> BattleOutcome {
+    let mut rng = BattleRng(seed);
+    let mut health = [
+        challenger.iter().map(|f| f.health).collect::<Vec<i32>>(),
+        defender.iter().map(|f| f.health).collect::<Vec<i32>>(),
+    ];
+    let fighters = [challenger, defender];
+    let mut log = vec![];
+    let mut rounds = 0;
+    // a side without fighters loses without a fight, the defender holds its ground if neither has any
+    let mut winner = if challenger.is_empty() {
+        Some(Side::Defender)
+    } else if defender.is_empty() {
+        Some(Side::Challenger)
+    } else {
+        None
+    };
+
+    while winner.is_none() && rounds < MAX_ROUNDS {
+        rounds += 1;
+        for attacking in 0..2 {
+            let defending = 1 - attacking;
+            for (attacker, fighter) in fighters[attacking].iter().enumerate() {
+                if health[attacking][attacker] <= 0 {
+                    continue;
+                }
+                let standing: Vec<usize> = (0..fighters[defending].len())
+                    .filter(|i| health[defending][*i] > 0)
+                    .collect();
+                if standing.is_empty() {
+                    break;
+                }
+                let target = standing[rng.below(standing.len())];
+                let attacker_name = &fighter.name;
+                let target_name = &fighters[defending][target].name;
+
+                let roll = rng.below(100);
+                let mut damage = fighter.power * (80 + rng.below(41) as i32) / 100;
+                if roll < 5 {
+                    log.push(format!(
+                        "R{rounds}: **{attacker_name}** missed **{target_name}**"
+                    ));
+                    continue;
+                } else if roll < 15 {
+                    damage *= 2;
+                    log.push(format!(
+                        "R{rounds}: **{attacker_name}** critically hit **{target_name}** for {damage}"
+                    ));
+                } else {
+                    log.push(format!(
+                        "R{rounds}: **{attacker_name}** hit **{target_name}** for {damage}"
+                    ));
+                }
+
+                health[defending][target] -= damage;
+                if health[defending][target] <= 0 {
+                    log.push(format!("R{rounds}: **{target_name}** was knocked out!"));
+                }
+            }
+            if health[defending].iter().all(|h| *h <= 0) {
+                winner = Some(if attacking == 0 {
+                    Side::Challenger
+                } else {
+                    Side::Defender
+                });
+                break;
+            }
+        }
+    }
+
+    let winner = winner.unwrap_or_else(|| {
+        let remaining = |side: usize| health[side].iter().map(|h| (*h).max(0)).sum::<i32>();
+        // the defender holds its ground on a tie
+        if remaining(0) > remaining(1) {
+            Side::Challenger
+        } else {
+            Side::Defender
+        }
+    });
+
+    BattleOutcome {
+        winner,
+        rounds,
+        log,
+        challenger_roster: challenger.iter().map(|f| f.instance_id).collect(),
+        defender_roster: defender.iter().map(|f| f.instance_id).collect(),
+    }
+}
+
+/// A fought battle between two alliances, with everything needed to replay it
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct AllianceBattle {
+    pub battle_id: i64,
+    pub challenger_id: i32,
+    pub defender_id: i32,
+    pub winner_id: i32,
+    pub seed: i64,
+    /// Currency the loser's treasury paid the winner's
+    pub wager: i32,
+    pub rounds: i16,
+    pub log: Vec<String>,
+    /// Instance ids of the waifus each side fielded
+    pub challenger_roster: Vec<i64>,
+    pub defender_roster: Vec<i64>,
+    pub fought_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fighter(instance_id: i64, power: i32, health: i32) -> Fighter {
+        Fighter {
+            instance_id,
+            name: format!("Waifu {instance_id}"),
+            power,
+            health,
+        }
+    }
+
+    fn rosters() -> (Vec<Fighter>, Vec<Fighter>) {
+        let challenger = (1..=5).map(|i| fighter(i, 10 + i as i32, 120)).collect();
+        let defender = (6..=10)
+            .map(|i| fighter(i, 12, 100 + i as i32 * 5))
+            .collect();
+        (challenger, defender)
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_battle() {
+        let (challenger, defender) = rosters();
+        for seed in [0, 42, u64::MAX] {
+            let first = simulate(&challenger, &defender, seed);
+            let replay = simulate(&challenger, &defender, seed);
+            assert_eq!(first.winner, replay.winner);
+            assert_eq!(first.rounds, replay.rounds);
+            assert_eq!(first.log, replay.log);
+            assert!(!first.log.is_empty());
+            assert!((1..=MAX_ROUNDS).contains(&first.rounds));
+        }
+    }
+
+    #[test]
+    fn empty_roster_loses() {
+        let (challenger, defender) = rosters();
+
+        let outcome = simulate(&[], &defender, 7);
+        assert_eq!(outcome.winner, Side::Defender);
+        assert!(outcome.log.is_empty());
+        let outcome = simulate(&challenger, &[], 7);
+        assert_eq!(outcome.winner, Side::Challenger);
+        assert!(outcome.log.is_empty());
+        assert_eq!(simulate(&[], &[], 7).winner, Side::Defender);
+    }
+
+    #[test]
+    fn overwhelming_side_wins() {
+        let (challenger, _) = rosters();
+        let defender = vec![fighter(6, 1000, 10_000)];
+        for seed in 0..20 {
+            assert_eq!(
+                simulate(&challenger, &defender, seed).winner,
+                Side::Defender
+            );
+        }
+    }
+
+    #[test]
+    fn roster_keeps_the_strongest_oldest_first() {
+        let fighters = vec![
+            fighter(1, 10, 100),
+            fighter(2, 50, 100),
+            fighter(3, 20, 100),
+            fighter(4, 50, 100),
+            fighter(5, 10, 100),
+            fighter(6, 30, 100),
+            fighter(7, 40, 100),
+        ];
+        let roster: Vec<i64> = pick_roster(fighters)
+            .iter()
+            .map(|f| f.instance_id)
+            .collect();
+        assert_eq!(roster, vec![2, 4, 7, 6, 3]);
+        assert!(pick_roster(vec![]).is_empty());
+    }
+}
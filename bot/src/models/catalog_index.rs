@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use tokio::sync::RwLock;
+
+use super::waifu::{search_rank_lowercase, Rarity, Waifu};
+
+/// Rank given to names that only contain the query's characters in order, after every [`search_rank`]
+///
+/// [`search_rank`]: super::waifu::search_rank
+const FUZZY_RANK: u8 = 4;
+
+/// How well a name matches what the player typed so far, lower is better.
+/// Falls back to fuzzy matching so typos like skipped letters still find the waifu.
+pub fn match_rank(name: &str, partial: &str) -> Option<u8> {
+    match_rank_lowercase(&name.to_lowercase(), &partial.trim().to_lowercase())
+}
+
+/// [`match_rank`] for a name and partial query that are already lowercased, and trimmed for the query
+fn match_rank_lowercase(name: &str, partial: &str) -> Option<u8> {
+    search_rank_lowercase(name, partial).or_else(|| {
+        let mut chars = name.chars();
+        let is_subsequence = partial
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .all(|c| chars.any(|n| n == c));
+        is_subsequence.then_some(FUZZY_RANK)
+    })
+}
+
+struct IndexEntry {
+    waifu_id: i16,
+    name: String,
+    /// The name lowercased once when building the index, entries are sorted by it
+    key: String,
+    rarity: Rarity,
+}
+
+#[derive(Default)]
+struct Entries {
+    sorted: Vec<IndexEntry>,
+    /// Where each waifu sits in `sorted`
+    positions: HashMap<i16, usize>,
+}
+
+/// Names of every catalog waifu kept in memory, so autocomplete doesn't query the catalog on every keystroke
+pub struct CatalogIndex {
+    entries: RwLock<Entries>,
+}
+impl CatalogIndex {
+    pub fn new() -> Self {
+        Self {
+            entries: RwLock::new(Entries::default()),
+        }
+    }
+    pub async fn rebuild(&self, waifus: &[Waifu]) {
+        let mut sorted: Vec<IndexEntry> = waifus
+            .iter()
+            .map(|w| IndexEntry {
+                waifu_id: w._id as i16,
+                name: w.name.clone(),
+                key: w.name.to_lowercase(),
+                rarity: w.rarity,
+            })
+            .collect();
+        sorted.sort_by(|a, b| a.key.cmp(&b.key));
+        let positions = sorted
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.waifu_id, i))
+            .collect();
+
+        *self.entries.write().await = Entries { sorted, positions };
+    }
+    /// Names of the waifus, in the order of `waifu_ids`. Unknown ids are left out.
+    pub async fn names(&self, waifu_ids: &[i16]) -> Vec<(i16, String)> {
+        let entries = self.entries.read().await;
+        waifu_ids
+            .iter()
+            .filter_map(|id| {
+                let entry = &entries.sorted[*entries.positions.get(id)?];
+                Some((entry.waifu_id, entry.name.clone()))
+            })
+            .collect()
+    }
+    /// Ids of every waifu of the rarity, so listings can be filtered by it without asking the catalog
+    pub async fn ids_with_rarity(&self, rarity: Rarity) -> Vec<i16> {
+        let entries = self.entries.read().await;
+        entries
+            .sorted
+            .iter()
+            .filter(|e| e.rarity == rarity)
+            .map(|e| e.waifu_id)
+            .collect()
+    }
+    /// The `limit` waifus best matching what the player typed so far, waifus in `owned` first
+    pub async fn complete(&self, partial: &str, owned: &[i16], limit: usize) -> Vec<(i16, String)> {
+        let entries = self.entries.read().await;
+        let partial = partial.trim().to_lowercase();
+
+        // owned waifus are few, so they're ranked one by one
+        let mut owned_matches: Vec<(u8, usize)> = owned
+            .iter()
+            .filter_map(|id| entries.positions.get(id))
+            .filter_map(|i| Some((match_rank_lowercase(&entries.sorted[*i].key, &partial)?, *i)))
+            .collect();
+        // positions follow the names, so ties stay alphabetical, and copies of a waifu collapse
+        owned_matches.sort();
+        owned_matches.dedup();
+        let mut picked: Vec<usize> = owned_matches
+            .into_iter()
+            .map(|(_, i)| i)
+            .take(limit)
+            .collect();
+
+        // names starting with the query are a contiguous range of the sorted entries, exact match first
+        let start = entries
+            .sorted
+            .partition_point(|e| e.key.as_str() < partial.as_str());
+        for (i, _) in entries
+            .sorted
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take_while(|(_, e)| e.key.starts_with(&partial))
+        {
+            if picked.len() >= limit {
+                break;
+            }
+            if !picked.contains(&i) {
+                picked.push(i);
+            }
+        }
+
+        // word, substring and fuzzy matches need every name, so they're only looked for when prefixes fall short
+        if picked.len() < limit {
+            let mut rest: Vec<(u8, usize)> = entries
+                .sorted
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !picked.contains(i))
+                .filter_map(|(i, e)| Some((match_rank_lowercase(&e.key, &partial)?, i)))
+                .collect();
+            rest.sort();
+            let missing = limit - picked.len();
+            picked.extend(rest.into_iter().map(|(_, i)| i).take(missing));
+        }
+
+        picked
+            .into_iter()
+            .map(|i| {
+                let entry = &entries.sorted[i];
+                (entry.waifu_id, entry.name.clone())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{models::waifu::level_for, utils::ToEmbed};
+
+/// How many players each page of a leaderboard shows
+pub const PAGE_SIZE: usize = 10;
+
+/// What players are ranked by
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderboardCategory {
+    Level,
+    Currency,
+    #[name = "Waifu Count"]
+    WaifuCount,
+    #[name = "Alliance Size"]
+    AllianceSize,
+}
+impl LeaderboardCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Level => "Level",
+            Self::Currency => "Currency",
+            Self::WaifuCount => "Waifu Count",
+            Self::AllianceSize => "Alliance Size",
+        }
+    }
+    fn format_value(&self, value: i64) -> String {
+        match self {
+            Self::Level => format!("Level {} ({} XP)", level_for(value as i32), value),
+            Self::Currency => format!(":coin: {}", value),
+            Self::WaifuCount => format!("{} waifus", value),
+            Self::AllianceSize => format!("{} members", value),
+        }
+    }
+}
+
+/// One ranked player, or alliance owner for [`LeaderboardCategory::AllianceSize`]
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub user_id: i64,
+    /// The alliance name, only set for alliance rankings
+    pub name: Option<String>,
+    pub value: i64,
+}
+
+/// A page of a leaderboard, as shown by the paginator
+pub struct LeaderboardPage {
+    pub title: String,
+    pub category: LeaderboardCategory,
+    pub entries: Vec<LeaderboardEntry>,
+    /// Rank of the first entry on this page
+    pub first_rank: usize,
+    pub page: usize,
+    pub pages: usize,
+}
+impl LeaderboardPage {
+    /// Splits a ranked list into pages of [`PAGE_SIZE`]
+    pub fn paginate(
+        title: &str,
+        category: LeaderboardCategory,
+        entries: Vec<LeaderboardEntry>,
+    ) -> Vec<Self> {
+        let pages = (entries.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+        entries
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .map(|(page, chunk)| Self {
+                title: title.into(),
+                category,
+                entries: chunk.to_vec(),
+                first_rank: page * PAGE_SIZE + 1,
+                page: page + 1,
+                pages,
+            })
+            .collect()
+    }
+}
+
+impl ToEmbed for LeaderboardPage {
+    fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let holder = match &entry.name {
+                    Some(name) => format!("**`{}`** (<@{}>)", name, entry.user_id),
+                    None => format!("<@{}>", entry.user_id),
+                };
+                format!(
+                    "**#{}** {} - {}",
+                    self.first_rank + index,
+                    holder,
+                    self.category.format_value(entry.value)
+                )
+            })
+            .collect();
+
+        ce.title(format!("{} - {}", self.title, self.category.name()))
+            .description(lines.join("\n"))
+            .footer(|cf| cf.text(format!("Page {}/{}", self.page, self.pages)))
+            .colour(serenity::Colour::GOLD)
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// What a ledger entry changes on an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerKind {
+    Currency,
+    PremiumCurrency,
+    Packs,
+    PremiumOnePacks,
+    Experience,
+    Waifu,
+    WaifuExperience,
+    Treasury,
+    AllianceExperience,
+}
+impl LedgerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Currency => "currency",
+            Self::PremiumCurrency => "premium_currency",
+            Self::Packs => "packs",
+            Self::PremiumOnePacks => "premium_one_packs",
+            Self::Experience => "experience",
+            Self::Waifu => "waifu",
+            Self::WaifuExperience => "waifu_experience",
+            Self::Treasury => "treasury",
+            Self::AllianceExperience => "alliance_experience",
+        }
+    }
+}
+
+/// Why a set of ledger entries was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerReason {
+    Achievement,
+    AllianceBattle,
+    AllianceDeposit,
+    AllianceUpgrade,
+    AllianceWithdrawal,
+    MarketListing,
+    MarketSale,
+    PackPurchase,
+    PremiumRedeem,
+    Reward,
+    Summon,
+    Trade,
+    WaifuFeed,
+    WaifuPurchase,
+    WaifuSale,
+}
+impl LedgerReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Achievement => "achievement",
+            Self::AllianceBattle => "alliance_battle",
+            Self::AllianceDeposit => "alliance_deposit",
+            Self::AllianceUpgrade => "alliance_upgrade",
+            Self::AllianceWithdrawal => "alliance_withdrawal",
+            Self::MarketListing => "market_listing",
+            Self::MarketSale => "market_sale",
+            Self::PackPurchase => "pack_purchase",
+            Self::PremiumRedeem => "premium_redeem",
+            Self::Reward => "reward",
+            Self::Summon => "summon",
+            Self::Trade => "trade",
+            Self::WaifuFeed => "waifu_feed",
+            Self::WaifuPurchase => "waifu_purchase",
+            Self::WaifuSale => "waifu_sale",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LedgerChange {
+    /// Adds `delta` to one of the account's balances
+    Balance {
+        user_id: serenity::UserId,
+        kind: LedgerKind,
+        delta: i32,
+    },
+    /// Gives the user a new copy of a waifu
+    AddWaifu {
+        user_id: serenity::UserId,
+        waifu_id: u16,
+    },
+    /// Takes an owned waifu away from the user
+    RemoveWaifu {
+        user_id: serenity::UserId,
+        instance_id: i64,
+    },
+    /// Adds experience to an owned waifu
+    WaifuExperience {
+        user_id: serenity::UserId,
+        instance_id: i64,
+        amount: i32,
+    },
+    /// Hands an owned waifu over to another user, keeping the instance
+    TransferWaifu {
+        from: serenity::UserId,
+        to: serenity::UserId,
+        instance_id: i64,
+    },
+    /// Adds `delta` to an alliance's treasury, recorded as a movement by the user
+    Treasury {
+        user_id: serenity::UserId,
+        alliance_id: i32,
+        delta: i32,
+    },
+    /// Adds experience to the user's alliance, nothing happens if they aren't in one
+    AllianceExperience {
+        user_id: serenity::UserId,
+        amount: i32,
+    },
+}
+
+/// A single economic action. Every change is applied in one database transaction,
+/// either all of them are written or none are.
+#[derive(Debug, Clone)]
+pub struct LedgerAction {
+    pub reason: LedgerReason,
+    pub command: String,
+    pub changes: Vec<LedgerChange>,
+}
+impl LedgerAction {
+    pub fn new(reason: LedgerReason, command: &str) -> Self {
+        Self {
+            reason,
+            command: command.into(),
+            changes: vec![],
+        }
+    }
+    fn push(mut self, user_id: serenity::UserId, kind: LedgerKind, delta: i32) -> Self {
+        if delta != 0 {
+            self.changes.push(LedgerChange::Balance {
+                user_id,
+                kind,
+                delta,
+            });
+        }
+        self
+    }
+    pub fn currencies(
+        self,
+        user_id: serenity::UserId,
+        currency: i32,
+        premium_currency: i32,
+    ) -> Self {
+        self.push(user_id, LedgerKind::Currency, currency).push(
+            user_id,
+            LedgerKind::PremiumCurrency,
+            premium_currency,
+        )
+    }
+    pub fn packs(self, user_id: serenity::UserId, packs: i16, premium_one_packs: i16) -> Self {
+        self.push(user_id, LedgerKind::Packs, packs.into()).push(
+            user_id,
+            LedgerKind::PremiumOnePacks,
+            premium_one_packs.into(),
+        )
+    }
+    pub fn experience(self, user_id: serenity::UserId, amount: i32) -> Self {
+        self.push(user_id, LedgerKind::Experience, amount)
+    }
+    pub fn add_waifu(mut self, user_id: serenity::UserId, waifu_id: u16) -> Self {
+        self.changes
+            .push(LedgerChange::AddWaifu { user_id, waifu_id });
+        self
+    }
+    pub fn remove_waifu(mut self, user_id: serenity::UserId, instance_id: i64) -> Self {
+        self.changes.push(LedgerChange::RemoveWaifu {
+            user_id,
+            instance_id,
+        });
+        self
+    }
+    pub fn waifu_experience(
+        mut self,
+        user_id: serenity::UserId,
+        instance_id: i64,
+        amount: i32,
+    ) -> Self {
+        self.changes.push(LedgerChange::WaifuExperience {
+            user_id,
+            instance_id,
+            amount,
+        });
+        self
+    }
+    pub fn transfer_waifu(
+        mut self,
+        from: serenity::UserId,
+        to: serenity::UserId,
+        instance_id: i64,
+    ) -> Self {
+        self.changes.push(LedgerChange::TransferWaifu {
+            from,
+            to,
+            instance_id,
+        });
+        self
+    }
+    pub fn treasury(mut self, user_id: serenity::UserId, alliance_id: i32, delta: i32) -> Self {
+        if delta != 0 {
+            self.changes.push(LedgerChange::Treasury {
+                user_id,
+                alliance_id,
+                delta,
+            });
+        }
+        self
+    }
+    pub fn alliance_experience(mut self, user_id: serenity::UserId, amount: i32) -> Self {
+        self.changes
+            .push(LedgerChange::AllianceExperience { user_id, amount });
+        self
+    }
+    /// Users gaining or losing waifus when this action is applied
+    pub fn inventory_owners(&self) -> Vec<serenity::UserId> {
+        let mut owners = vec![];
+        for change in &self.changes {
+            match change {
+                LedgerChange::AddWaifu { user_id, .. }
+                | LedgerChange::RemoveWaifu { user_id, .. } => owners.push(*user_id),
+                LedgerChange::TransferWaifu { from, to, .. } => owners.extend([*from, *to]),
+                _ => {}
+            }
+        }
+        owners.sort();
+        owners.dedup();
+        owners
+    }
+}
+
+/// A row of `ledger_entries`, one for every balance or waifu a change touched
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct LedgerEntry {
+    pub entry_id: i64,
+    /// Shared by every entry written by the same action
+    pub transaction_id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub delta: i32,
+    pub waifu_id: Option<i16>,
+    pub instance_id: Option<i64>,
+    pub reason: String,
+    pub command: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returned when a change cannot be applied, for example when it would make a balance
+/// negative or remove a waifu the user doesn't own. Nothing from the action is written.
+#[derive(Debug)]
+pub struct LedgerError {
+    pub user_id: serenity::UserId,
+    pub kind: LedgerKind,
+}
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ledger change to {} could not be applied for user {}",
+            self.kind.as_str(),
+            self.user_id.0
+        )
+    }
+}
+impl std::error::Error for LedgerError {}
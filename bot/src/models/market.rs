@@ -0,0 +1,108 @@
+use std::fmt;
+
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::{
+    models::{
+        ledger::{LedgerAction, LedgerReason},
+        waifu::Waifu,
+    },
+    utils::ToEmbed,
+};
+
+/// A waifu put up for sale. While the listing is active the waifu is held in escrow:
+/// it stays owned by the seller but can't be used, traded or sold until the listing ends.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct MarketListing {
+    pub listing_id: i64,
+    pub instance_id: i64,
+    pub seller_id: i64,
+    pub waifu_id: i16,
+    pub price: i32,
+    pub listed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+impl MarketListing {
+    pub fn seller(&self) -> serenity::UserId {
+        serenity::UserId(self.seller_id as u64)
+    }
+    /// Pays the seller and hands the waifu over to the buyer
+    pub fn to_sale_action(&self, buyer: serenity::UserId, command: &str) -> LedgerAction {
+        LedgerAction::new(LedgerReason::MarketSale, command)
+            .currencies(buyer, -self.price, 0)
+            .currencies(self.seller(), self.price, 0)
+            .transfer_waifu(self.seller(), buyer, self.instance_id)
+    }
+}
+
+/// The part of a listing's price that is taken when it is listed and never refunded
+pub fn listing_fee(price: i32, fee_percent: i32) -> i32 {
+    (i64::from(price) * i64::from(fee_percent) / 100) as i32
+}
+
+/// Narrows down active listings, every `None` matches everything
+#[derive(Debug, Clone, Default)]
+pub struct ListingFilter {
+    pub waifu_ids: Option<Vec<i16>>,
+    pub seller_id: Option<serenity::UserId>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+impl ListingFilter {
+    pub fn matches(&self, listing: &MarketListing) -> bool {
+        self.waifu_ids
+            .as_ref()
+            .map_or(true, |ids| ids.contains(&listing.waifu_id))
+            && self
+                .seller_id
+                .map_or(true, |seller| seller.0 as i64 == listing.seller_id)
+            && self.min_price.map_or(true, |min| listing.price >= min)
+            && self.max_price.map_or(true, |max| listing.price <= max)
+    }
+}
+
+/// A listing together with the catalog entry of its waifu, for browsing the market
+pub struct MarketEntry {
+    pub listing: MarketListing,
+    pub waifu: Waifu,
+}
+impl MarketEntry {
+    /// Pairs each listing with its catalog entry, dropping listings of waifus missing from the catalog
+    pub fn join(listings: Vec<MarketListing>, waifus: &[Waifu]) -> Vec<Self> {
+        listings
+            .into_iter()
+            .filter_map(|listing| {
+                let waifu = waifus.iter().find(|w| w._id as i16 == listing.waifu_id)?;
+                Some(Self {
+                    listing,
+                    waifu: waifu.clone(),
+                })
+            })
+            .collect()
+    }
+}
+impl ToEmbed for MarketEntry {
+    fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
+        self.waifu
+            .to_embed(ce)
+            .field("Price", format!(":coin: {}", self.listing.price), true)
+            .field("Seller", format!("<@{}>", self.listing.seller_id), true)
+            .field(
+                "Expires",
+                format!("<t:{}:R>", self.listing.expires_at.timestamp()),
+                true,
+            )
+            .footer(|cf| cf.text(format!("Listing #{}", self.listing.listing_id)))
+    }
+}
+
+/// Returned when a listing was bought, cancelled or expired before it could be bought
+#[derive(Debug)]
+pub struct ListingUnavailable;
+impl fmt::Display for ListingUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "listing is no longer available")
+    }
+}
+impl std::error::Error for ListingUnavailable {}
@@ -1,2 +1,15 @@
 pub mod account;
+pub mod achievement;
+pub mod alliance;
+pub mod banner;
+pub mod battle;
+pub mod catalog_index;
+pub mod leaderboard;
+pub mod ledger;
+pub mod market;
+pub mod pity;
+pub mod price;
+pub mod reward;
+pub mod trade;
+pub mod vote;
 pub mod waifu;
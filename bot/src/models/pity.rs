@@ -0,0 +1,250 @@
+use serde::Deserialize;
+
+use crate::models::waifu::{DropTable, Rarity, Waifu};
+
+/// Guarantees a premium waifu after too many packs without one
+#[derive(Clone, Deserialize)]
+pub struct Pity {
+    /// Packs opened in a row without a premium waifu until the next pack guarantees one, `0` disables pity
+    pub threshold: i32,
+    /// Rarities that count as premium
+    pub rarities: Vec<Rarity>,
+}
+impl Pity {
+    pub fn is_premium(&self, rarity: Rarity) -> bool {
+        self.rarities.contains(&rarity)
+    }
+    /// Whether the pack has a premium waifu, which resets the counter
+    pub fn hits_premium(&self, waifus: &[Waifu]) -> bool {
+        waifus.iter().any(|w| self.is_premium(w.rarity))
+    }
+    /// Whether the next pack guarantees a premium waifu, given how many packs went without one
+    pub fn guarantees_next(&self, pulls: i32) -> bool {
+        self.threshold > 0 && !self.rarities.is_empty() && pulls + 1 >= self.threshold
+    }
+    /// The drop table of the guaranteed waifu, which only rolls premium rarities
+    pub fn premium_table(&self, table: &DropTable) -> DropTable {
+        let weight = |rarity: Rarity| {
+            if self.is_premium(rarity) {
+                table.weight(rarity)
+            } else {
+                0
+            }
+        };
+        let restricted = DropTable {
+            common: weight(Rarity::Common),
+            uncommon: weight(Rarity::Uncommon),
+            rare: weight(Rarity::Rare),
+            epic: weight(Rarity::Epic),
+            legendary: weight(Rarity::Legendary),
+        };
+        if Rarity::ALL.iter().any(|r| restricted.weight(*r) > 0) {
+            return restricted;
+        }
+
+        // the pack never drops premium rarities on its own, so they're all equally likely
+        let even = |rarity: Rarity| u32::from(self.is_premium(rarity));
+        DropTable {
+            common: even(Rarity::Common),
+            uncommon: even(Rarity::Uncommon),
+            rare: even(Rarity::Rare),
+            epic: even(Rarity::Epic),
+            legendary: even(Rarity::Legendary),
+        }
+    }
+    /// Progress towards the guarantee with the counter at `pulls`
+    pub fn describe(&self, pulls: i32) -> String {
+        if self.threshold <= 0 || self.rarities.is_empty() {
+            return String::from("This pack has no pity.");
+        }
+
+        let premium: Vec<String> = self
+            .rarities
+            .iter()
+            .map(|r| format!("{} {}", r.icon(), r.name()))
+            .collect();
+        let premium = premium.join(" or ");
+        if self.guarantees_next(pulls) {
+            format!(
+                "**{pulls}/{}** packs without a {premium} waifu. Your next pack is guaranteed to have one!",
+                self.threshold
+            )
+        } else {
+            format!(
+                "**{pulls}/{}** packs without a {premium} waifu. One is guaranteed in **{}** packs.",
+                self.threshold,
+                self.threshold - pulls
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude as serenity;
+
+    use super::*;
+    use crate::{
+        database::{
+            memory::{MemoryCatalog, MemoryStore},
+            AccountStore, PityStore, WaifuCatalog,
+        },
+        models::ledger::{LedgerAction, LedgerReason},
+    };
+
+    fn pity(threshold: i32) -> Pity {
+        Pity {
+            threshold,
+            rarities: vec![Rarity::Epic, Rarity::Legendary],
+        }
+    }
+
+    fn waifu(_id: u16, rarity: Rarity) -> Waifu {
+        Waifu {
+            _id,
+            name: format!("Waifu {_id}"),
+            description: String::new(),
+            gdrive_id: String::new(),
+            likes: 0,
+            trash: 0,
+            rarity,
+        }
+    }
+
+    /// A pack that only drops commons, so only the guarantee can bring a premium waifu
+    const COMMONS_ONLY: DropTable = DropTable {
+        common: 1,
+        uncommon: 0,
+        rare: 0,
+        epic: 0,
+        legendary: 0,
+    };
+
+    /// Opens `packs` one-waifu packs the way `/summon` does, keeping every waifu.
+    /// Returns the packs that were guaranteed and whether each of them had a premium waifu.
+    async fn open_packs(
+        pity: &Pity,
+        catalog: &MemoryCatalog,
+        store: &MemoryStore,
+        packs: usize,
+    ) -> Vec<(usize, bool)> {
+        let user_id = serenity::UserId(1);
+        let action = LedgerAction::new(LedgerReason::Summon, "test");
+        let mut owned: Vec<i16> = vec![];
+        let mut pulls = store.get_pity(user_id, "standard").await.unwrap();
+        let mut guaranteed_packs = vec![];
+        for pack in 1..=packs {
+            let guaranteed = pity.guarantees_next(pulls);
+            let table = if guaranteed {
+                pity.premium_table(&COMMONS_ONLY)
+            } else {
+                COMMONS_ONLY
+            };
+            let drawn = catalog
+                .get_random_waifus(1, &owned, &table, &[])
+                .await
+                .unwrap();
+            owned.extend(drawn.iter().map(|w| w._id as i16));
+            let hit_premium = pity.hits_premium(&drawn);
+            if guaranteed {
+                guaranteed_packs.push((pack, hit_premium));
+            }
+            pulls = store
+                .record_summon(user_id, "standard", hit_premium, &action)
+                .await
+                .unwrap();
+        }
+
+        guaranteed_packs
+    }
+
+    #[test]
+    fn guarantee_fires_on_exactly_the_threshold_pack() {
+        let pity = pity(10);
+        // the counter holds the packs opened so far, so the 10th pack opens with 9 on it
+        assert!(!pity.guarantees_next(8));
+        assert!(pity.guarantees_next(9));
+    }
+
+    #[test]
+    fn guarantee_never_fires_when_disabled() {
+        assert!(!pity(0).guarantees_next(100));
+        let no_rarities = Pity {
+            threshold: 10,
+            rarities: vec![],
+        };
+        assert!(!no_rarities.guarantees_next(100));
+    }
+
+    #[test]
+    fn premium_table_only_rolls_premium_rarities() {
+        let table = DropTable {
+            common: 60,
+            uncommon: 25,
+            rare: 10,
+            epic: 4,
+            legendary: 1,
+        };
+        let premium = pity(10).premium_table(&table);
+        assert_eq!(premium.chance(Rarity::Common), 0.0);
+        assert_eq!(premium.chance(Rarity::Rare), 0.0);
+        assert_eq!(premium.weight(Rarity::Epic), 4);
+        assert_eq!(premium.weight(Rarity::Legendary), 1);
+
+        // a pack that never drops them on its own still guarantees one of them
+        let commons_only = DropTable {
+            common: 1,
+            uncommon: 0,
+            rare: 0,
+            epic: 0,
+            legendary: 0,
+        };
+        let premium = pity(10).premium_table(&commons_only);
+        assert_eq!(premium.weight(Rarity::Common), 0);
+        assert_eq!(
+            premium.weight(Rarity::Epic),
+            premium.weight(Rarity::Legendary)
+        );
+        assert!(premium.weight(Rarity::Epic) > 0);
+    }
+
+    #[tokio::test]
+    async fn guaranteed_packs_draw_a_premium_waifu_and_reset_the_counter() {
+        let store = MemoryStore::new();
+        store.register_account(serenity::UserId(1)).await.unwrap();
+        let mut waifus: Vec<Waifu> = (1..=10).map(|id| waifu(id, Rarity::Common)).collect();
+        waifus.extend((11..=13).map(|id| waifu(id, Rarity::Epic)));
+        waifus.extend((14..=16).map(|id| waifu(id, Rarity::Legendary)));
+        let catalog = MemoryCatalog::new(waifus);
+
+        let guaranteed_packs = open_packs(&pity(3), &catalog, &store, 7).await;
+
+        assert_eq!(guaranteed_packs, vec![(3, true), (6, true)]);
+        let pulls = store
+            .get_pity(serenity::UserId(1), "standard")
+            .await
+            .unwrap();
+        assert_eq!(pulls, 1);
+    }
+
+    #[tokio::test]
+    async fn guarantee_stays_pending_when_no_premium_waifu_is_left() {
+        let store = MemoryStore::new();
+        store.register_account(serenity::UserId(1)).await.unwrap();
+        let mut waifus: Vec<Waifu> = (1..=10).map(|id| waifu(id, Rarity::Common)).collect();
+        waifus.push(waifu(11, Rarity::Epic));
+        let catalog = MemoryCatalog::new(waifus);
+        let pity = pity(3);
+
+        // the only epic is drawn by the first guarantee, the second can't find one
+        let guaranteed_packs = open_packs(&pity, &catalog, &store, 7).await;
+
+        assert_eq!(guaranteed_packs, vec![(3, true), (6, false), (7, false)]);
+        let pulls = store
+            .get_pity(serenity::UserId(1), "standard")
+            .await
+            .unwrap();
+        assert_eq!(pulls, 4);
+        assert!(pity.guarantees_next(pulls));
+    }
+}
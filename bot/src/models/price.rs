@@ -0,0 +1,69 @@
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::waifu::{Rarity, Waifu};
+use crate::utils::ToEmbed;
+
+/// Prices never drop below this, however trashed or common a waifu is
+pub const MIN_PRICE: i32 = 25;
+/// How many times its sell price a waifu costs when bought straight from the shop
+pub const SHOP_MARKUP: i32 = 2;
+/// Currency worth one premium currency, going by the pack prices
+pub const PREMIUM_EXCHANGE_RATE: i32 = 5;
+
+/// A waifu's price as of one recompute
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WaifuPrice {
+    pub waifu_id: i16,
+    pub price: i32,
+    pub computed_at: DateTime<Utc>,
+}
+
+pub fn base_price(rarity: Rarity) -> i32 {
+    match rarity {
+        Rarity::Common => 150,
+        Rarity::Uncommon => 250,
+        Rarity::Rare => 450,
+        Rarity::Epic => 800,
+        Rarity::Legendary => 1500,
+    }
+}
+
+/// What the game pays for a waifu, starting from its rarity's base price.
+/// Each net like or trash moves the price by 1% of the base, between half and double,
+/// and each copy players own takes another 2% off, down to half.
+pub fn compute_price(waifu: &Waifu, copies: i64) -> i32 {
+    let base = base_price(waifu.rarity) as f64;
+    let votes = waifu.likes as i64 - waifu.trash as i64;
+    let popularity = (1.0 + votes as f64 / 100.0).clamp(0.5, 2.0);
+    let scarcity = (1.0 - copies as f64 / 50.0).max(0.5);
+
+    ((base * popularity * scarcity).round() as i32).max(MIN_PRICE)
+}
+
+/// Premium currency needed to buy a waifu with this sell price from the shop
+pub fn shop_price(price: i32) -> i32 {
+    let currency = price * SHOP_MARKUP;
+    (currency + PREMIUM_EXCHANGE_RATE - 1) / PREMIUM_EXCHANGE_RATE
+}
+
+/// How much the price moved since the one before it, given history newest first
+pub fn price_trend(history: &[WaifuPrice]) -> Option<i32> {
+    match history {
+        [current, previous, ..] => Some(current.price - previous.price),
+        _ => None,
+    }
+}
+
+/// A catalog waifu with its current sell price
+pub struct PricedWaifu {
+    pub waifu: Waifu,
+    pub price: i32,
+}
+impl ToEmbed for PricedWaifu {
+    fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
+        self.waifu
+            .to_embed(ce)
+            .field("Sell Price", format!("{} :coin:", self.price), true)
+    }
+}
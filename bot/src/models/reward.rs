@@ -0,0 +1,192 @@
+use std::fmt;
+
+use sqlx::types::chrono::{DateTime, Duration, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RewardKind {
+    Daily,
+    Weekly,
+}
+impl RewardKind {
+    /// The value stored in the `kind` column of `reward_claims`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Daily => "Daily",
+            Self::Weekly => "Weekly",
+        }
+    }
+    /// How long to wait between claims
+    pub fn period(&self) -> Duration {
+        match self {
+            Self::Daily => Duration::days(1),
+            Self::Weekly => Duration::weeks(1),
+        }
+    }
+    /// How late a claim can be after the period ended without breaking the streak
+    pub fn grace(&self) -> Duration {
+        match self {
+            Self::Daily => Duration::days(1),
+            Self::Weekly => Duration::days(3),
+        }
+    }
+    /// Rewards grow with the streak up to a cap, with packs on milestone claims
+    pub fn reward(&self, streak: i32) -> Reward {
+        match self {
+            Self::Daily => Reward {
+                currency: 150 + 25 * (streak - 1).min(10),
+                packs: if streak % 7 == 0 { 1 } else { 0 },
+                premium_one_packs: if streak % 30 == 0 { 1 } else { 0 },
+            },
+            Self::Weekly => Reward {
+                currency: 1000 + 150 * (streak - 1).min(8),
+                packs: 2,
+                premium_one_packs: if streak % 4 == 0 { 1 } else { 0 },
+            },
+        }
+    }
+    pub fn status(&self, claim: Option<&RewardClaim>, now: DateTime<Utc>) -> ClaimStatus {
+        let Some(claim) = claim else {
+            return ClaimStatus::Ready { streak: 1 };
+        };
+
+        let available_at = claim.last_claimed_at + self.period();
+        if now < available_at {
+            ClaimStatus::Cooldown { available_at }
+        } else if now <= available_at + self.grace() {
+            ClaimStatus::Ready {
+                streak: claim.streak + 1,
+            }
+        } else {
+            ClaimStatus::Ready { streak: 1 }
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct RewardClaim {
+    pub user_id: i64,
+    pub kind: String,
+    pub last_claimed_at: DateTime<Utc>,
+    pub streak: i32,
+}
+
+pub enum ClaimStatus {
+    /// The reward can be claimed, continuing (or restarting) the streak at `streak`
+    Ready {
+        streak: i32,
+    },
+    Cooldown {
+        available_at: DateTime<Utc>,
+    },
+}
+
+pub struct Reward {
+    pub currency: i32,
+    pub packs: i16,
+    pub premium_one_packs: i16,
+}
+impl Reward {
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("**{}** :coin:", self.currency)];
+        if self.packs > 0 {
+            parts.push(format!("**{}** Packs", self.packs));
+        }
+        if self.premium_one_packs > 0 {
+            parts.push(format!("**{}** Gold Packs", self.premium_one_packs));
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Returned when a claim races another claim of the same reward
+#[derive(Debug)]
+pub struct AlreadyClaimed;
+impl fmt::Display for AlreadyClaimed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reward was already claimed")
+    }
+}
+impl std::error::Error for AlreadyClaimed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(last_claimed_at: DateTime<Utc>, streak: i32) -> RewardClaim {
+        RewardClaim {
+            user_id: 1,
+            kind: RewardKind::Daily.as_str().into(),
+            last_claimed_at,
+            streak,
+        }
+    }
+
+    /// The streak a claim would continue at, `None` while on cooldown
+    fn ready_streak(status: ClaimStatus) -> Option<i32> {
+        match status {
+            ClaimStatus::Ready { streak } => Some(streak),
+            ClaimStatus::Cooldown { .. } => None,
+        }
+    }
+
+    #[test]
+    fn first_claim_starts_a_streak() {
+        let status = RewardKind::Daily.status(None, Utc::now());
+        assert_eq!(ready_streak(status), Some(1));
+    }
+
+    #[test]
+    fn claims_wait_out_the_period() {
+        let last = Utc::now();
+        let claim = claim(last, 4);
+        let status = RewardKind::Daily.status(Some(&claim), last + Duration::hours(23));
+        match status {
+            ClaimStatus::Cooldown { available_at } => {
+                assert_eq!(available_at, last + Duration::days(1))
+            }
+            ClaimStatus::Ready { .. } => panic!("claimed during the cooldown"),
+        }
+    }
+
+    #[test]
+    fn streak_holds_until_the_grace_window_ends() {
+        for kind in [RewardKind::Daily, RewardKind::Weekly] {
+            let last = Utc::now();
+            let claim = claim(last, 4);
+            let available_at = last + kind.period();
+            let deadline = available_at + kind.grace();
+
+            let on_time = kind.status(Some(&claim), available_at);
+            assert_eq!(ready_streak(on_time), Some(5));
+            let last_moment = kind.status(Some(&claim), deadline);
+            assert_eq!(ready_streak(last_moment), Some(5));
+            let too_late = kind.status(Some(&claim), deadline + Duration::seconds(1));
+            assert_eq!(ready_streak(too_late), Some(1));
+        }
+    }
+
+    #[test]
+    fn daily_reward_grows_up_to_its_cap() {
+        assert_eq!(RewardKind::Daily.reward(1).currency, 150);
+        assert_eq!(RewardKind::Daily.reward(2).currency, 175);
+        assert_eq!(RewardKind::Daily.reward(11).currency, 400);
+        assert_eq!(RewardKind::Daily.reward(50).currency, 400);
+    }
+
+    #[test]
+    fn milestone_claims_add_packs() {
+        assert_eq!(RewardKind::Daily.reward(6).packs, 0);
+        assert_eq!(RewardKind::Daily.reward(7).packs, 1);
+        assert_eq!(RewardKind::Daily.reward(29).premium_one_packs, 0);
+        assert_eq!(RewardKind::Daily.reward(30).premium_one_packs, 1);
+        assert_eq!(RewardKind::Weekly.reward(3).premium_one_packs, 0);
+        assert_eq!(RewardKind::Weekly.reward(4).premium_one_packs, 1);
+    }
+}
@@ -0,0 +1,307 @@
+use poise::serenity_prelude as serenity;
+use sqlx::types::chrono::{DateTime, Duration, Utc};
+use tokio::sync::{Mutex as TokioMutex, MutexGuard};
+
+use crate::models::{
+    account::Account,
+    ledger::{LedgerAction, LedgerReason},
+    waifu::OwnedWaifu,
+};
+
+/// The most currency one side of a trade can put up
+pub const MAX_TRADE_CURRENCY: i32 = 1_000_000;
+/// The most packs of one kind one side of a trade can put up
+pub const MAX_TRADE_PACKS: i16 = 1_000;
+/// How long a trade stays open without either side changing or confirming it
+pub const TRADE_TIMEOUT_MINUTES: i64 = 15;
+
+/// What one side of a trade puts up
+#[derive(Debug, Clone, Default)]
+pub struct TradeOffer {
+    /// Instance ids of the offered waifus
+    pub waifus: Vec<i64>,
+    pub currency: i32,
+    pub packs: i16,
+    pub premium_one_packs: i16,
+    pub confirmed: bool,
+}
+impl TradeOffer {
+    pub fn is_empty(&self) -> bool {
+        self.waifus.is_empty()
+            && self.currency == 0
+            && self.packs == 0
+            && self.premium_one_packs == 0
+    }
+    /// Whether every amount is within the trade limits, an offer can never take from the other side
+    pub fn is_valid(&self) -> bool {
+        (0..=MAX_TRADE_CURRENCY).contains(&self.currency)
+            && (0..=MAX_TRADE_PACKS).contains(&self.packs)
+            && (0..=MAX_TRADE_PACKS).contains(&self.premium_one_packs)
+    }
+    /// Adds to the offered amounts, `None` if that would go past the trade limits
+    pub fn add(&self, currency: i32, packs: i16, premium_one_packs: i16) -> Option<Self> {
+        let offer = Self {
+            currency: self.currency.checked_add(currency)?,
+            packs: self.packs.checked_add(packs)?,
+            premium_one_packs: self.premium_one_packs.checked_add(premium_one_packs)?,
+            ..self.clone()
+        };
+
+        offer.is_valid().then_some(offer)
+    }
+    /// Takes the waifu and amounts back out of the offer, `None` if the offer doesn't hold them
+    pub fn remove(
+        &self,
+        waifu: Option<i64>,
+        currency: i32,
+        packs: i16,
+        premium_one_packs: i16,
+    ) -> Option<Self> {
+        let mut waifus = self.waifus.clone();
+        if let Some(instance_id) = waifu {
+            let position = waifus.iter().position(|w| *w == instance_id)?;
+            waifus.remove(position);
+        }
+        let offer = Self {
+            waifus,
+            currency: self.currency.checked_sub(currency)?,
+            packs: self.packs.checked_sub(packs)?,
+            premium_one_packs: self.premium_one_packs.checked_sub(premium_one_packs)?,
+            ..self.clone()
+        };
+
+        offer.is_valid().then_some(offer)
+    }
+    /// Whether the account currently holds everything in this offer
+    pub fn is_covered_by(&self, account: &Account, owned_waifus: &[OwnedWaifu]) -> bool {
+        if !self.is_valid() {
+            return false;
+        }
+        let owns_waifus = self
+            .waifus
+            .iter()
+            .all(|instance_id| owned_waifus.iter().any(|w| w.instance_id == *instance_id));
+
+        owns_waifus
+            && account.currency >= self.currency
+            && account.packs >= self.packs
+            && account.premium_one_packs >= self.premium_one_packs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub initiator: serenity::UserId,
+    pub partner: serenity::UserId,
+    pub initiator_offer: TradeOffer,
+    pub partner_offer: TradeOffer,
+    /// Bumped every time an offer changes, so a confirmation only applies to what was shown
+    pub revision: u32,
+    /// Pushed back whenever either side acts, see [`TRADE_TIMEOUT_MINUTES`]
+    pub expires_at: DateTime<Utc>,
+}
+impl Trade {
+    pub fn new(initiator: serenity::UserId, partner: serenity::UserId) -> Self {
+        Self {
+            initiator,
+            partner,
+            initiator_offer: TradeOffer::default(),
+            partner_offer: TradeOffer::default(),
+            revision: 0,
+            expires_at: Self::next_expiry(),
+        }
+    }
+    fn next_expiry() -> DateTime<Utc> {
+        Utc::now() + Duration::minutes(TRADE_TIMEOUT_MINUTES)
+    }
+    pub fn involves(&self, user_id: serenity::UserId) -> bool {
+        self.initiator == user_id || self.partner == user_id
+    }
+    pub fn other(&self, user_id: serenity::UserId) -> serenity::UserId {
+        if self.initiator == user_id {
+            self.partner
+        } else {
+            self.initiator
+        }
+    }
+    pub fn offer(&self, user_id: serenity::UserId) -> &TradeOffer {
+        if self.initiator == user_id {
+            &self.initiator_offer
+        } else {
+            &self.partner_offer
+        }
+    }
+    fn offer_mut(&mut self, user_id: serenity::UserId) -> &mut TradeOffer {
+        if self.initiator == user_id {
+            &mut self.initiator_offer
+        } else {
+            &mut self.partner_offer
+        }
+    }
+    pub fn is_confirmed(&self) -> bool {
+        self.initiator_offer.confirmed && self.partner_offer.confirmed
+    }
+    /// Every transfer of the trade as one ledger action, so the swap either happens completely or not at all.
+    /// `None` if either offer is outside the trade limits.
+    pub fn to_ledger_action(&self, command: &str) -> Option<LedgerAction> {
+        if !self.initiator_offer.is_valid() || !self.partner_offer.is_valid() {
+            return None;
+        }
+        let mut action = LedgerAction::new(LedgerReason::Trade, command);
+        let sides = [
+            (self.initiator, self.partner, &self.initiator_offer),
+            (self.partner, self.initiator, &self.partner_offer),
+        ];
+        for (from, to, offer) in sides {
+            action = action
+                .currencies(from, -offer.currency, 0)
+                .currencies(to, offer.currency, 0)
+                .packs(from, -offer.packs, -offer.premium_one_packs)
+                .packs(to, offer.packs, offer.premium_one_packs);
+            for instance_id in offer.waifus.iter() {
+                action = action.transfer_waifu(from, to, *instance_id);
+            }
+        }
+
+        Some(action)
+    }
+}
+
+pub enum TradeConfirmation {
+    /// The other side still has to confirm
+    Pending,
+    /// Both sides confirmed, the trade was taken out of the book and should be executed
+    Ready(Trade),
+    /// The trade changed or was cancelled while the confirmation was open
+    Outdated,
+}
+
+/// Trades that are being negotiated. Each user can only be part of one trade at a time.
+/// Trades nobody touched for [`TRADE_TIMEOUT_MINUTES`] are dropped, so an abandoned trade doesn't lock anyone out.
+pub struct TradeBook {
+    trades: TokioMutex<Vec<Trade>>,
+}
+impl TradeBook {
+    pub fn new() -> Self {
+        Self {
+            trades: TokioMutex::new(vec![]),
+        }
+    }
+    /// Locks the book with the expired trades already taken out
+    async fn lock(&self) -> MutexGuard<'_, Vec<Trade>> {
+        let mut guard = self.trades.lock().await;
+        let now = Utc::now();
+        guard.retain(|t| t.expires_at > now);
+
+        guard
+    }
+    /// Opens a new trade, returns `false` if either user is already trading
+    pub async fn open(&self, initiator: serenity::UserId, partner: serenity::UserId) -> bool {
+        let mut guard = self.lock().await;
+        if guard
+            .iter()
+            .any(|t| t.involves(initiator) || t.involves(partner))
+        {
+            return false;
+        }
+        guard.push(Trade::new(initiator, partner));
+
+        true
+    }
+    pub async fn get(&self, user_id: serenity::UserId) -> Option<Trade> {
+        let guard = self.lock().await;
+        guard.iter().find(|t| t.involves(user_id)).cloned()
+    }
+    /// Changes the user's offer. Any earlier confirmations are withdrawn.
+    pub async fn update_offer<F: FnOnce(&mut TradeOffer)>(
+        &self,
+        user_id: serenity::UserId,
+        update: F,
+    ) -> Option<Trade> {
+        let mut guard = self.lock().await;
+        let trade = guard.iter_mut().find(|t| t.involves(user_id))?;
+        update(trade.offer_mut(user_id));
+        trade.initiator_offer.confirmed = false;
+        trade.partner_offer.confirmed = false;
+        trade.revision += 1;
+        trade.expires_at = Trade::next_expiry();
+
+        Some(trade.clone())
+    }
+    pub async fn confirm(&self, user_id: serenity::UserId, revision: u32) -> TradeConfirmation {
+        let mut guard = self.lock().await;
+        let position = guard
+            .iter()
+            .position(|t| t.involves(user_id) && t.revision == revision);
+        let Some(position) = position else {
+            return TradeConfirmation::Outdated;
+        };
+
+        let trade = &mut guard[position];
+        trade.offer_mut(user_id).confirmed = true;
+        trade.expires_at = Trade::next_expiry();
+        if trade.is_confirmed() {
+            TradeConfirmation::Ready(guard.remove(position))
+        } else {
+            TradeConfirmation::Pending
+        }
+    }
+    pub async fn remove(&self, user_id: serenity::UserId) -> Option<Trade> {
+        let mut guard = self.lock().await;
+        let position = guard.iter().position(|t| t.involves(user_id))?;
+
+        Some(guard.remove(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: serenity::UserId = serenity::UserId(1);
+    const BOB: serenity::UserId = serenity::UserId(2);
+
+    #[test]
+    fn removing_takes_back_only_what_was_offered() {
+        let offer = TradeOffer {
+            waifus: vec![7, 9],
+            currency: 100,
+            packs: 2,
+            ..Default::default()
+        };
+
+        let removed = offer.remove(Some(7), 40, 2, 0).unwrap();
+        assert_eq!(removed.waifus, vec![9]);
+        assert_eq!(removed.currency, 60);
+        assert_eq!(removed.packs, 0);
+
+        assert!(offer.remove(Some(8), 0, 0, 0).is_none());
+        assert!(offer.remove(None, 101, 0, 0).is_none());
+        assert!(offer.remove(None, 0, 0, 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_trades_free_both_players() {
+        let book = TradeBook::new();
+        assert!(book.open(ALICE, BOB).await);
+        assert!(!book.open(BOB, ALICE).await);
+
+        book.trades.lock().await[0].expires_at = Utc::now() - Duration::seconds(1);
+        assert!(book.get(ALICE).await.is_none());
+        assert!(book.open(BOB, ALICE).await);
+    }
+
+    #[tokio::test]
+    async fn changing_an_offer_pushes_the_expiry_back() {
+        let book = TradeBook::new();
+        book.open(ALICE, BOB).await;
+        let soon = Utc::now() + Duration::seconds(1);
+        book.trades.lock().await[0].expires_at = soon;
+
+        let trade = book
+            .update_offer(ALICE, |offer| offer.currency = 10)
+            .await
+            .unwrap();
+        assert!(trade.expires_at > soon);
+    }
+}
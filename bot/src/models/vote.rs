@@ -0,0 +1,70 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vote {
+    Like,
+    Trash,
+}
+impl Vote {
+    /// The value stored in the `vote` column of `waifu_votes`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Like => "like",
+            Self::Trash => "trash",
+        }
+    }
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "like" => Some(Self::Like),
+            "trash" => Some(Self::Trash),
+            _ => None,
+        }
+    }
+    /// Past tense, as in "you already liked"
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Self::Like => "liked",
+            Self::Trash => "trashed",
+        }
+    }
+}
+
+/// Every vote cast on a waifu, or how a vote changes them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoteTotals {
+    pub likes: i64,
+    pub trash: i64,
+}
+impl VoteTotals {
+    /// How casting `vote` changes the totals, taking back the user's `previous` vote
+    pub fn change(vote: Vote, previous: Option<Vote>) -> Self {
+        let mut change = Self::default();
+        for (vote, delta) in [(Some(vote), 1), (previous, -1)] {
+            match vote {
+                Some(Vote::Like) => change.likes += delta,
+                Some(Vote::Trash) => change.trash += delta,
+                None => {}
+            }
+        }
+
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changing_a_vote_moves_it_between_totals() {
+        assert_eq!(
+            VoteTotals::change(Vote::Like, None),
+            VoteTotals { likes: 1, trash: 0 }
+        );
+        assert_eq!(
+            VoteTotals::change(Vote::Trash, Some(Vote::Like)),
+            VoteTotals {
+                likes: -1,
+                trash: 1
+            }
+        );
+    }
+}
@@ -1,8 +1,124 @@
 use poise::serenity_prelude as serenity;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    thread_rng, Rng,
+};
 use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
 
 use crate::utils::ToEmbed;
 
+/// Ordered from most to least common
+#[derive(
+    Serialize,
+    Deserialize,
+    poise::ChoiceParameter,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Rarity {
+    #[default]
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+impl Rarity {
+    pub const ALL: [Rarity; 5] = [
+        Self::Common,
+        Self::Uncommon,
+        Self::Rare,
+        Self::Epic,
+        Self::Legendary,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Common => "Common",
+            Self::Uncommon => "Uncommon",
+            Self::Rare => "Rare",
+            Self::Epic => "Epic",
+            Self::Legendary => "Legendary",
+        }
+    }
+    /// The value stored in the `rarity` field of waifu documents
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Common => "common",
+            Self::Uncommon => "uncommon",
+            Self::Rare => "rare",
+            Self::Epic => "epic",
+            Self::Legendary => "legendary",
+        }
+    }
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Common => "⚪",
+            Self::Uncommon => "🟢",
+            Self::Rare => "🔵",
+            Self::Epic => "🟣",
+            Self::Legendary => "🟡",
+        }
+    }
+}
+
+/// Relative weights of each rarity when a pack rolls a waifu
+#[derive(Clone, Deserialize)]
+pub struct DropTable {
+    pub common: u32,
+    pub uncommon: u32,
+    pub rare: u32,
+    pub epic: u32,
+    pub legendary: u32,
+}
+impl DropTable {
+    pub fn weight(&self, rarity: Rarity) -> u32 {
+        match rarity {
+            Rarity::Common => self.common,
+            Rarity::Uncommon => self.uncommon,
+            Rarity::Rare => self.rare,
+            Rarity::Epic => self.epic,
+            Rarity::Legendary => self.legendary,
+        }
+    }
+    /// Chance of a single waifu being of this rarity, in percent
+    pub fn chance(&self, rarity: Rarity) -> f64 {
+        let total: u32 = Rarity::ALL.iter().map(|r| self.weight(*r)).sum();
+        if total == 0 {
+            0.0
+        } else {
+            self.weight(rarity) as f64 / total as f64 * 100.0
+        }
+    }
+    /// Rolls a rarity for each of the `count` waifus and returns how many of each rarity to draw
+    pub fn roll(&self, count: u32) -> Vec<(Rarity, u32)> {
+        let weights = Rarity::ALL.map(|r| self.weight(r));
+        let mut rolled: Vec<(Rarity, u32)> = Rarity::ALL.iter().map(|r| (*r, 0)).collect();
+        // an all-zero table can't be sampled, so everything ends up common
+        match WeightedIndex::new(weights) {
+            Ok(distribution) => {
+                let mut rng = thread_rng();
+                for _ in 0..count {
+                    rolled[distribution.sample(&mut rng)].1 += 1;
+                }
+            }
+            Err(_) => rolled[0].1 = count,
+        }
+
+        rolled.retain(|(_, amount)| *amount > 0);
+        rolled
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Waifu {
     pub _id: u16,
@@ -11,6 +127,9 @@ pub struct Waifu {
     pub gdrive_id: String,
     pub likes: u32,
     pub trash: u32,
+    /// Waifus added before rarities existed have no rarity and count as common
+    #[serde(default)]
+    pub rarity: Rarity,
 }
 impl Waifu {
     pub fn download_url(&self) -> String {
@@ -19,14 +138,6 @@ impl Waifu {
             self.gdrive_id
         )
     }
-    pub fn price(&self) -> u32 {
-        let calculated = (self.likes - self.trash) + 175;
-        if calculated <= 0 {
-            0
-        } else {
-            calculated
-        }
-    }
 }
 impl ToEmbed for Waifu {
     fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
@@ -34,5 +145,190 @@ impl ToEmbed for Waifu {
             .colour(serenity::Colour::FABLED_PINK)
             .title(&self.name)
             .description(&self.description)
+            .field(
+                "Rarity",
+                format!("{} {}", self.rarity.icon(), self.rarity.name()),
+                true,
+            )
+    }
+}
+
+/// How many waifus `/search` shows
+pub const SEARCH_LIMIT: usize = 25;
+
+/// How well a waifu's name matches a search, lower is better, `None` if it doesn't match.
+/// Exact names come first, then names starting with the query, then names with a word starting with it.
+pub fn search_rank(name: &str, query: &str) -> Option<u8> {
+    search_rank_lowercase(&name.to_lowercase(), &query.trim().to_lowercase())
+}
+
+/// [`search_rank`] for a name and query that are already lowercased, and trimmed for the query
+pub fn search_rank_lowercase(name: &str, query: &str) -> Option<u8> {
+    if name == query {
+        Some(0)
+    } else if name.starts_with(query) {
+        Some(1)
+    } else if name.split_whitespace().any(|word| word.starts_with(query)) {
+        Some(2)
+    } else if name.contains(query) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Keeps the `limit` best matches of the query, ordered by [`search_rank`] and then by shortest name
+pub fn rank_search_results(waifus: Vec<Waifu>, query: &str, limit: usize) -> Vec<Waifu> {
+    rank_by_name(waifus, |w| w.name.as_str(), query, limit)
+}
+
+/// [`rank_search_results`] for anything with a name, so candidates can be ranked before fetching whole waifus
+pub fn rank_by_name<T>(
+    mut items: Vec<T>,
+    name: impl Fn(&T) -> &str,
+    query: &str,
+    limit: usize,
+) -> Vec<T> {
+    items.retain(|i| search_rank(name(i), query).is_some());
+    items.sort_by_cached_key(|i| {
+        let name = name(i);
+        (
+            search_rank(name, query),
+            name.chars().count(),
+            name.to_lowercase(),
+        )
+    });
+    items.truncate(limit);
+
+    items
+}
+
+/// Chance in percent that a waifu slot is drawn from the player's wishlisted waifus of the rolled rarity
+pub const WISHLIST_BOOST_PERCENT: u32 = 10;
+
+/// Rolls how many of the `count` waifus of one rarity come from the wishlist
+pub fn roll_wishlisted(count: u32) -> u32 {
+    let mut rng = thread_rng();
+    (0..count)
+        .filter(|_| rng.gen_range(0..100) < WISHLIST_BOOST_PERCENT)
+        .count() as u32
+}
+
+/// One copy of a waifu owned by a user
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct OwnedWaifu {
+    pub instance_id: i64,
+    pub waifu_id: i16,
+    pub owner_id: i64,
+    pub acquired_at: DateTime<Utc>,
+    pub source: String,
+    pub experience: i32,
+}
+impl OwnedWaifu {
+    pub fn level(&self) -> i32 {
+        level_for(self.experience)
+    }
+}
+
+/// Levels are the same for accounts and waifus, one level every 250 experience
+pub fn level_for(experience: i32) -> i32 {
+    experience / 250
+}
+
+/// An owned waifu together with its catalog entry, for displaying inventories
+pub struct InventoryWaifu {
+    pub owned: OwnedWaifu,
+    pub waifu: Waifu,
+}
+impl InventoryWaifu {
+    /// Pairs every owned waifu with its catalog entry, skipping ones missing from the catalog
+    pub fn join(owned: Vec<OwnedWaifu>, waifus: &[Waifu]) -> Vec<Self> {
+        owned
+            .into_iter()
+            .filter_map(|owned| {
+                let waifu = waifus.iter().find(|w| w._id as i16 == owned.waifu_id)?;
+                Some(Self {
+                    owned,
+                    waifu: waifu.clone(),
+                })
+            })
+            .collect()
+    }
+}
+impl ToEmbed for InventoryWaifu {
+    fn to_embed<'a>(&self, ce: &'a mut serenity::CreateEmbed) -> &'a mut serenity::CreateEmbed {
+        self.waifu
+            .to_embed(ce)
+            .field(
+                "Acquired",
+                format!("<t:{}:R>", self.owned.acquired_at.timestamp()),
+                true,
+            )
+            .field("Source", &self.owned.source, true)
+            .field(
+                "Level",
+                format!("{} ({} XP)", self.owned.level(), self.owned.experience),
+                true,
+            )
+            .footer(|cf| cf.text(format!("#{}", self.owned.instance_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(common: u32, uncommon: u32, rare: u32, epic: u32, legendary: u32) -> DropTable {
+        DropTable {
+            common,
+            uncommon,
+            rare,
+            epic,
+            legendary,
+        }
+    }
+
+    fn rolled(rolls: &[(Rarity, u32)], rarity: Rarity) -> u32 {
+        rolls
+            .iter()
+            .find(|(r, _)| *r == rarity)
+            .map_or(0, |(_, amount)| *amount)
+    }
+
+    #[test]
+    fn roll_draws_every_waifu_once() {
+        let rolls = table(60, 25, 10, 4, 1).roll(10);
+        assert_eq!(rolls.iter().map(|(_, amount)| amount).sum::<u32>(), 10);
+        assert!(rolls.iter().all(|(_, amount)| *amount > 0));
+    }
+
+    #[test]
+    fn roll_never_draws_weightless_rarities() {
+        let rolls = table(0, 1, 0, 1, 0).roll(1000);
+        for rarity in [Rarity::Common, Rarity::Rare, Rarity::Legendary] {
+            assert_eq!(rolled(&rolls, rarity), 0);
+        }
+    }
+
+    #[test]
+    fn roll_follows_the_weights() {
+        let rolls = table(90, 0, 10, 0, 0).roll(10_000);
+        // 9000 expected, a standard deviation is 30, so this only fails by a wide fluke
+        let common = rolled(&rolls, Rarity::Common);
+        assert!((8700..=9300).contains(&common), "{common} commons");
+        assert_eq!(common + rolled(&rolls, Rarity::Rare), 10_000);
+    }
+
+    #[test]
+    fn roll_of_an_empty_table_is_all_common() {
+        assert_eq!(table(0, 0, 0, 0, 0).roll(5), vec![(Rarity::Common, 5)]);
+    }
+
+    #[test]
+    fn chance_is_the_share_of_the_total_weight() {
+        let drop_table = table(32, 16, 8, 4, 4);
+        assert_eq!(drop_table.chance(Rarity::Common), 50.0);
+        assert_eq!(drop_table.chance(Rarity::Legendary), 6.25);
+        assert_eq!(table(0, 0, 0, 0, 0).chance(Rarity::Common), 0.0);
     }
 }